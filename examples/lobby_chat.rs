@@ -0,0 +1,223 @@
+//! A terminal lobby that replicates the player list and chats over a user channel.
+//!
+//! The roster is regular replication, the chat bypasses replicon on a reliable channel
+//! registered in `MatchboxConfig::user_channels`. Type a line and press enter to send it.
+
+use bevy::log::LogPlugin;
+use bevy::prelude::*;
+use bevy_replicon::prelude::*;
+use bevy_replicon_matchbox::prelude::*;
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use std::io::BufRead;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+
+/// Index of the chat channel in [`MatchboxConfig::user_channels`].
+const CHAT_CHANNEL: usize = 0;
+
+fn main() {
+    App::new()
+        .init_resource::<Cli>()
+        .add_plugins((
+            MinimalPlugins.set(bevy::app::ScheduleRunnerPlugin::run_loop(
+                Duration::from_secs_f64(1.0 / 60.0),
+            )),
+            LogPlugin::default(),
+            RepliconPlugins,
+            RepliconMatchboxPlugins,
+        ))
+        .replicate::<Player>()
+        .add_observer(spawn_players)
+        .add_observer(despawn_players)
+        .add_observer(announce_players)
+        .add_systems(Startup, (read_cli, read_stdin))
+        .add_systems(Update, (send_chat, receive_chat))
+        .run();
+}
+
+fn config() -> MatchboxConfig {
+    MatchboxConfig {
+        user_channels: vec![Channel::Ordered],
+        ..Default::default()
+    }
+}
+
+fn read_cli(mut commands: Commands, cli: Res<Cli>) -> Result<()> {
+    match *cli {
+        Cli::Server { port } => {
+            info!("hosting lobby at port {port}");
+            let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+            let mut signaling = EmbeddedSignalingServer::new(addr, None);
+            signaling.start()?;
+            let room_url = signaling
+                .room_url("lobby")
+                .expect("server should be running after start");
+            commands.queue(StartHost {
+                room_url,
+                config: config(),
+            });
+            commands.insert_resource(signaling);
+            commands.spawn(Player {
+                name: "Host".into(),
+            });
+        }
+        Cli::Client { port } => {
+            info!("joining lobby at port {port}");
+            let room_url = format!("ws://localhost:{port}/lobby");
+            commands.queue(StartClient {
+                room_url,
+                config: config(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads lines from the terminal on a separate thread, since reading blocks.
+fn read_stdin(mut commands: Commands) {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    commands.insert_resource(TerminalInput(Mutex::new(receiver)));
+}
+
+/// Adds a player to the replicated roster whenever a client connects.
+fn spawn_players(
+    trigger: Trigger<OnAdd, ConnectedClient>,
+    mut commands: Commands,
+    mut count: Local<usize>,
+) {
+    *count += 1;
+    commands.spawn((
+        Player {
+            name: format!("Player {}", *count),
+        },
+        PlayerClient(trigger.target()),
+    ));
+}
+
+fn despawn_players(
+    trigger: Trigger<OnRemove, ConnectedClient>,
+    mut commands: Commands,
+    players: Query<(Entity, &PlayerClient)>,
+) {
+    if let Some((entity, _)) = players
+        .iter()
+        .find(|(_, client)| client.0 == trigger.target())
+    {
+        commands.entity(entity).despawn();
+    }
+}
+
+/// Prints players as they join, on the host and through replication on the clients.
+fn announce_players(trigger: Trigger<OnAdd, Player>, players: Query<&Player>) {
+    if let Ok(player) = players.get(trigger.target()) {
+        println!("* {} joined the lobby", player.name);
+    }
+}
+
+fn send_chat(
+    input: Option<Res<TerminalInput>>,
+    host: Option<ResMut<MatchboxHost>>,
+    client: Option<ResMut<MatchboxClient>>,
+) {
+    let Some(input) = input else {
+        return;
+    };
+    let lines: Vec<_> = input.0.lock().unwrap().try_iter().collect();
+    if let Some(mut host) = host {
+        for line in lines {
+            let text = format!("Host: {line}");
+            println!("{text}");
+            host.broadcast(CHAT_CHANNEL, text);
+        }
+    } else if let Some(mut client) = client {
+        for line in lines {
+            client.send_to_host(CHAT_CHANNEL, line);
+        }
+    }
+}
+
+/// Prints chat messages, on the host prefixed with the sender's name and forwarded to
+/// everyone.
+fn receive_chat(
+    mut messages: EventReader<UserMessage>,
+    host: Option<ResMut<MatchboxHost>>,
+    clients: Query<(Entity, &MatchboxClientConnection)>,
+    players: Query<(&Player, &PlayerClient)>,
+) {
+    let Some(mut host) = host else {
+        for message in messages.read() {
+            println!("{}", String::from_utf8_lossy(&message.payload));
+        }
+        return;
+    };
+    for message in messages.read() {
+        let Some((client_entity, _)) = clients
+            .iter()
+            .find(|(_, connection)| connection.peer_id == message.sender)
+        else {
+            continue;
+        };
+        let Some((player, _)) = players.iter().find(|(_, client)| client.0 == client_entity) else {
+            continue;
+        };
+        let text = format!(
+            "{}: {}",
+            player.name,
+            String::from_utf8_lossy(&message.payload)
+        );
+        println!("{text}");
+        host.broadcast(CHAT_CHANNEL, text);
+    }
+}
+
+const PORT: u16 = 5000;
+
+/// A terminal lobby with chat.
+#[derive(Parser, PartialEq, Resource)]
+enum Cli {
+    /// Host the lobby and take part in the chat.
+    Server {
+        #[arg(short, long, default_value_t = PORT)]
+        port: u16,
+    },
+    /// Join the lobby of a host.
+    Client {
+        #[arg(short, long, default_value_t = PORT)]
+        port: u16,
+    },
+}
+
+impl Default for Cli {
+    fn default() -> Self {
+        Self::parse()
+    }
+}
+
+/// Lobby member, replicated to everyone.
+#[derive(Component, Deserialize, Serialize)]
+#[require(Replicated)]
+struct Player {
+    name: String,
+}
+
+/// Client entity of a player, only present on the host.
+#[derive(Component)]
+struct PlayerClient(Entity);
+
+/// Lines typed into the terminal.
+#[derive(Resource)]
+struct TerminalInput(Mutex<Receiver<String>>);
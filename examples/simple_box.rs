@@ -7,13 +7,12 @@ use bevy::{
     prelude::*,
     winit::{UpdateMode::Continuous, WinitSettings},
 };
-use bevy_matchbox::matchbox_signaling::SignalingServer;
 use bevy_replicon::prelude::*;
-use bevy_replicon_matchbox::{MatchboxClient, MatchboxHost, RepliconMatchboxPlugins};
+use bevy_replicon_matchbox::prelude::*;
 use clap::Parser;
 use serde::{Deserialize, Serialize};
 use std::hash::{DefaultHasher, Hash, Hasher};
-use std::net::{Ipv4Addr, SocketAddrV4};
+use std::net::{Ipv4Addr, SocketAddr};
 
 #[derive(Clone, Copy, Debug, Default, Deserialize, Event, Serialize)]
 struct ExampleEvent {
@@ -64,11 +63,11 @@ fn read_cli(mut commands: Commands, cli: Res<Cli>, channels: Res<RepliconChannel
         }
         Cli::Server { port } => {
             info!("starting server at port {port}");
-            start_signaling_server(&mut commands, port);
-            let room_url = format!("ws://localhost:{port}/simple-box");
-
-            let server = MatchboxHost::new(room_url, &channels)?;
+            let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+            let (server, signaling) =
+                MatchboxHost::with_embedded_signaling(addr, "simple-box", &channels)?;
             commands.insert_resource(server);
+            commands.insert_resource(signaling);
             commands.spawn((
                 Text::new("Server"),
                 TextFont {
@@ -104,27 +103,6 @@ fn read_cli(mut commands: Commands, cli: Res<Cli>, channels: Res<RepliconChannel
     Ok(())
 }
 
-fn start_signaling_server(commands: &mut Commands, port: u16) {
-    info!("Starting signaling server on port {port}");
-    let addr = SocketAddrV4::new(Ipv4Addr::LOCALHOST, port);
-    let signaling_server = bevy_matchbox::MatchboxServer::from(
-        SignalingServer::client_server_builder(addr)
-            .on_connection_request(|connection| {
-                info!("Connecting: {connection:?}");
-                Ok(true) // Allow all connections
-            })
-            .on_id_assignment(|(socket, id)| info!("{socket} received {id}"))
-            .on_host_connected(|id| info!("Host joined: {id}"))
-            .on_host_disconnected(|id| info!("Host left: {id}"))
-            .on_client_connected(|id| info!("Client joined: {id}"))
-            .on_client_disconnected(|id| info!("Client left: {id}"))
-            .cors()
-            .trace()
-            .build(),
-    );
-    commands.insert_resource(signaling_server);
-}
-
 fn spawn_camera(mut commands: Commands) {
     commands.spawn(Camera2d);
 }
@@ -7,7 +7,7 @@ use bevy::{
 };
 use bevy_matchbox::matchbox_signaling::SignalingServer;
 use bevy_replicon::prelude::*;
-use bevy_replicon_matchbox::{MatchboxClient, MatchboxHost, RepliconMatchboxPlugins};
+use bevy_replicon_matchbox::prelude::*;
 use clap::{Parser, ValueEnum};
 use serde::{Deserialize, Serialize};
 use std::fmt::{self, Formatter};
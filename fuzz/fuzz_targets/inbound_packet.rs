@@ -0,0 +1,18 @@
+#![no_main]
+
+use bevy_replicon_matchbox::fuzzing;
+use libfuzzer_sys::fuzz_target;
+
+// first byte selects the channel the packet arrived on
+fuzz_target!(|data: &[u8]| {
+    let Some((&channel, packet)) = data.split_first() else {
+        return;
+    };
+    match channel % 5 {
+        0 => fuzzing::system_message(packet),
+        1 => fuzzing::replicon_message(packet),
+        2 => fuzzing::relay_envelope(packet),
+        3 => fuzzing::voice_packet(packet),
+        _ => fuzzing::chat_message(packet),
+    }
+});
@@ -0,0 +1,17 @@
+#![no_main]
+
+use bevy_replicon_matchbox::fuzzing;
+use libfuzzer_sys::fuzz_target;
+
+// packets are prefixed with their length as a single byte
+fuzz_target!(|data: &[u8]| {
+    let mut packets = Vec::new();
+    let mut rest = data;
+    while let Some((&len, tail)) = rest.split_first() {
+        let len = (len as usize).min(tail.len());
+        let (packet, tail) = tail.split_at(len);
+        packets.push(packet);
+        rest = tail;
+    }
+    fuzzing::transfer_packets(packets);
+});
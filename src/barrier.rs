@@ -0,0 +1,165 @@
+use bevy::prelude::*;
+#[cfg(feature = "client")]
+use bytes::Bytes;
+use std::collections::HashMap;
+#[cfg(feature = "client")]
+use std::collections::VecDeque;
+#[cfg(feature = "client")]
+use std::time::Duration;
+
+/// Replicon messages the host sent a client during the current frame, by server channel.
+///
+/// Sent to the client as a tick barrier once the frame's messages are handed to the
+/// transport, see [`MatchboxConfig::tick_barrier`](crate::MatchboxConfig::tick_barrier).
+#[cfg(feature = "server")]
+#[derive(Component, Default)]
+pub(crate) struct TickCounter(HashMap<usize, u32>);
+
+#[cfg(feature = "server")]
+impl TickCounter {
+    pub(crate) fn count(&mut self, channel_id: usize) {
+        *self.0.entry(channel_id).or_default() += 1;
+    }
+
+    /// Takes the counts of the frame, `None` if nothing was sent.
+    pub(crate) fn take(&mut self) -> Option<Vec<(usize, u32)>> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let mut counts: Vec<_> = self.0.drain().collect();
+        counts.sort_unstable();
+        Some(counts)
+    }
+}
+
+/// Holds the client's received messages on reliable channels until all messages of their
+/// tick arrived.
+///
+/// Each message is numbered per channel in arrival order. Barriers from the host add up to
+/// the number of messages each channel must have received, so messages up to the last
+/// complete barrier are released together.
+#[cfg(feature = "client")]
+#[derive(Default)]
+pub(crate) struct TickBarrier {
+    /// Messages received per channel.
+    received: HashMap<usize, u64>,
+    /// Messages expected per channel once the latest barrier is complete.
+    expected: HashMap<usize, u64>,
+    /// Barriers that aren't complete yet, as expected totals.
+    barriers: VecDeque<HashMap<usize, u64>>,
+    /// Messages released up to per channel.
+    released: HashMap<usize, u64>,
+    /// Channel, number and arrival time of held messages, in arrival order.
+    pending: VecDeque<(usize, u64, Bytes, Duration)>,
+}
+
+#[cfg(feature = "client")]
+impl TickBarrier {
+    pub(crate) fn push(&mut self, channel_id: usize, message: Bytes, now: Duration) {
+        let received = self.received.entry(channel_id).or_default();
+        *received += 1;
+        self.pending
+            .push_back((channel_id, *received, message, now));
+    }
+
+    /// Counts a message that was received but discarded, so its tick isn't held for it.
+    pub(crate) fn skip(&mut self, channel_id: usize) {
+        *self.received.entry(channel_id).or_default() += 1;
+    }
+
+    /// Adds a barrier with the messages the host sent per channel since the previous one.
+    pub(crate) fn add_barrier(&mut self, counts: &[(usize, u32)]) {
+        for &(channel_id, count) in counts {
+            *self.expected.entry(channel_id).or_default() += u64::from(count);
+        }
+        self.barriers.push_back(self.expected.clone());
+    }
+
+    /// Returns the messages of complete ticks in arrival order.
+    ///
+    /// Messages held longer than `timeout` are released anyway, e.g. when a message of their
+    /// tick was lost to a send failure, and their ticks are considered complete.
+    pub(crate) fn release(&mut self, now: Duration, timeout: Duration) -> Vec<(usize, Bytes)> {
+        while let Some(barrier) = self.barriers.front() {
+            let complete = barrier.iter().all(|(channel_id, &expected)| {
+                self.received.get(channel_id).copied().unwrap_or_default() >= expected
+            });
+            if !complete {
+                break;
+            }
+            let barrier = self.barriers.pop_front().unwrap();
+            self.released.extend(barrier);
+        }
+
+        let timed_out = self
+            .pending
+            .front()
+            .is_some_and(|&(.., arrived_at)| now.saturating_sub(arrived_at) >= timeout);
+        if timed_out {
+            debug!(
+                "tick barrier timed out, releasing {} messages",
+                self.pending.len()
+            );
+            // count the missing messages as received to not wait for them again
+            for (&channel_id, &expected) in &self.expected {
+                let received = self.received.entry(channel_id).or_default();
+                *received = (*received).max(expected);
+            }
+            self.barriers.clear();
+            self.released.clone_from(&self.received);
+            return self
+                .pending
+                .drain(..)
+                .map(|(channel_id, _, message, _)| (channel_id, message))
+                .collect();
+        }
+
+        let mut released = Vec::new();
+        let mut held = VecDeque::new();
+        for (channel_id, number, message, arrived_at) in self.pending.drain(..) {
+            if number <= self.released.get(&channel_id).copied().unwrap_or_default() {
+                released.push((channel_id, message));
+            } else {
+                held.push_back((channel_id, number, message, arrived_at));
+            }
+        }
+        self.pending = held;
+        released
+    }
+}
+
+#[cfg(feature = "client")]
+#[test]
+fn test_tick_barrier() {
+    let timeout = Duration::from_secs(1);
+    let at = Duration::from_millis;
+    let message = Bytes::from_static(b"message");
+    let mut barrier = TickBarrier::default();
+
+    // a trigger overtakes the replication of its tick
+    barrier.push(2, message.clone(), at(0));
+    barrier.add_barrier(&[(0, 1), (2, 1)]);
+    assert!(barrier.release(at(0), timeout).is_empty());
+
+    barrier.push(2, message.clone(), at(10));
+    barrier.push(0, message.clone(), at(10));
+    let released: Vec<_> = barrier
+        .release(at(10), timeout)
+        .into_iter()
+        .map(|(channel_id, _)| channel_id)
+        .collect();
+    assert_eq!(released, [2, 0], "only the complete tick is released");
+
+    barrier.add_barrier(&[(2, 1)]);
+    assert_eq!(barrier.release(at(20), timeout).len(), 1);
+
+    // the next tick misses a message
+    barrier.add_barrier(&[(0, 1)]);
+    barrier.push(2, message, at(30));
+    assert!(barrier.release(at(30), timeout).is_empty());
+    assert_eq!(
+        barrier.release(at(1030), timeout).len(),
+        1,
+        "released after the timeout"
+    );
+}
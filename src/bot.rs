@@ -0,0 +1,180 @@
+use crate::client::MatchboxClient;
+use crate::relay::RelayedMessage;
+use crate::shared::{MatchboxConfig, RepliconMatchboxPlugins};
+use bevy::prelude::*;
+use bevy_replicon::prelude::*;
+
+/// Synthetic traffic a bot produces on top of the game's own replication.
+#[derive(Clone, Debug, Default)]
+pub struct BotTraffic {
+    /// Voice frames sent per second, forwarded by the host to every other client.
+    pub voice_frames_per_second: f32,
+    /// Payload size of each voice frame.
+    pub voice_frame_size: usize,
+    /// Sends every relayed message back to the client it came from.
+    pub echo_relayed: bool,
+    /// Size of a transfer sent to the host once connected, zero disables it.
+    pub transfer_size: usize,
+}
+
+/// Connects a headless client to the room and produces [`BotTraffic`].
+#[derive(Clone)]
+pub struct BotPlugin {
+    pub room_url: String,
+    pub config: MatchboxConfig,
+    pub traffic: BotTraffic,
+}
+
+impl Plugin for BotPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(BotSettings(self.clone()))
+            .init_resource::<BotState>()
+            .add_systems(Startup, connect_bot)
+            .add_systems(
+                Update,
+                (send_bot_voice, echo_relayed, send_bot_transfer)
+                    .run_if(resource_exists::<MatchboxClient>),
+            );
+    }
+}
+
+#[derive(Resource)]
+struct BotSettings(BotPlugin);
+
+#[derive(Resource, Default)]
+struct BotState {
+    voice_budget: f32,
+    voice_timestamp: u32,
+    transfer_sent: bool,
+}
+
+fn connect_bot(
+    mut commands: Commands,
+    settings: Res<BotSettings>,
+    channels: Res<RepliconChannels>,
+) -> Result<()> {
+    let client =
+        MatchboxClient::with_config(&settings.0.room_url, &channels, settings.0.config.clone())?;
+    commands.insert_resource(client);
+    Ok(())
+}
+
+fn send_bot_voice(
+    mut client: ResMut<MatchboxClient>,
+    mut state: ResMut<BotState>,
+    settings: Res<BotSettings>,
+    time: Res<Time<Real>>,
+) {
+    let traffic = &settings.0.traffic;
+    if !client.is_connected() {
+        return;
+    }
+    state.voice_budget += traffic.voice_frames_per_second * time.delta_secs();
+    let payload = vec![0; traffic.voice_frame_size];
+    while state.voice_budget >= 1.0 {
+        state.voice_budget -= 1.0;
+        state.voice_timestamp = state.voice_timestamp.wrapping_add(1);
+        client.send_voice(state.voice_timestamp, &payload);
+    }
+}
+
+fn echo_relayed(
+    mut client: ResMut<MatchboxClient>,
+    mut relayed_messages: EventReader<RelayedMessage>,
+    settings: Res<BotSettings>,
+) {
+    if !settings.0.traffic.echo_relayed {
+        relayed_messages.clear();
+        return;
+    }
+    for message in relayed_messages.read() {
+        client.send_relayed(message.from, &message.payload);
+    }
+}
+
+fn send_bot_transfer(
+    mut client: ResMut<MatchboxClient>,
+    mut state: ResMut<BotState>,
+    settings: Res<BotSettings>,
+) {
+    let transfer_size = settings.0.traffic.transfer_size;
+    if state.transfer_sent || transfer_size == 0 || !client.is_connected() {
+        return;
+    }
+    client.send_transfer(vec![0; transfer_size]);
+    state.transfer_sent = true;
+}
+
+/// A set of headless bot clients in the current process, for load testing a host.
+///
+/// Only one client fits into a [`World`], so every bot runs its own [`App`]. To distribute
+/// the load, run a swarm on several machines with the same room URL.
+///
+/// ```no_run
+/// # use bevy::prelude::*;
+/// # use bevy_replicon_matchbox::*;
+/// let bot = BotPlugin {
+///     room_url: "ws://localhost:3536/my-game".into(),
+///     config: Default::default(),
+///     traffic: BotTraffic {
+///         voice_frames_per_second: 50.0,
+///         voice_frame_size: 80,
+///         ..Default::default()
+///     },
+/// };
+/// let mut swarm = BotSwarm::new(20, bot, |_app| {
+///     // register the same replication as the game
+/// });
+/// loop {
+///     swarm.update();
+/// }
+/// ```
+pub struct BotSwarm {
+    bots: Vec<App>,
+}
+
+impl BotSwarm {
+    /// Creates `count` bots, calling `setup` on each app to register the game's replication
+    /// rules and systems.
+    pub fn new(count: usize, bot: BotPlugin, setup: impl Fn(&mut App)) -> Self {
+        let bots = (0..count)
+            .map(|_| {
+                let mut app = App::new();
+                app.add_plugins((
+                    MinimalPlugins,
+                    RepliconPlugins,
+                    RepliconMatchboxPlugins,
+                    bot.clone(),
+                ));
+                setup(&mut app);
+                app.finish();
+                app.cleanup();
+                app
+            })
+            .collect();
+        Self { bots }
+    }
+
+    /// Runs a single frame of every bot.
+    pub fn update(&mut self) {
+        for app in &mut self.bots {
+            app.update();
+        }
+    }
+
+    /// Bots currently connected to the host.
+    pub fn connected(&self) -> usize {
+        self.bots
+            .iter()
+            .filter(|app| {
+                app.world()
+                    .get_resource::<MatchboxClient>()
+                    .is_some_and(|client| client.is_connected())
+            })
+            .count()
+    }
+
+    pub fn apps_mut(&mut self) -> impl Iterator<Item = &mut App> {
+        self.bots.iter_mut()
+    }
+}
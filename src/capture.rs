@@ -0,0 +1,203 @@
+use bevy::prelude::*;
+use bevy_matchbox::matchbox_socket::PeerId;
+use bytes::Bytes;
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+const MAGIC: &[u8; 8] = b"MBXCAP1\n";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureDirection {
+    Sent,
+    Received,
+}
+
+/// A single message of a capture.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaptureRecord {
+    pub direction: CaptureDirection,
+    /// [`Time<Real>`] elapsed time when the message was recorded.
+    pub timestamp: Duration,
+    pub peer_id: PeerId,
+    /// Replicon channel ID.
+    pub channel_id: usize,
+    pub data: Bytes,
+}
+
+/// Writes records to the capture sink of a host or client.
+pub(crate) struct PacketCapture {
+    writer: Box<dyn Write + Send + Sync>,
+}
+
+impl PacketCapture {
+    pub(crate) fn new(mut writer: Box<dyn Write + Send + Sync>) -> io::Result<Self> {
+        writer.write_all(MAGIC)?;
+        Ok(Self { writer })
+    }
+
+    pub(crate) fn record(
+        &mut self,
+        direction: CaptureDirection,
+        timestamp: Duration,
+        peer_id: PeerId,
+        channel_id: usize,
+        data: &[u8],
+    ) -> io::Result<()> {
+        let direction = match direction {
+            CaptureDirection::Sent => 0u8,
+            CaptureDirection::Received => 1,
+        };
+        self.writer.write_all(&[direction])?;
+        self.writer
+            .write_all(&(timestamp.as_micros() as u64).to_le_bytes())?;
+        self.writer.write_all(peer_id.0.as_bytes())?;
+        self.writer.write_all(&(channel_id as u16).to_le_bytes())?;
+        self.writer.write_all(&(data.len() as u32).to_le_bytes())?;
+        self.writer.write_all(data)
+    }
+
+    pub(crate) fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Records a message, stopping the capture on write errors.
+pub(crate) fn record(
+    capture: &mut Option<PacketCapture>,
+    direction: CaptureDirection,
+    timestamp: Duration,
+    peer_id: PeerId,
+    channel_id: usize,
+    data: &[u8],
+) {
+    let Some(writer) = capture else {
+        return;
+    };
+    if let Err(e) = writer.record(direction, timestamp, peer_id, channel_id, data) {
+        error!("stopping packet capture: {e}");
+        *capture = None;
+    }
+}
+
+/// Reads the records of a capture.
+///
+/// A capture starts with the 8 byte magic `MBXCAP1\n`, followed by one record per message.
+/// All integers are little endian:
+///
+/// | field      | size | description                                   |
+/// |------------|------|-----------------------------------------------|
+/// | direction  | 1    | `0` sent, `1` received                        |
+/// | timestamp  | 8    | microseconds of [`Time<Real>`] elapsed time   |
+/// | peer ID    | 16   | UUID of the remote peer                       |
+/// | channel ID | 2    | replicon channel ID                           |
+/// | length     | 4    | payload length                                |
+/// | payload    | *    | message without the transport's marker byte   |
+pub struct CaptureReader<R> {
+    reader: R,
+}
+
+impl<R: Read> CaptureReader<R> {
+    /// Checks the header and returns a reader positioned at the first record.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut magic = [0; MAGIC.len()];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a packet capture",
+            ));
+        }
+        Ok(Self { reader })
+    }
+
+    fn read_record(&mut self, direction: u8) -> io::Result<CaptureRecord> {
+        let direction = match direction {
+            0 => CaptureDirection::Sent,
+            1 => CaptureDirection::Received,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "invalid direction",
+                ));
+            }
+        };
+        let mut timestamp = [0; 8];
+        self.reader.read_exact(&mut timestamp)?;
+        let mut peer_id = [0; 16];
+        self.reader.read_exact(&mut peer_id)?;
+        let mut channel_id = [0; 2];
+        self.reader.read_exact(&mut channel_id)?;
+        let mut len = [0; 4];
+        self.reader.read_exact(&mut len)?;
+        let mut data = vec![0; u32::from_le_bytes(len) as usize];
+        self.reader.read_exact(&mut data)?;
+
+        Ok(CaptureRecord {
+            direction,
+            timestamp: Duration::from_micros(u64::from_le_bytes(timestamp)),
+            peer_id: PeerId(uuid::Uuid::from_bytes(peer_id)),
+            channel_id: u16::from_le_bytes(channel_id).into(),
+            data: data.into(),
+        })
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = io::Result<CaptureRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut direction = [0];
+        match self.reader.read(&mut direction) {
+            Ok(0) => None,
+            Ok(_) => Some(self.read_record(direction[0])),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// Writer whose clones share the written bytes, so tests can read back what a log wrote.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub(crate) struct SharedBuf(pub(crate) std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+#[cfg(test)]
+impl Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_capture() {
+    let buf = SharedBuf::default();
+    let mut capture = Some(PacketCapture::new(Box::new(buf.clone())).unwrap());
+    let peer_id = PeerId(uuid::Uuid::from_u128(42));
+    record(
+        &mut capture,
+        CaptureDirection::Received,
+        Duration::from_millis(5),
+        peer_id,
+        2,
+        &[1, 2, 3],
+    );
+
+    let data = buf.0.lock().unwrap().clone();
+    let records: Vec<_> = CaptureReader::new(&data[..])
+        .unwrap()
+        .collect::<io::Result<_>>()
+        .unwrap();
+    assert_eq!(
+        records,
+        [CaptureRecord {
+            direction: CaptureDirection::Received,
+            timestamp: Duration::from_millis(5),
+            peer_id,
+            channel_id: 2,
+            data: Bytes::from_static(&[1, 2, 3]),
+        }]
+    );
+}
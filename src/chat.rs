@@ -0,0 +1,258 @@
+use crate::shared::*;
+use bevy::prelude::*;
+use bevy_matchbox::matchbox_socket::PeerId;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[cfg(feature = "client")]
+use crate::client::MatchboxClient;
+#[cfg(feature = "server")]
+use crate::server::{MatchboxHost, client_violation};
+#[cfg(feature = "server")]
+use crate::stats::ConnectionStats;
+
+/// Upper bound of the serialized fields besides the text.
+const CHAT_OVERHEAD: usize = 64;
+
+/// Text chat message, emitted on the host and on the clients it was delivered to.
+///
+/// Chat has its own reliable channel and works as soon as a client is connected, before
+/// replicon authorized it.
+#[derive(Event, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChatMessage {
+    pub sender: PeerId,
+    /// Addressed client, `None` for messages to everyone.
+    pub recipient: Option<PeerId>,
+    pub text: String,
+}
+
+/// Limits the host enforces on chat messages of each client.
+#[derive(Clone, Debug)]
+pub struct ChatLimits {
+    /// Sustained number of messages per second.
+    pub messages_per_second: f32,
+    /// Messages that can be sent at once after a quiet period.
+    pub burst: u32,
+    /// Longer messages are dropped.
+    pub max_length: usize,
+}
+
+impl Default for ChatLimits {
+    fn default() -> Self {
+        Self {
+            messages_per_second: 1.0,
+            burst: 5,
+            max_length: 500,
+        }
+    }
+}
+
+/// Inspects chat messages on the host before they are delivered.
+///
+/// Can rewrite the text, returning `false` drops the message.
+pub type ChatFilter = Box<dyn Fn(&mut ChatMessage) -> bool + Send + Sync>;
+
+/// Chat state of the host.
+#[derive(Default)]
+pub(crate) struct ChatState {
+    pub(crate) filter: Option<ChatFilter>,
+    allowance: HashMap<PeerId, f32>,
+}
+
+impl ChatState {
+    /// Refills the allowance of all clients for the elapsed `delta_secs`.
+    fn refill(&mut self, limits: &ChatLimits, delta_secs: f32) {
+        for allowance in self.allowance.values_mut() {
+            *allowance =
+                (*allowance + limits.messages_per_second * delta_secs).min(limits.burst as f32);
+        }
+    }
+
+    /// Takes one message from the allowance of the peer if available.
+    fn try_take(&mut self, limits: &ChatLimits, peer_id: PeerId) -> bool {
+        let allowance = self.allowance.entry(peer_id).or_insert(limits.burst as f32);
+        if *allowance < 1.0 {
+            return false;
+        }
+        *allowance -= 1.0;
+        true
+    }
+
+    /// Drops the allowance of a peer that is gone.
+    #[cfg(feature = "server")]
+    pub(crate) fn forget(&mut self, peer_id: PeerId) {
+        self.allowance.remove(&peer_id);
+    }
+}
+
+pub(crate) fn chat_packet(message: &ChatMessage) -> bevy_matchbox::matchbox_socket::Packet {
+    to_vec_packet(message, message.text.len() + CHAT_OVERHEAD)
+}
+
+#[cfg(feature = "server")]
+impl MatchboxHost {
+    /// Sends a chat message from the host player, to everyone if `recipient` is `None`.
+    pub fn send_chat(&mut self, recipient: Option<PeerId>, text: impl Into<String>) {
+        let Some(sender) = self.socket.id() else {
+            return;
+        };
+        let message = ChatMessage {
+            sender,
+            recipient,
+            text: text.into(),
+        };
+        self.deliver_chat(&message);
+    }
+
+    /// Sets a filter applied to chat messages of clients before they are delivered.
+    pub fn set_chat_filter(
+        &mut self,
+        filter: impl Fn(&mut ChatMessage) -> bool + Send + Sync + 'static,
+    ) {
+        self.chat.filter = Some(Box::new(filter));
+    }
+
+    fn deliver_chat(&mut self, message: &ChatMessage) {
+        let packet = chat_packet(message);
+        let chat_channel = self.layout.chat_channel();
+        let recipients: Vec<_> = match message.recipient {
+            Some(recipient) => self
+                .client_entities
+                .contains_key(&recipient)
+                .then_some(recipient)
+                .into_iter()
+                .collect(),
+            None => self
+                .client_entities
+                .keys()
+                .copied()
+                .filter(|&peer_id| peer_id != message.sender)
+                .collect(),
+        };
+        for peer_id in recipients {
+            self.forward(peer_id, chat_channel, packet.clone());
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+pub(crate) fn receive_chat_on_host(
+    mut server: ResMut<MatchboxHost>,
+    mut stats: Query<&mut ConnectionStats>,
+    mut chat_messages: EventWriter<ChatMessage>,
+    time: Res<Time<Real>>,
+) {
+    let limits = server.config().chat_limits.clone();
+    server.chat.refill(&limits, time.delta_secs());
+    let chat_channel = server.layout.chat_channel();
+    for (from, packet) in server.socket.channel_mut(chat_channel).receive() {
+        if !server.client_entities.contains_key(&from) {
+            continue;
+        }
+        if packet.len() > server.config().max_inbound_message_size {
+            client_violation(&mut server, &mut stats, from, "oversized chat message");
+            continue;
+        }
+        let Ok(mut message) = from_packet::<ChatMessage>(&packet) else {
+            client_violation(&mut server, &mut stats, from, "malformed chat message");
+            continue;
+        };
+        message.sender = from;
+        if message.text.len() > limits.max_length {
+            debug!("dropping too long chat message from {from}");
+            continue;
+        }
+        if !server.chat.try_take(&limits, from) {
+            debug!("chat rate limit of {from} exceeded");
+            continue;
+        }
+        if let Some(filter) = &server.chat.filter {
+            if !filter(&mut message) {
+                trace!("chat message from {from} filtered");
+                continue;
+            }
+        }
+        server.deliver_chat(&message);
+        chat_messages.write(message);
+    }
+}
+
+#[cfg(feature = "client")]
+impl MatchboxClient {
+    /// Sends a chat message through the host, to everyone if `recipient` is `None`.
+    pub fn send_chat(&mut self, recipient: Option<PeerId>, text: impl Into<String>) {
+        let Some(host_peer_id) = self.host_peer_id else {
+            warn!("can't chat before connecting to the host");
+            return;
+        };
+        let message = ChatMessage {
+            // replaced by the host
+            sender: host_peer_id,
+            recipient,
+            text: text.into(),
+        };
+        let chat_channel = self.layout.chat_channel();
+        self.socket
+            .channel_mut(chat_channel)
+            .send(chat_packet(&message), host_peer_id);
+    }
+}
+
+#[cfg(feature = "client")]
+pub(crate) fn receive_chat_on_client(
+    mut client: ResMut<MatchboxClient>,
+    mut chat_messages: EventWriter<ChatMessage>,
+) {
+    let chat_channel = client.layout.chat_channel();
+    let Ok(channel) = client.socket.get_channel_mut(chat_channel) else {
+        return;
+    };
+    for (from, packet) in channel.receive() {
+        // the host applies the filter and limits, other clients of a full mesh would bypass
+        // them and could forge the sender
+        if Some(from) != client.host_peer_id {
+            debug!("ignoring chat message from peer {from}, which isn't the host");
+            continue;
+        }
+        let Ok(message) = from_packet::<ChatMessage>(&packet) else {
+            error!("failed to deserialize chat message {}", packet.len());
+            continue;
+        };
+        chat_messages.write(message);
+    }
+}
+
+#[test]
+fn test_chat_rate_limit() {
+    let limits = ChatLimits {
+        messages_per_second: 2.0,
+        burst: 2,
+        max_length: 10,
+    };
+    let peer_id = PeerId(Default::default());
+    let mut state = ChatState::default();
+    assert!(state.try_take(&limits, peer_id));
+    assert!(state.try_take(&limits, peer_id));
+    assert!(!state.try_take(&limits, peer_id), "burst is used up");
+
+    state.refill(&limits, 0.5);
+    assert!(state.try_take(&limits, peer_id));
+    assert!(!state.try_take(&limits, peer_id));
+
+    #[cfg(feature = "server")]
+    {
+        state.forget(peer_id);
+        assert!(state.allowance.is_empty());
+    }
+}
+
+#[test]
+fn test_chat_packet() {
+    let message = ChatMessage {
+        sender: PeerId(Default::default()),
+        recipient: None,
+        text: "hello".into(),
+    };
+    let packet = chat_packet(&message);
+    assert_eq!(from_packet::<ChatMessage>(&packet).unwrap(), message);
+}
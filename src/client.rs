@@ -1,41 +1,137 @@
+use crate::barrier::TickBarrier;
+use crate::capture::*;
+use crate::chat::*;
+use crate::delivery::PendingDeliveries;
+use crate::loopback::*;
+use crate::mute::{ChannelMutes, MutedChannel};
+#[cfg(feature = "net_debug")]
+use crate::net_debug::*;
+use crate::relay::{DirectMessage, RelayEnvelope, RelayedMessage};
+use crate::room_url::{IntoRoomUrl, redact_room_url};
+use crate::send_failure::SendFailures;
+use crate::shaping::{ChannelBuckets, ChannelPacer};
 use crate::shared::*;
+use crate::stats::{BandwidthProfile, BandwidthReports, ConnectionStats, MessageKind};
+use crate::transfer::*;
+use crate::user_channel::*;
+use crate::voice::*;
+use crate::wait_connected::{ConnectSignal, WaitConnected};
+use bevy::ecs::schedule::{InternedScheduleLabel, InternedSystemSet, ScheduleLabel};
 use bevy::prelude::*;
 use bevy_matchbox::MatchboxSocket;
-use bevy_matchbox::matchbox_socket::PeerId;
+use bevy_matchbox::matchbox_socket::{Packet, PeerId};
 use bevy_matchbox::prelude::PeerState;
 use bevy_replicon::prelude::*;
+use bevy_replicon::shared::protocol::ProtocolHash;
+use bytes::Bytes;
+use std::collections::{HashSet, VecDeque};
 use std::io;
+use std::time::Duration;
 
 /// Adds a client messaging backend made for examples to `bevy_replicon`.
-pub struct RepliconMatchboxClientPlugin;
+pub struct RepliconMatchboxClientPlugin {
+    /// Schedule of the systems reading from the socket.
+    pub receive_schedule: InternedScheduleLabel,
+    /// Set of the systems reading from the socket.
+    ///
+    /// Must run before replicon processes received messages, which happens in
+    /// [`ClientSet::Receive`] in [`PreUpdate`].
+    pub receive_set: InternedSystemSet,
+    /// Schedule of the systems writing to the socket.
+    pub send_schedule: InternedScheduleLabel,
+    /// Set of the systems writing to the socket.
+    ///
+    /// Must run after replicon produced messages, which happens in [`ClientSet::Send`] in
+    /// [`PostUpdate`].
+    pub send_set: InternedSystemSet,
+}
+
+impl Default for RepliconMatchboxClientPlugin {
+    fn default() -> Self {
+        Self {
+            receive_schedule: PreUpdate.intern(),
+            receive_set: ClientSet::ReceivePackets.intern(),
+            send_schedule: PostUpdate.intern(),
+            send_set: ClientSet::SendPackets.intern(),
+        }
+    }
+}
 
 impl Plugin for RepliconMatchboxClientPlugin {
     fn build(&self, app: &mut App) {
+        app.add_event::<SignalingLost>()
+            .add_event::<HostLost>()
+            .add_event::<DisconnectedFromHost>()
+            .add_event::<HostFailover>()
+            .add_event::<ConnectionProgress>()
+            .add_event::<RelayedMessage>()
+            .add_event::<DirectMessage>()
+            .add_event::<VoiceFrame>()
+            .add_event::<ChatMessage>()
+            .add_event::<UserMessage>()
+            .add_event::<TransferProgress>()
+            .add_event::<TransferCompleted>()
+            .add_event::<TransferCorrupted>()
+            .add_event::<TransferCancelled>()
+            .init_resource::<BandwidthReports>();
+        #[cfg(feature = "net_debug")]
+        app.add_event::<MessageSent>()
+            .add_event::<MessageReceived>();
         app.add_systems(
-            PreUpdate,
+            self.receive_schedule,
             (
+                build_deferred_client.run_if(resource_exists::<DeferredMatchboxClient>),
                 receive_packets.run_if(resource_exists::<MatchboxClient>),
                 receive_system_channel_packets.run_if(resource_exists::<MatchboxClient>),
+                release_tick_barrier.run_if(resource_exists::<MatchboxClient>),
+                release_held_packets
+                    .run_if(resource_exists::<MatchboxClient>.and(fixed_tick_this_frame)),
+                receive_relayed.run_if(resource_exists::<MatchboxClient>),
+                receive_direct.run_if(resource_exists::<MatchboxClient>),
+                receive_voice.run_if(resource_exists::<MatchboxClient>),
+                receive_chat_on_client.run_if(resource_exists::<MatchboxClient>),
+                receive_user_on_client.run_if(resource_exists::<MatchboxClient>),
+                update_user_handles_on_client.run_if(resource_exists::<MatchboxClient>),
+                update_client_transfers.run_if(resource_exists::<MatchboxClient>),
                 update_peers.run_if(resource_exists::<MatchboxClient>),
+                check_channel_open_timeout.run_if(resource_exists::<MatchboxClient>),
+                check_channel_registry.run_if(
+                    resource_exists::<MatchboxClient>.and(resource_changed::<RepliconChannels>),
+                ),
+                receive_loopback_on_client.run_if(resource_exists::<LoopbackClient>),
             )
                 .chain()
-                .in_set(ClientSet::ReceivePackets),
+                .in_set(self.receive_set),
         );
 
+        app.add_systems(
+            self.send_schedule,
+            (
+                send_packets.run_if(not(no_host_defined).and(resource_exists::<MatchboxClient>)),
+                send_loopback_on_client.run_if(resource_exists::<LoopbackClient>),
+                update_bandwidth_report
+                    .run_if(resource_exists::<MatchboxClient>)
+                    .after(send_packets),
+            )
+                .in_set(self.send_set),
+        );
         app.add_systems(
             PostUpdate,
             (
-                set_disconnected
-                    .in_set(ClientSet::Send)
-                    .run_if(resource_removed::<MatchboxClient>),
-                send_packets
-                    .in_set(ClientSet::SendPackets)
-                    .run_if(not(no_host_defined).and(resource_exists::<MatchboxClient>)),
-            ),
+                set_disconnected.run_if(resource_removed::<MatchboxClient>),
+                set_loopback_disconnected.run_if(resource_removed::<LoopbackClient>),
+            )
+                .in_set(ClientSet::Send),
         );
     }
 }
 
+/// Run condition that is `true` while the [`MatchboxClient`] is connected to the host and
+/// all data channels to it are open, the counterpart of [`MatchboxClient::wait_connected`].
+pub fn matchbox_client_connected(client: Option<Res<MatchboxClient>>) -> bool {
+    client.is_some_and(|client| client.is_connected())
+}
+
 fn no_host_defined(client: Option<Res<MatchboxClient>>) -> bool {
     if let Some(client) = client {
         return client.host_peer_id.is_none();
@@ -43,31 +139,148 @@ fn no_host_defined(client: Option<Res<MatchboxClient>>) -> bool {
     true
 }
 
+fn build_deferred_client(
+    mut commands: Commands,
+    deferred: Res<DeferredMatchboxClient>,
+    channels: Res<RepliconChannels>,
+) -> Result {
+    commands.remove_resource::<DeferredMatchboxClient>();
+    let client =
+        MatchboxClient::with_config(&deferred.room_url, &channels, deferred.config.clone())?;
+    commands.insert_resource(client);
+    Ok(())
+}
+
+fn check_channel_registry(client: Res<MatchboxClient>, channels: Res<RepliconChannels>) {
+    if !client.layout.covers(&channels) {
+        warn!(
+            "replicon channels changed after the client was created, \
+            reconnect with a new client to use them"
+        );
+    }
+}
+
 fn set_disconnected(mut replicon_client: ResMut<RepliconClient>) {
     replicon_client.set_status(RepliconClientStatus::Disconnected);
 }
 
-fn update_peers(mut client: ResMut<MatchboxClient>, mut commands: Commands) {
+fn update_peers(
+    mut client: ResMut<MatchboxClient>,
+    mut replicon_client: ResMut<RepliconClient>,
+    mut commands: Commands,
+    mut signaling_lost: EventWriter<SignalingLost>,
+    mut host_lost: EventWriter<HostLost>,
+    mut disconnected: EventWriter<DisconnectedFromHost>,
+    channels: Res<RepliconChannels>,
+) {
     let Ok(peers) = client.socket.try_update_peers() else {
+        trace!("signaling connection lost");
+        if client.auto_rejoin(&channels) {
+            replicon_client.set_status(RepliconClientStatus::Connecting);
+            return;
+        }
+        if let Some(event) = client.fail_over(&channels, DisconnectKind::SignalingLost) {
+            replicon_client.set_status(RepliconClientStatus::Disconnected);
+            commands.send_event(event);
+            return;
+        }
+        signaling_lost.write(SignalingLost);
+        client.fail_connecting(&mut commands, &DisconnectKind::SignalingLost);
+        disconnected.write(DisconnectedFromHost {
+            kind: DisconnectKind::SignalingLost,
+        });
         commands.remove_resource::<MatchboxClient>();
         return;
     };
 
+    if client.peer_id.is_none() {
+        client.peer_id = client.socket.id();
+        if client.peer_id.is_some() {
+            commands.send_event(ConnectionProgress::SignalingConnected);
+        }
+    }
+    for &(peer_id, state) in &peers {
+        match state {
+            PeerState::Connected => client.open_peers.insert(peer_id),
+            PeerState::Disconnected => client.open_peers.remove(&peer_id),
+        };
+    }
+    client.finish_connecting(&mut replicon_client, &mut commands);
+
     let Some(host_peer_id) = client.host_peer_id else {
         return;
     };
     for (peer_id, state) in peers {
-        if matches!(state, PeerState::Disconnected) && peer_id != host_peer_id {
+        if matches!(state, PeerState::Disconnected) && peer_id == host_peer_id {
             trace!("host {} disconnected", peer_id);
+            if client.auto_rejoin(&channels) {
+                replicon_client.set_status(RepliconClientStatus::Connecting);
+                return;
+            }
+            if let Some(event) = client.fail_over(&channels, DisconnectKind::IceFailed) {
+                replicon_client.set_status(RepliconClientStatus::Disconnected);
+                commands.send_event(event);
+                return;
+            }
+            host_lost.write(HostLost { host_peer_id });
+            client.fail_connecting(&mut commands, &DisconnectKind::IceFailed);
+            disconnected.write(DisconnectedFromHost {
+                kind: DisconnectKind::IceFailed,
+            });
             commands.remove_resource::<MatchboxClient>();
             return;
         }
     }
 }
 
+fn check_channel_open_timeout(
+    mut commands: Commands,
+    mut client: ResMut<MatchboxClient>,
+    mut replicon_client: ResMut<RepliconClient>,
+    mut disconnected: EventWriter<DisconnectedFromHost>,
+    channels: Res<RepliconChannels>,
+    time: Res<Time<Real>>,
+) {
+    let Some(timeout) = client.config.channel_open_timeout else {
+        return;
+    };
+    if client.peer_id.is_none()
+        || client.is_connected()
+        || client.pending_disconnect.is_some()
+        || client.socket.all_channels_closed()
+    {
+        return;
+    }
+    let since = *client.signaled_at.get_or_insert(time.elapsed());
+    if time.elapsed().saturating_sub(since) < timeout {
+        return;
+    }
+    warn!("data channels to the host didn't open within {timeout:?}, disconnecting");
+    if client.host_peer_id.is_some() {
+        client.close(DisconnectKind::ChannelOpenTimeout);
+        return;
+    }
+
+    // without the host's introduction there is no system channel to say goodbye on
+    if let Some(event) = client.fail_over(&channels, DisconnectKind::ChannelOpenTimeout) {
+        replicon_client.set_status(RepliconClientStatus::Disconnected);
+        commands.send_event(event);
+        return;
+    }
+    client.fail_connecting(&mut commands, &DisconnectKind::ChannelOpenTimeout);
+    disconnected.write(DisconnectedFromHost {
+        kind: DisconnectKind::ChannelOpenTimeout,
+    });
+    commands.remove_resource::<MatchboxClient>();
+}
+
 fn receive_system_channel_packets(
+    mut commands: Commands,
     mut client: ResMut<MatchboxClient>,
     mut replicon_client: ResMut<RepliconClient>,
+    mut progress: EventWriter<ConnectionProgress>,
+    time: Res<Time<Real>>,
+    protocol_hash: Option<Res<ProtocolHash>>,
 ) {
     if client.socket.all_channels_closed() {
         trace!("matchbox socket was closed");
@@ -78,27 +291,112 @@ fn receive_system_channel_packets(
         return;
     };
     for (peer_id, packet) in channel.receive() {
+        let from_host = client.is_host(peer_id);
         let Ok(message) = from_packet(&packet) else {
-            error!("failed to deserialize system message {}", packet.len());
+            if from_host {
+                client.protocol_violation("malformed system message");
+            }
             continue;
         };
         trace!(
             "client received system message {:?} from peer {}",
             message, peer_id
         );
+        // other clients of a full mesh only acknowledge user messages
+        let from_any_peer = matches!(
+            message,
+            SystemChannelMessage::RequestJoin
+                | SystemChannelMessage::Join { .. }
+                | SystemChannelMessage::Delivered { .. }
+        );
+        if !from_host && !from_any_peer {
+            debug!("ignoring system message from peer {peer_id}, which isn't the host");
+            continue;
+        }
 
         match message {
-            SystemChannelMessage::ConnectedToHost => {
-                client.host_peer_id = Some(peer_id);
-                replicon_client.set_status(RepliconClientStatus::Connected);
+            SystemChannelMessage::ConnectedToHost {
+                session_token,
+                protocol_hash: host_hash,
+            } => {
+                if host_hash.is_some() && host_hash != protocol_hash.as_deref().copied() {
+                    warn!("protocol differs from host {peer_id}, disconnecting");
+                    client.host_peer_id.get_or_insert(peer_id);
+                    client.close(DisconnectKind::ProtocolMismatch);
+                    continue;
+                }
+                client.rejoin_token = None;
+                let resumed = client.session_token == Some(session_token);
+                client.session_token = Some(session_token);
+                if client.host_peer_id.replace(peer_id) != Some(peer_id) {
+                    commands.trigger(HostAssigned {
+                        host_peer_id: peer_id,
+                    });
+                }
+                progress.write(ConnectionProgress::HostDiscovered);
+                if client.auto_rejoining {
+                    client.auto_rejoining = false;
+                    if !resumed {
+                        warn!("host no longer knows the session, disconnecting");
+                        client.close(DisconnectKind::Timeout);
+                        continue;
+                    }
+                    info!("rejoined host {}", peer_id);
+                }
+                client.rejoin_attempts = 0;
+                client.awaiting_channels = true;
+                client.finish_connecting(&mut replicon_client, &mut commands);
             }
-            SystemChannelMessage::HostRequestsDisconnect => {
-                info!("disconnected by server");
-                client.should_disconnect = true;
+            SystemChannelMessage::HostRequestsDisconnect { reason } => {
+                match &reason {
+                    Some(reason) => info!("kicked by server: {reason}"),
+                    None => info!("disconnected by server"),
+                }
+                // throttled connection attempts are rejected before the host introduces itself
+                client.host_peer_id.get_or_insert(peer_id);
+                client.close(DisconnectKind::Kicked { reason });
             }
-
-            SystemChannelMessage::ClientDisconnects => {
-                error!("Unexpected message received from host");
+            SystemChannelMessage::ReceiveReport { bytes_per_second } => {
+                client.stats.apply_report(bytes_per_second.into());
+            }
+            SystemChannelMessage::Ping { sent_at_micros } => {
+                let packet = system_packet(&SystemChannelMessage::Pong { sent_at_micros });
+                client
+                    .socket
+                    .channel_mut(SYSTEM_CHANNEL_ID)
+                    .send(packet, peer_id);
+            }
+            SystemChannelMessage::Pong { sent_at_micros } => {
+                let sent_at = Duration::from_micros(sent_at_micros);
+                client
+                    .stats
+                    .record_rtt(time.elapsed().saturating_sub(sent_at));
+            }
+            SystemChannelMessage::Queued { position } => {
+                debug!("waiting for a free slot at position {position}");
+                progress.write(ConnectionProgress::Queued { position });
+            }
+            SystemChannelMessage::TickBarrier { counts } => {
+                client.barrier.add_barrier(&counts);
+            }
+            SystemChannelMessage::Delivered { id } => {
+                if let Some(delivered) = client.deliveries.confirm(peer_id, id) {
+                    commands.trigger(delivered);
+                }
+            }
+            SystemChannelMessage::RequestJoin => {
+                // only the host asks, but other clients of a full mesh could ask as well to
+                // learn the session token
+                if client.join_sent_to.is_some() {
+                    debug!("ignoring another join request from peer {peer_id}");
+                    continue;
+                }
+                client.send_join(peer_id);
+            }
+            // only the host handles joins
+            SystemChannelMessage::Join { .. } => (),
+            SystemChannelMessage::ClientDisconnects | SystemChannelMessage::ProtocolMismatch => {
+                client.protocol_violation("unexpected system message");
             }
         }
     }
@@ -108,34 +406,182 @@ fn receive_packets(
     mut client: ResMut<MatchboxClient>,
     mut replicon_client: ResMut<RepliconClient>,
     channels: Res<RepliconChannels>,
+    time: Res<Time<Real>>,
+    #[cfg(feature = "net_debug")] mut net_debug: NetDebugWriter,
 ) {
     if client.socket.all_channels_closed() {
         trace!("matchbox socket was closed");
         return;
     }
 
-    for (channel_id, _) in channels.server_channels().iter().enumerate() {
-        //server socket channels are the same as the channel id +1 for the system channel
-        let socket_channel_id = 1 + channel_id;
+    // client channels only carry messages to the host
+    for channel_id in 0..client.layout.client_channel_count() {
+        let Some(socket_channel_id) = client.layout.client_channel(channel_id) else {
+            continue;
+        };
+        let Ok(channel) = client.socket.get_channel_mut(socket_channel_id) else {
+            continue;
+        };
+        for (id, _) in channel.receive() {
+            if client.is_host(id) {
+                client.protocol_violation("message on a client channel");
+            }
+        }
+    }
+
+    for channel_id in 0..client.layout.server_channel_count() {
+        let Some(socket_channel_id) = client.layout.server_channel(channel_id) else {
+            continue;
+        };
+        let kind = channels.server_channels()[channel_id];
+        let barrier = client.config.tick_barrier.is_some() && !matches!(kind, Channel::Unreliable);
         let Ok(channel) = client.socket.get_channel_mut(socket_channel_id) else {
             continue;
         };
         for (id, packet) in channel.receive() {
+            if !client.is_host(id) {
+                debug!("ignoring replicon message from peer {id}, which isn't the host");
+                continue;
+            }
             trace!(
                 "client received packet from peer {}, c:{} size {}",
                 id,
                 channel_id,
                 packet.len()
             );
-            replicon_client.insert_received(channel_id, strip_marker(packet.as_ref()));
+            client.stats.record_received(channel_id, packet.len());
+            if client.mutes.discards((), channel_id) {
+                trace!("discarding packet on muted channel {channel_id}");
+                if barrier {
+                    client.barrier.skip(channel_id);
+                }
+                continue;
+            }
+            let message = strip_marker(&packet);
+            if message.len() > client.config.max_inbound_message_size {
+                client.protocol_violation("oversized message");
+                if barrier {
+                    client.barrier.skip(channel_id);
+                }
+                continue;
+            }
+            client.stats.record_activity(time.elapsed());
+            #[cfg(feature = "net_debug")]
+            net_debug.received(id, channel_id, message.len());
+            record(
+                &mut client.capture,
+                CaptureDirection::Received,
+                time.elapsed(),
+                id,
+                channel_id,
+                &message,
+            );
+            if barrier {
+                client.barrier.push(channel_id, message, time.elapsed());
+            } else if client.config.hold_until_fixed_update {
+                client.held_packets.push((channel_id, message));
+            } else {
+                client.deliver(&mut replicon_client, channel_id, message);
+            }
+        }
+    }
+}
+
+/// Hands the reliable messages of complete ticks to replicon, see
+/// [`MatchboxConfig::tick_barrier`].
+fn release_tick_barrier(
+    mut client: ResMut<MatchboxClient>,
+    mut replicon_client: ResMut<RepliconClient>,
+    time: Res<Time<Real>>,
+) {
+    let Some(timeout) = client.config.tick_barrier else {
+        return;
+    };
+    for (channel_id, message) in client.barrier.release(time.elapsed(), timeout) {
+        if client.config.hold_until_fixed_update {
+            client.held_packets.push((channel_id, message));
+        } else {
+            client.deliver(&mut replicon_client, channel_id, message);
+        }
+    }
+}
+
+fn receive_relayed(
+    mut client: ResMut<MatchboxClient>,
+    mut relayed_messages: EventWriter<RelayedMessage>,
+) {
+    let relay_channel = client.layout.relay_channel();
+    let Ok(channel) = client.socket.get_channel_mut(relay_channel) else {
+        return;
+    };
+    for (from, packet) in channel.receive() {
+        if !client.is_host(from) {
+            debug!("ignoring relayed message from peer {from}, which isn't the host");
+            continue;
         }
+        let Ok(envelope) = from_packet::<RelayEnvelope>(&packet) else {
+            error!("failed to deserialize relayed message {}", packet.len());
+            continue;
+        };
+        relayed_messages.write(RelayedMessage {
+            from: envelope.peer_id,
+            payload: Bytes::copy_from_slice(envelope.payload),
+        });
+    }
+}
+
+fn receive_direct(
+    mut client: ResMut<MatchboxClient>,
+    mut direct_messages: EventWriter<DirectMessage>,
+) {
+    let direct_channel = client.layout.direct_channel();
+    let Ok(channel) = client.socket.get_channel_mut(direct_channel) else {
+        return;
+    };
+    for (from, packet) in channel.receive() {
+        direct_messages.write(DirectMessage {
+            from,
+            payload: strip_marker(&packet),
+        });
+    }
+}
+
+fn update_bandwidth_report(
+    mut client: ResMut<MatchboxClient>,
+    mut reports: ResMut<BandwidthReports>,
+    time: Res<Time<Real>>,
+) {
+    let Some(window) = client.config.bandwidth_profile_window else {
+        return;
+    };
+    let MatchboxClient { stats, profile, .. } = &mut *client;
+    profile.collect(stats);
+    if let Some(report) = profile.finish(
+        window,
+        time.elapsed(),
+        MessageKind::of_client_channel,
+        MessageKind::of_server_channel,
+    ) {
+        reports.client = Some(report);
+    }
+}
+
+fn release_held_packets(
+    mut client: ResMut<MatchboxClient>,
+    mut replicon_client: ResMut<RepliconClient>,
+) {
+    for (channel_id, message) in std::mem::take(&mut client.held_packets) {
+        client.deliver(&mut replicon_client, channel_id, message);
     }
 }
 
 fn send_packets(
+    mut commands: Commands,
     mut client: ResMut<MatchboxClient>,
     mut replicon_client: ResMut<RepliconClient>,
-    channels: Res<RepliconChannels>,
+    mut disconnected: EventWriter<DisconnectedFromHost>,
+    time: Res<Time<Real>>,
+    #[cfg(feature = "net_debug")] mut net_debug: NetDebugWriter,
 ) {
     if client.socket.any_channel_closed() {
         trace!("matchbox socket was closed");
@@ -146,48 +592,643 @@ fn send_packets(
         error!("set connected before host was defined");
         return;
     };
-    for (channel_id, message) in replicon_client.drain_sent() {
-        //client socket channels are offset by the server channel length + 1 for the system channel
-        let socket_channel_id = 1 + channels.server_channels().len() + channel_id;
-        client
-            .socket
-            .channel_mut(socket_channel_id)
-            .send(add_marker(message.as_ref()), host_peer_id);
+    let MatchboxClient {
+        socket,
+        config,
+        channel_buckets,
+        channel_pacer,
+        send_failures,
+        stats,
+        capture,
+        mutes,
+        layout,
+        ..
+    } = &mut *client;
+    if stats.update_rates(time.elapsed()) {
+        let packet = system_packet(&SystemChannelMessage::ReceiveReport {
+            bytes_per_second: stats.received_bytes_per_second as u32,
+        });
+        socket
+            .channel_mut(SYSTEM_CHANNEL_ID)
+            .send(packet, host_peer_id);
+        let packet = system_packet(&SystemChannelMessage::Ping {
+            sent_at_micros: time.elapsed().as_micros() as u64,
+        });
+        socket
+            .channel_mut(SYSTEM_CHANNEL_ID)
+            .send(packet, host_peer_id);
+    }
+    let released = mutes
+        .release()
+        .into_iter()
+        .map(|((), channel_id, message)| (channel_id, message));
+    for (channel_id, message) in released.chain(replicon_client.drain_sent()) {
+        let Some(socket_channel_id) = layout.client_channel(channel_id) else {
+            warn_once!(
+                "dropping messages on client channel {channel_id}, \
+                it was registered after the socket was created"
+            );
+            continue;
+        };
+        let Some(message) = mutes.hold((), channel_id, message) else {
+            continue;
+        };
+        #[cfg(feature = "net_debug")]
+        net_debug.sent(host_peer_id, channel_id, message.len());
+        record(
+            capture,
+            CaptureDirection::Sent,
+            time.elapsed(),
+            host_peer_id,
+            channel_id,
+            &message,
+        );
+        let packet = add_marker(message.as_ref());
+        if config.immediate_channels.contains(&channel_id) {
+            stats.record_sent(channel_id, packet.len());
+            send_failures.send(
+                socket,
+                stats,
+                config.send_failure_policy(channel_id),
+                socket_channel_id,
+                packet,
+                host_peer_id,
+            );
+            continue;
+        }
+        match (
+            config.channel_limits.get(&channel_id),
+            config.channel_pacing.get(&channel_id),
+        ) {
+            (Some(bucket), _) => channel_buckets.push(channel_id, bucket, packet),
+            (None, Some(&interval)) => channel_pacer.push(channel_id, interval, packet),
+            (None, None) => {
+                stats.record_sent(channel_id, packet.len());
+                send_failures.send(
+                    socket,
+                    stats,
+                    config.send_failure_policy(channel_id),
+                    socket_channel_id,
+                    packet,
+                    host_peer_id,
+                );
+            }
+        }
+    }
+    let layout = *layout;
+    let mut send = |channel_id, packet: Packet| {
+        // only messages of channels in the layout are shaped
+        let Some(socket_channel_id) = layout.client_channel(channel_id) else {
+            return;
+        };
+        stats.record_sent(channel_id, packet.len());
+        send_failures.send(
+            socket,
+            stats,
+            config.send_failure_policy(channel_id),
+            socket_channel_id,
+            packet,
+            host_peer_id,
+        );
+    };
+    channel_buckets.release(&config.channel_limits, time.delta(), &mut send);
+    channel_pacer.release(&config.channel_pacing, time.delta(), &mut send);
+    if send_failures.take_disconnect() && client.pending_disconnect.is_none() {
+        client.close(DisconnectKind::SendFailed);
     }
 
-    if client.should_disconnect {
+    if let Some(kind) = client.pending_disconnect.take() {
+        // flush so the host receives everything sent before the disconnect
+        let MatchboxClient {
+            socket,
+            channel_buckets,
+            channel_pacer,
+            ..
+        } = &mut *client;
+        for (channel_id, packet) in channel_buckets.drain().chain(channel_pacer.drain()) {
+            if let Some(socket_channel_id) = layout.client_channel(channel_id) {
+                socket
+                    .channel_mut(socket_channel_id)
+                    .send(packet, host_peer_id);
+            }
+        }
+        client.fail_connecting(&mut commands, &kind);
         client.socket.close();
         client.host_peer_id = None;
-        client.should_disconnect = false;
         replicon_client.set_status(RepliconClientStatus::Disconnected);
+        disconnected.write(DisconnectedFromHost { kind });
     }
 }
 
+/// Sent on the client when its connection to the host ended.
+///
+/// Accompanies [`SignalingLost`] and [`HostLost`], and is also sent when the host kicked
+/// the client or the client disconnected itself.
+#[derive(Event, Debug, Clone)]
+pub struct DisconnectedFromHost {
+    pub kind: DisconnectKind,
+}
+
+/// Sent on the client when it gave up on a room and moves on to the next of its
+/// [`MatchboxClient::with_fallbacks`].
+///
+/// Replaces [`SignalingLost`], [`HostLost`] and [`DisconnectedFromHost`] for the abandoned
+/// room. The new host doesn't know the previous session, so the client joins it as a new
+/// client.
+#[derive(Event, Debug, Clone)]
+pub struct HostFailover {
+    /// Room URL the client connects to now.
+    pub room_url: String,
+    /// Fallbacks left after this one.
+    pub remaining: usize,
+    /// Why the previous connection ended.
+    pub kind: DisconnectKind,
+}
+
+/// Triggered on the client when the host introduced itself, observe it with
+/// [`App::add_observer`].
+///
+/// Arrives before all data channels are open and thus before replicon reports the client
+/// as connected. Triggered again after an automatic rejoin, where the host may have a
+/// different ID.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct HostAssigned {
+    pub host_peer_id: PeerId,
+}
+
+/// Triggered on the client once it is connected to the host and all data channels are
+/// open, observe it with [`App::add_observer`].
+///
+/// Triggered together with replicon's switch to `RepliconClientStatus::Connected`, again
+/// after each automatic rejoin.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct ClientConnectedToHost {
+    pub host_peer: PeerId,
+}
+
+/// Triggered on the client when it disconnected before it was ever connected, observe it
+/// with [`App::add_observer`].
+///
+/// [`DisconnectedFromHost`] is sent as well.
+#[derive(Event, Debug, Clone)]
+pub struct ClientConnectionFailed {
+    pub phase: ConnectionPhase,
+    pub error: DisconnectKind,
+}
+
+/// Sent on the client as its connection progresses, e.g. to show progress on a loading
+/// screen.
+///
+/// Sent in this order, again during automatic rejoins. Which event was sent last shows
+/// where a connection stalls; [`ClientConnectionFailed`] reports the phase as well.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionProgress {
+    /// The signaling server assigned the client an ID.
+    SignalingConnected,
+    /// The host is full and queued the client in its waiting room, sent again whenever the
+    /// position changes. See `MatchboxConfig::waiting_room`.
+    Queued {
+        /// Position in the queue, one is next.
+        position: u32,
+    },
+    /// The host introduced itself.
+    HostDiscovered,
+    /// All data channels to the host are open.
+    ChannelsOpen,
+    /// The connection is ready and replicon reports the client as connected.
+    ///
+    /// Currently sent together with [`Self::ChannelsOpen`], as the host completes its side
+    /// of the handshake when introducing itself.
+    HandshakeComplete,
+}
+
+/// How far a client got before its connection failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionPhase {
+    /// The signaling server hasn't assigned an ID yet.
+    Signaling,
+    /// Connected to the signaling server, but the host hasn't introduced itself.
+    AwaitingHost,
+    /// The host introduced itself, but not all data channels to it are open.
+    OpeningChannels,
+}
+
 #[derive(Resource)]
 pub struct MatchboxClient {
     pub socket: MatchboxSocket,
     pub host_peer_id: Option<PeerId>,
-    should_disconnect: bool,
+    peer_id: Option<PeerId>,
+    pending_disconnect: Option<DisconnectKind>,
+    room_url: String,
+    session_token: Option<u64>,
+    rejoin_token: Option<u64>,
+    rejoin_attempts: u16,
+    auto_rejoining: bool,
+    fallback_urls: VecDeque<String>,
+    /// Peers whose data channels are all open, matchbox reports a peer as connected only
+    /// after every channel opened on this side.
+    open_peers: HashSet<PeerId>,
+    /// Peer `SystemChannelMessage::Join` was sent to on the current socket, trusted as the
+    /// host until it introduces itself. Only the first join request is answered.
+    join_sent_to: Option<PeerId>,
+    /// The host introduced itself, but not all channels to it are open yet.
+    awaiting_channels: bool,
+    /// Time the signaling server assigned the ID of the current socket at, see
+    /// [`MatchboxConfig::channel_open_timeout`].
+    signaled_at: Option<Duration>,
+    config: MatchboxConfig,
+    channel_buckets: ChannelBuckets,
+    channel_pacer: ChannelPacer,
+    send_failures: SendFailures,
+    connect_signal: ConnectSignal,
+    stats: ConnectionStats,
+    profile: BandwidthProfile,
+    held_packets: Vec<(usize, Bytes)>,
+    /// Messages received before the client is connected, see [`Self::deliver`].
+    early_packets: Vec<(usize, Bytes)>,
+    barrier: TickBarrier,
+    pub(crate) deliveries: PendingDeliveries,
+    mutes: ChannelMutes<()>,
+    capture: Option<PacketCapture>,
+    pub(crate) layout: ChannelLayout,
+    pub(crate) voice: VoiceState,
+    pub(crate) transfers: Transfers,
+    pub(crate) user_handles: UserChannelHandles,
 }
 
 impl MatchboxClient {
+    /// Fails with [`io::ErrorKind::InvalidInput`] if `room_url` isn't a valid
+    /// [`RoomUrl`](crate::RoomUrl).
     pub fn new(
-        room_url: impl Into<String>,
+        room_url: impl IntoRoomUrl,
         replicon_channels: &RepliconChannels,
     ) -> io::Result<Self> {
-        let socket = create_matchbox_socket(room_url, replicon_channels);
+        Self::with_config(room_url, replicon_channels, MatchboxConfig::default())
+    }
+
+    pub fn with_config(
+        room_url: impl IntoRoomUrl,
+        replicon_channels: &RepliconChannels,
+        config: MatchboxConfig,
+    ) -> io::Result<Self> {
+        let room_url = String::from(parse_room_url(room_url)?);
+        validate_config(
+            &config,
+            replicon_channels,
+            replicon_channels.client_channels().len(),
+        )?;
+        let socket = create_matchbox_socket(room_url.clone(), replicon_channels, &config);
+        let layout = ChannelLayout::new(replicon_channels, &config);
         Ok(Self {
             socket,
             host_peer_id: None,
-            should_disconnect: false,
+            peer_id: None,
+            pending_disconnect: None,
+            room_url,
+            session_token: None,
+            rejoin_token: None,
+            rejoin_attempts: 0,
+            auto_rejoining: false,
+            fallback_urls: VecDeque::new(),
+            open_peers: HashSet::new(),
+            join_sent_to: None,
+            awaiting_channels: false,
+            signaled_at: None,
+            config,
+            channel_buckets: ChannelBuckets::default(),
+            channel_pacer: ChannelPacer::default(),
+            send_failures: SendFailures::default(),
+            connect_signal: ConnectSignal::default(),
+            stats: ConnectionStats::default(),
+            profile: BandwidthProfile::default(),
+            held_packets: Vec::new(),
+            early_packets: Vec::new(),
+            barrier: TickBarrier::default(),
+            deliveries: PendingDeliveries::default(),
+            mutes: ChannelMutes::default(),
+            capture: None,
+            layout,
+            voice: VoiceState::default(),
+            transfers: Transfers::default(),
+            user_handles: UserChannelHandles::default(),
         })
     }
 
+    /// Connects like [`Self::with_config`], but asks the host to resume the client entity
+    /// identified by `session_token`.
+    ///
+    /// Resuming only succeeds while the host still keeps the entity around, see
+    /// [`MatchboxConfig::rejoin_grace_period`]. Otherwise the client joins as a new client.
+    pub fn rejoin(
+        room_url: impl IntoRoomUrl,
+        replicon_channels: &RepliconChannels,
+        config: MatchboxConfig,
+        session_token: u64,
+    ) -> io::Result<Self> {
+        let mut client = Self::with_config(room_url, replicon_channels, config)?;
+        client.rejoin_token = Some(session_token);
+        Ok(client)
+    }
+
+    /// Rooms to try in order when the connection to the current one fails for good, e.g.
+    /// standby hosts.
+    ///
+    /// Fails over when the signaling connection or the host is lost and no automatic rejoin
+    /// is left, see [`HostFailover`]. Fails with [`io::ErrorKind::InvalidInput`] if a URL
+    /// isn't a valid [`RoomUrl`](crate::RoomUrl).
+    pub fn with_fallbacks<U: IntoRoomUrl>(
+        mut self,
+        room_urls: impl IntoIterator<Item = U>,
+    ) -> io::Result<Self> {
+        for room_url in room_urls {
+            self.fallback_urls
+                .push_back(String::from(parse_room_url(room_url)?));
+        }
+        Ok(self)
+    }
+
+    /// Token assigned by the host, pass it to [`Self::rejoin`] to resume the session after a drop.
+    pub fn session_token(&self) -> Option<u64> {
+        self.session_token
+    }
+
+    /// ID the signaling server assigned to this client, `None` until the socket connected
+    /// to it.
+    ///
+    /// Changes when the client rejoins automatically, since that creates a new socket.
+    pub fn peer_id(&self) -> Option<PeerId> {
+        self.peer_id
+    }
+
+    /// ID of the host, `None` until the host introduced itself.
+    pub fn host_peer_id(&self) -> Option<PeerId> {
+        self.host_peer_id
+    }
+
+    /// Returns a handle that resolves once the client is connected, or disconnected before.
+    ///
+    /// Unlike replicon's run conditions, the handle can be awaited from async tasks outside
+    /// of the ECS. Systems can use [`matchbox_client_connected`] instead.
+    pub fn wait_connected(&self) -> WaitConnected {
+        self.connect_signal.handle()
+    }
+
+    pub fn config(&self) -> &MatchboxConfig {
+        &self.config
+    }
+
+    /// Records all replicon messages exchanged with the host to `writer`.
+    ///
+    /// Replaces a running capture. See [`CaptureReader`] for the format.
+    pub fn start_capture(
+        &mut self,
+        writer: impl std::io::Write + Send + Sync + 'static,
+    ) -> io::Result<()> {
+        self.capture = Some(PacketCapture::new(Box::new(writer))?);
+        Ok(())
+    }
+
+    /// Stops recording and flushes the capture.
+    pub fn stop_capture(&mut self) -> io::Result<()> {
+        match self.capture.take() {
+            Some(mut capture) => capture.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Traffic counters of the connection to the host.
+    pub fn stats(&self) -> &ConnectionStats {
+        &self.stats
+    }
+
+    /// Returns `true` once the host is known and all data channels to it are open, like
+    /// [`matchbox_client_connected`].
     pub fn is_connected(&self) -> bool {
-        self.host_peer_id.is_some()
+        self.host_peer_id.is_some() && !self.awaiting_channels
+    }
+
+    /// Mutes a replicon channel until [`Self::unmute`], e.g. to pause bulk traffic during a
+    /// loading screen.
+    ///
+    /// See [`MutedChannel`] for how each direction is treated.
+    pub fn mute(&mut self, channel: MutedChannel) {
+        self.mutes.mute(None, channel);
+    }
+
+    pub fn unmute(&mut self, channel: MutedChannel) {
+        self.mutes.unmute(None, channel);
+    }
+
+    /// Messages held back on muted outbound channels, they pile up while a channel stays
+    /// muted.
+    pub fn muted_messages(&self) -> usize {
+        self.mutes.held_messages()
+    }
+
+    /// Sends a payload to another client, relayed through the host.
+    ///
+    /// The host may filter the payload, see `MatchboxHost::set_relay_filter`, and drops
+    /// payloads over its [`MatchboxConfig::max_inbound_message_size`]. Relayed payloads share
+    /// the recipient's [`MatchboxConfig::bandwidth_limit`] with replication. Arrives as
+    /// [`RelayedMessage`] on the addressed client.
+    pub fn send_relayed(&mut self, to: PeerId, payload: &[u8]) {
+        let Some(host_peer_id) = self.host_peer_id else {
+            warn!("can't relay a message before connecting to the host");
+            return;
+        };
+        let packet = RelayEnvelope {
+            peer_id: to,
+            payload,
+        }
+        .to_packet();
+        let relay_channel = self.layout.relay_channel();
+        self.socket
+            .channel_mut(relay_channel)
+            .send(packet, host_peer_id);
+    }
+
+    /// Sends an unreliable payload directly to another client, bypassing the host.
+    ///
+    /// Clients only have connections to each other when the signaling server uses the full
+    /// mesh topology, e.g. `SignalingServer::full_mesh_builder`. With the client/server
+    /// topology only the host is reachable and the payload is dropped. Arrives as
+    /// [`DirectMessage`] on the addressed client.
+    pub fn send_direct(&mut self, to: PeerId, payload: &[u8]) {
+        if Some(to) == self.host_peer_id {
+            warn!("direct messages are for other clients, not the host");
+            return;
+        }
+        let direct_channel = self.layout.direct_channel();
+        self.socket
+            .channel_mut(direct_channel)
+            .send(add_marker(payload), to);
+    }
+
+    /// Other clients reachable with [`Self::send_direct`].
+    pub fn direct_peers(&self) -> impl Iterator<Item = PeerId> + '_ {
+        self.socket
+            .connected_peers()
+            .filter(|&peer_id| Some(peer_id) != self.host_peer_id)
+    }
+
+    /// Whether a peer is the host, or the peer the join went to before the host introduced
+    /// itself.
+    ///
+    /// With the full mesh topology other clients are connected as well, messages that only
+    /// the host may send are dropped if they come from anyone else.
+    pub(crate) fn is_host(&self, peer_id: PeerId) -> bool {
+        self.host_peer_id.or(self.join_sent_to) == Some(peer_id)
+    }
+
+    /// Counts a protocol violation of the host.
+    pub(crate) fn protocol_violation(&mut self, violation: &str) {
+        if self.stats.record_violation() {
+            warn!(
+                "protocol violation by host: {violation}, {} in total",
+                self.stats.protocol_violations
+            );
+        }
+    }
+
+    /// Replaces the socket to rejoin the host, returns `false` if automatic rejoins are
+    /// exhausted or the client never connected.
+    fn auto_rejoin(&mut self, replicon_channels: &RepliconChannels) -> bool {
+        let Some(session_token) = self.session_token else {
+            return false;
+        };
+        if self.rejoin_attempts >= self.config.auto_rejoin_attempts {
+            return false;
+        }
+        self.rejoin_attempts += 1;
+        info!(
+            "connection to host lost, rejoining (attempt {}/{})",
+            self.rejoin_attempts, self.config.auto_rejoin_attempts
+        );
+        self.socket =
+            create_matchbox_socket(self.room_url.clone(), replicon_channels, &self.config);
+        self.host_peer_id = None;
+        self.peer_id = None;
+        self.open_peers.clear();
+        self.join_sent_to = None;
+        self.awaiting_channels = false;
+        self.signaled_at = None;
+        self.barrier = TickBarrier::default();
+        self.deliveries = PendingDeliveries::default();
+        self.rejoin_token = Some(session_token);
+        self.auto_rejoining = true;
+        true
+    }
+
+    /// Replaces the socket with one for the next fallback room, returns `None` if there is
+    /// none left.
+    fn fail_over(
+        &mut self,
+        replicon_channels: &RepliconChannels,
+        kind: DisconnectKind,
+    ) -> Option<HostFailover> {
+        let room_url = self.fallback_urls.pop_front()?;
+        info!(
+            "giving up on {}, failing over to {}",
+            redact_room_url(&self.room_url),
+            redact_room_url(&room_url)
+        );
+        self.socket = create_matchbox_socket(room_url.clone(), replicon_channels, &self.config);
+        self.room_url = room_url.clone();
+        self.host_peer_id = None;
+        self.peer_id = None;
+        self.open_peers.clear();
+        self.join_sent_to = None;
+        self.awaiting_channels = false;
+        self.signaled_at = None;
+        self.barrier = TickBarrier::default();
+        self.deliveries = PendingDeliveries::default();
+        self.early_packets.clear();
+        self.session_token = None;
+        self.rejoin_token = None;
+        self.rejoin_attempts = 0;
+        self.auto_rejoining = false;
+        Some(HostFailover {
+            room_url,
+            remaining: self.fallback_urls.len(),
+            kind,
+        })
+    }
+
+    /// Reports the connection once the host introduced itself and every data channel to it
+    /// is open.
+    ///
+    /// Both sides open channels independently, so the introduction can arrive on the system
+    /// channel before the remaining channels are open here. Messages sent on those would
+    /// be lost.
+    fn finish_connecting(&mut self, replicon_client: &mut RepliconClient, commands: &mut Commands) {
+        let Some(host_peer_id) = self.host_peer_id else {
+            return;
+        };
+        if self.awaiting_channels && self.open_peers.contains(&host_peer_id) {
+            self.awaiting_channels = false;
+            self.signaled_at = None;
+            replicon_client.set_status(RepliconClientStatus::Connected);
+            for (channel_id, message) in self.early_packets.drain(..) {
+                replicon_client.insert_received(channel_id, message);
+            }
+            commands.send_event(ConnectionProgress::ChannelsOpen);
+            commands.send_event(ConnectionProgress::HandshakeComplete);
+            self.connect_signal.resolve(Ok(host_peer_id));
+            commands.trigger(ClientConnectedToHost {
+                host_peer: host_peer_id,
+            });
+        }
+    }
+
+    /// Answers the join request of the host, which admits the client in response.
+    fn send_join(&mut self, peer_id: PeerId) {
+        if self.rejoin_token.is_some() {
+            trace!("requesting rejoin from host {}", peer_id);
+        }
+        let packet = system_packet(&SystemChannelMessage::Join {
+            rejoin_token: self.rejoin_token,
+        });
+        self.socket
+            .channel_mut(SYSTEM_CHANNEL_ID)
+            .send(packet, peer_id);
+        self.join_sent_to = Some(peer_id);
+    }
+
+    /// Hands a message to replicon, or keeps it until the client is connected.
+    ///
+    /// After a rejoin the host sends the messages it kept for the client right away, while
+    /// replicon only accepts messages once the client is connected again.
+    fn deliver(&mut self, replicon_client: &mut RepliconClient, channel_id: usize, message: Bytes) {
+        if replicon_client.is_connected() {
+            replicon_client.insert_received(channel_id, message);
+        } else {
+            self.early_packets.push((channel_id, message));
+        }
+    }
+
+    /// Resolves [`Self::wait_connected`] with the disconnect and triggers
+    /// [`ClientConnectionFailed`] if the client was never connected.
+    fn fail_connecting(&mut self, commands: &mut Commands, kind: &DisconnectKind) {
+        let phase = if self.peer_id.is_none() {
+            ConnectionPhase::Signaling
+        } else if self.host_peer_id.is_none() {
+            ConnectionPhase::AwaitingHost
+        } else {
+            ConnectionPhase::OpeningChannels
+        };
+        if self.connect_signal.resolve(Err(kind.clone())) {
+            commands.trigger(ClientConnectionFailed {
+                phase,
+                error: kind.clone(),
+            });
+        }
     }
 
     pub fn disconnect(&mut self) {
+        self.close(DisconnectKind::LocalClosed);
+    }
+
+    fn close(&mut self, kind: DisconnectKind) {
         let Ok(channel) = self.socket.get_channel_mut(SYSTEM_CHANNEL_ID) else {
             return;
         };
@@ -195,9 +1236,106 @@ impl MatchboxClient {
             return;
         };
         trace!("sending disconnect message to host");
-        let mut buf = [0u8; 1];
-        let package = to_packet(&SystemChannelMessage::ClientDisconnects, &mut buf).into();
+        let message = match kind {
+            DisconnectKind::ProtocolMismatch => SystemChannelMessage::ProtocolMismatch,
+            _ => SystemChannelMessage::ClientDisconnects,
+        };
+        let package = system_packet(&message);
         channel.send(package, host_peer);
-        self.should_disconnect = true;
+        self.pending_disconnect = Some(kind);
+    }
+}
+
+/// Settings of a [`MatchboxClient`] that is created once the app runs, when all replicon
+/// channels are registered.
+///
+/// The client counterpart of [`DeferredMatchboxHost`](crate::DeferredMatchboxHost), replaced
+/// by the client in the first [`RepliconMatchboxClientPlugin::receive_schedule`] run.
+#[derive(Resource)]
+pub struct DeferredMatchboxClient {
+    room_url: String,
+    config: MatchboxConfig,
+}
+
+impl DeferredMatchboxClient {
+    pub fn new(room_url: impl Into<String>) -> Self {
+        Self::with_config(room_url, MatchboxConfig::default())
+    }
+
+    pub fn with_config(room_url: impl Into<String>, config: MatchboxConfig) -> Self {
+        Self {
+            room_url: room_url.into(),
+            config,
+        }
+    }
+}
+
+/// Command that creates a [`MatchboxClient`] with the world's current [`RepliconChannels`],
+/// the client counterpart of [`StartHost`](crate::StartHost).
+pub struct StartClient {
+    pub room_url: String,
+    pub config: MatchboxConfig,
+}
+
+impl StartClient {
+    pub fn new(room_url: impl Into<String>) -> Self {
+        Self {
+            room_url: room_url.into(),
+            config: MatchboxConfig::default(),
+        }
+    }
+}
+
+impl Command<Result> for StartClient {
+    fn apply(self, world: &mut World) -> Result {
+        let channels = world.resource::<RepliconChannels>();
+        let client = MatchboxClient::with_config(self.room_url, channels, self.config)?;
+        world.insert_resource(client);
+        Ok(())
+    }
+}
+
+/// Prints the room URL redacted.
+impl std::fmt::Debug for DeferredMatchboxClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DeferredMatchboxClient")
+            .field("room_url", &redact_room_url(&self.room_url))
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+/// Summarizes the socket state with the room URL and session token redacted, since the
+/// token lets anyone rejoin as this client.
+impl std::fmt::Debug for MatchboxClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MatchboxClient")
+            .field("peer_id", &self.peer_id)
+            .field("host_peer_id", &self.host_peer_id)
+            .field("room_url", &redact_room_url(&self.room_url))
+            .field("pending_disconnect", &self.pending_disconnect)
+            .field("has_session_token", &self.session_token.is_some())
+            .field("rejoin_attempts", &self.rejoin_attempts)
+            .field("auto_rejoining", &self.auto_rejoining)
+            .field("fallbacks", &self.fallback_urls.len())
+            .field("open_peers", &self.open_peers)
+            .field("join_sent_to", &self.join_sent_to)
+            .field("awaiting_channels", &self.awaiting_channels)
+            .field("signaled_at", &self.signaled_at)
+            .field("config", &self.config)
+            .field("layout", &self.layout)
+            .field("held_packets", &self.held_packets.len())
+            .field("muted_messages", &self.mutes.held_messages())
+            .field("capturing", &self.capture.is_some())
+            .field("stats", &self.stats)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for MatchboxClient {
+    fn drop(&mut self) {
+        // removed without a disconnect, e.g. by game code
+        self.connect_signal
+            .resolve(Err(DisconnectKind::LocalClosed));
     }
 }
@@ -0,0 +1,126 @@
+use bevy::prelude::*;
+use bevy_egui::{EguiContextPass, EguiContexts, egui};
+use std::time::Duration;
+
+#[cfg(feature = "client")]
+use crate::client::MatchboxClient;
+#[cfg(feature = "server")]
+use crate::server::{MatchboxClientConnection, MatchboxHost, PeerDisconnected};
+#[cfg(feature = "server")]
+use crate::shaping::SendQueue;
+#[cfg(feature = "server")]
+use crate::stats::ConnectionStats;
+
+/// Draws a window with per-connection transport diagnostics.
+///
+/// Requires `bevy_egui::EguiPlugin`. Shows a row per client on the host and the connection
+/// to the host on the client.
+pub struct MatchboxDebugOverlayPlugin;
+
+impl Plugin for MatchboxDebugOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        #[cfg(feature = "server")]
+        app.add_systems(
+            EguiContextPass,
+            draw_host_overlay.run_if(resource_exists::<MatchboxHost>),
+        );
+        #[cfg(feature = "client")]
+        app.add_systems(
+            EguiContextPass,
+            draw_client_overlay.run_if(resource_exists::<MatchboxClient>),
+        );
+    }
+}
+
+#[cfg(feature = "server")]
+fn draw_host_overlay(
+    mut contexts: EguiContexts,
+    clients: Query<(
+        Entity,
+        &MatchboxClientConnection,
+        &ConnectionStats,
+        &SendQueue,
+        Has<PeerDisconnected>,
+    )>,
+) {
+    egui::Window::new("Matchbox host").show(contexts.ctx_mut(), |ui| {
+        egui::Grid::new("matchbox_clients")
+            .striped(true)
+            .show(ui, |ui| {
+                for header in [
+                    "client", "peer", "phase", "rtt", "loss", "sent", "received", "queued",
+                ] {
+                    ui.strong(header);
+                }
+                ui.end_row();
+
+                for (client_entity, connection, stats, queue, disconnected) in &clients {
+                    ui.label(client_entity.to_string());
+                    ui.label(connection.peer_id.to_string());
+                    ui.label(if disconnected {
+                        "awaiting rejoin"
+                    } else {
+                        "connected"
+                    });
+                    ui.label(format_rtt(stats.rtt));
+                    ui.label(format_loss(stats.loss()));
+                    ui.label(format_rate(stats.sent_bytes_per_second));
+                    ui.label(format_rate(stats.received_bytes_per_second));
+                    ui.label(queue.len().to_string());
+                    ui.end_row();
+                }
+            });
+    });
+}
+
+#[cfg(feature = "client")]
+fn draw_client_overlay(mut contexts: EguiContexts, client: Res<MatchboxClient>) {
+    egui::Window::new("Matchbox client").show(contexts.ctx_mut(), |ui| {
+        egui::Grid::new("matchbox_host").show(ui, |ui| {
+            ui.strong("host");
+            match client.host_peer_id {
+                Some(host_peer_id) => ui.label(host_peer_id.to_string()),
+                None => ui.label("-"),
+            };
+            ui.end_row();
+
+            ui.strong("phase");
+            ui.label(if client.is_connected() {
+                "connected"
+            } else {
+                "connecting"
+            });
+            ui.end_row();
+
+            ui.strong("rtt");
+            ui.label(format_rtt(client.stats().rtt));
+            ui.end_row();
+
+            ui.strong("loss");
+            ui.label(format_loss(client.stats().loss()));
+            ui.end_row();
+
+            ui.strong("sent");
+            ui.label(format_rate(client.stats().sent_bytes_per_second));
+            ui.end_row();
+
+            ui.strong("received");
+            ui.label(format_rate(client.stats().received_bytes_per_second));
+            ui.end_row();
+        });
+    });
+}
+
+fn format_rate(bytes_per_second: f64) -> String {
+    format!("{:.1} KB/s", bytes_per_second / 1000.0)
+}
+
+fn format_rtt(rtt: Option<Duration>) -> String {
+    rtt.map_or("-".into(), |rtt| {
+        format!("{:.0} ms", rtt.as_secs_f64() * 1000.0)
+    })
+}
+
+fn format_loss(loss: Option<f64>) -> String {
+    loss.map_or("-".into(), |loss| format!("{:.1} %", loss * 100.0))
+}
@@ -0,0 +1,105 @@
+use crate::shared::strip_marker;
+use bevy::prelude::*;
+use bevy_matchbox::matchbox_socket::{Packet, PeerId};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// First byte of user messages whose receiver acknowledges them, followed by the delivery
+/// ID. Other user messages start with the zero marker of `add_marker`.
+const ACKED_MARKER: u8 = 1;
+
+/// Bytes in front of the payload of an acknowledged user message.
+const ACKED_HEADER: usize = 1 + size_of::<u64>();
+
+/// Identifies a user message sent with an acknowledgment request, see
+/// `MatchboxHost::send_to_acked` and `MatchboxClient::send_to_host_acked`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DeliveryId(pub u64);
+
+/// Triggered on the sender once the remote peer received a message sent with an
+/// acknowledgment request, observe it with [`App::add_observer`].
+///
+/// Never triggered if the peer disconnects before receiving the message.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct MessageDelivered {
+    pub id: DeliveryId,
+    pub peer_id: PeerId,
+}
+
+/// Messages awaiting their acknowledgment, by the peer they were sent to.
+#[derive(Default)]
+pub(crate) struct PendingDeliveries {
+    next_id: u64,
+    pending: HashMap<DeliveryId, PeerId>,
+}
+
+impl PendingDeliveries {
+    /// Wraps `payload` into a packet the receiver acknowledges.
+    pub(crate) fn packet(&mut self, peer_id: PeerId, payload: &[u8]) -> (DeliveryId, Packet) {
+        let id = DeliveryId(self.next_id);
+        self.next_id += 1;
+        self.pending.insert(id, peer_id);
+        let mut packet = Vec::with_capacity(ACKED_HEADER + payload.len());
+        packet.push(ACKED_MARKER);
+        packet.extend_from_slice(&id.0.to_le_bytes());
+        packet.extend_from_slice(payload);
+        (id, packet.into())
+    }
+
+    /// Returns the delivery if `id` was sent to `peer_id` and not acknowledged before.
+    pub(crate) fn confirm(&mut self, peer_id: PeerId, id: DeliveryId) -> Option<MessageDelivered> {
+        if self.pending.get(&id) != Some(&peer_id) {
+            return None;
+        }
+        self.pending.remove(&id);
+        Some(MessageDelivered { id, peer_id })
+    }
+
+    /// Drops the deliveries to a peer that is gone.
+    #[cfg(feature = "server")]
+    pub(crate) fn forget(&mut self, peer_id: PeerId) {
+        self.pending.retain(|_, &mut pending| pending != peer_id);
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+/// Payload of a received user message, with the delivery ID if the sender requested an
+/// acknowledgment.
+pub(crate) fn read_user_packet(packet: &[u8]) -> (Bytes, Option<DeliveryId>) {
+    match packet.split_first_chunk::<ACKED_HEADER>() {
+        Some(([ACKED_MARKER, id @ ..], payload)) => (
+            Bytes::copy_from_slice(payload),
+            Some(DeliveryId(u64::from_le_bytes(*id))),
+        ),
+        _ => (strip_marker(packet), None),
+    }
+}
+
+#[test]
+fn test_pending_deliveries() {
+    let mut deliveries = PendingDeliveries::default();
+    let peer_id = PeerId(uuid::Uuid::from_u128(1));
+    let other_peer_id = PeerId(uuid::Uuid::from_u128(2));
+
+    let (id, packet) = deliveries.packet(peer_id, b"critical");
+    let (payload, received_id) = read_user_packet(&packet);
+    assert_eq!(&payload[..], b"critical");
+    assert_eq!(received_id, Some(id));
+    assert_eq!(
+        read_user_packet(&[0, 1, 2]),
+        (Bytes::from_static(&[1, 2]), None),
+        "regular messages aren't acknowledged"
+    );
+
+    assert!(deliveries.confirm(other_peer_id, id).is_none());
+    assert!(deliveries.confirm(peer_id, id).is_some());
+    assert!(
+        deliveries.confirm(peer_id, id).is_none(),
+        "acknowledged only once"
+    );
+    assert_eq!(deliveries.len(), 0);
+}
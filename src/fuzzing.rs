@@ -0,0 +1,49 @@
+//! Entry points for the fuzz targets in `fuzz/`, not covered by semver.
+//!
+//! Each function runs inbound data through the same parsing and re-framing as the host and
+//! client systems, so malformed data that would panic them panics here as well.
+
+use crate::chat::{ChatMessage, chat_packet};
+use crate::relay::RelayEnvelope;
+use crate::shared::*;
+use crate::transfer::Transfers;
+use crate::voice::VoicePacket;
+use bevy_matchbox::matchbox_socket::PeerId;
+
+pub fn system_message(data: &[u8]) {
+    let _ = from_packet::<SystemChannelMessage>(data);
+}
+
+pub fn replicon_message(data: &[u8]) {
+    strip_marker(data);
+}
+
+pub fn relay_envelope(data: &[u8]) {
+    if let Ok(envelope) = from_packet::<RelayEnvelope>(data) {
+        envelope.to_packet();
+    }
+}
+
+pub fn voice_packet(data: &[u8]) {
+    if let Ok(mut voice_packet) = from_packet::<VoicePacket>(data) {
+        voice_packet.header.sender = PeerId(Default::default());
+        voice_packet.to_packet();
+    }
+}
+
+pub fn chat_message(data: &[u8]) {
+    if let Ok(message) = from_packet::<ChatMessage>(data) {
+        chat_packet(&message);
+    }
+}
+
+/// Feeds a sequence of packets from a single peer into transfer reassembly.
+pub fn transfer_packets<'a>(packets: impl IntoIterator<Item = &'a [u8]>) {
+    let peer_id = PeerId(Default::default());
+    let max_incoming = MatchboxConfig::default().max_inbound_transfer_bytes;
+    let mut transfers = Transfers::default();
+    for packet in packets {
+        transfers.receive(peer_id, packet, max_incoming);
+    }
+    transfers.retain_peers(|_| false, |_| ());
+}
@@ -0,0 +1,222 @@
+use crate::room_url::RoomUrl;
+use crate::shared::from_packet;
+use bevy::prelude::*;
+use bevy_replicon::postcard;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::Duration;
+
+/// UDP port hosts announce themselves on.
+pub const LAN_DISCOVERY_PORT: u16 = 3537;
+
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Hosts that weren't announced for this long are removed from [`LanDiscovery`].
+const HOST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Identifies announcements of this crate among other broadcasts on the port.
+const MAGIC: u32 = 0x4d42_584c;
+
+const MAX_ANNOUNCEMENT_SIZE: usize = 512;
+
+/// Finds hosts on the local network without an internet connection.
+///
+/// Hosts run their own signaling server, e.g. with `MatchboxHost::with_embedded_signaling`
+/// bound to `0.0.0.0`, and broadcast its port with a [`LanAnnouncer`]. Clients collect the
+/// announcements in [`LanDiscovery`] and connect to the advertised room URL. Peers on the
+/// same network connect through their host candidates, so no STUN or TURN server is needed.
+pub struct LanDiscoveryPlugin;
+
+impl Plugin for LanDiscoveryPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                announce_host.run_if(resource_exists::<LanAnnouncer>),
+                discover_hosts.run_if(resource_exists::<LanDiscovery>),
+            ),
+        );
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Announcement {
+    magic: u32,
+    name: String,
+    signaling_port: u16,
+    room: String,
+}
+
+/// Broadcasts the host's signaling server on [`LAN_DISCOVERY_PORT`] every second.
+#[derive(Resource)]
+pub struct LanAnnouncer {
+    socket: UdpSocket,
+    packet: Box<[u8]>,
+    last_sent: Option<Duration>,
+}
+
+impl LanAnnouncer {
+    /// Announces `room` on the signaling server listening at `signaling_port` under a name
+    /// shown to players.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidInput`] if the name and room don't fit into an
+    /// announcement or the room doesn't form a valid room URL.
+    pub fn new(
+        name: impl Into<String>,
+        signaling_port: u16,
+        room: impl Into<String>,
+    ) -> io::Result<Self> {
+        let announcement = Announcement {
+            magic: MAGIC,
+            name: name.into(),
+            signaling_port,
+            room: room.into(),
+        };
+        let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, signaling_port));
+        if let Err(e) = RoomUrl::parse(format!("ws://{addr}/{}", announcement.room)) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, e));
+        }
+        let mut packet = vec![0; MAX_ANNOUNCEMENT_SIZE];
+        let len = postcard::to_slice(&announcement, &mut packet)
+            .map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "name and room don't fit into an announcement",
+                )
+            })?
+            .len();
+        packet.truncate(len);
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        socket.set_broadcast(true)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            packet: packet.into(),
+            last_sent: None,
+        })
+    }
+}
+
+/// A host found by [`LanDiscovery`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredHost {
+    pub name: String,
+    /// Room URL to pass to `MatchboxClient::new`.
+    ///
+    /// Announcements whose room doesn't form a valid URL are ignored.
+    pub room_url: RoomUrl,
+    /// [`Time<Real>`] elapsed time of the last announcement.
+    pub last_seen: Duration,
+}
+
+/// Hosts announced on the local network, see [`LanDiscoveryPlugin`].
+///
+/// Binds [`LAN_DISCOVERY_PORT`], so only one discovery per machine can run at a time.
+#[derive(Resource)]
+pub struct LanDiscovery {
+    socket: UdpSocket,
+    hosts: HashMap<SocketAddr, DiscoveredHost>,
+}
+
+impl LanDiscovery {
+    pub fn new() -> io::Result<Self> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, LAN_DISCOVERY_PORT))?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            hosts: HashMap::new(),
+        })
+    }
+
+    pub fn hosts(&self) -> impl Iterator<Item = &DiscoveredHost> {
+        self.hosts.values()
+    }
+}
+
+fn announce_host(mut announcer: ResMut<LanAnnouncer>, time: Res<Time<Real>>) {
+    if announcer
+        .last_sent
+        .is_some_and(|last_sent| time.elapsed() - last_sent < ANNOUNCE_INTERVAL)
+    {
+        return;
+    }
+    announcer.last_sent = Some(time.elapsed());
+    let target = (Ipv4Addr::BROADCAST, LAN_DISCOVERY_PORT);
+    if let Err(e) = announcer.socket.send_to(&announcer.packet, target) {
+        warn!("unable to announce host on the local network: {e}");
+    }
+}
+
+fn discover_hosts(mut discovery: ResMut<LanDiscovery>, time: Res<Time<Real>>) {
+    let mut buf = [0; MAX_ANNOUNCEMENT_SIZE];
+    loop {
+        let (len, from) = match discovery.socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+            Err(e) => {
+                warn!("unable to receive host announcements: {e}");
+                break;
+            }
+        };
+        let Some((name, room_url)) = parse_announcement(&buf[..len], from.ip()) else {
+            continue;
+        };
+        discovery.hosts.insert(
+            from,
+            DiscoveredHost {
+                name,
+                room_url,
+                last_seen: time.elapsed(),
+            },
+        );
+    }
+    discovery
+        .hosts
+        .retain(|_, host| time.elapsed() - host.last_seen < HOST_TIMEOUT);
+}
+
+/// Returns the host name and room URL of an announcement sent from `ip`.
+fn parse_announcement(data: &[u8], ip: IpAddr) -> Option<(String, RoomUrl)> {
+    let announcement: Announcement = from_packet(data).ok()?;
+    if announcement.magic != MAGIC {
+        return None;
+    }
+    let addr = SocketAddr::new(ip, announcement.signaling_port);
+    let room_url = RoomUrl::parse(format!("ws://{addr}/{}", announcement.room)).ok()?;
+    Some((announcement.name, room_url))
+}
+
+#[test]
+fn test_announcement() {
+    let announcer = LanAnnouncer::new("Living room", 3536, "game").unwrap();
+    let ip = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20));
+    assert_eq!(
+        parse_announcement(&announcer.packet, ip),
+        Some((
+            "Living room".to_string(),
+            RoomUrl::parse("ws://192.168.1.20:3536/game").unwrap()
+        ))
+    );
+    assert_eq!(parse_announcement(b"unrelated broadcast", ip), None);
+
+    let forged = Announcement {
+        magic: MAGIC,
+        name: "Living room".to_string(),
+        signaling_port: 3536,
+        room: "game#fragment".to_string(),
+    };
+    let mut buf = [0; MAX_ANNOUNCEMENT_SIZE];
+    let packet = postcard::to_slice(&forged, &mut buf).unwrap();
+    assert_eq!(parse_announcement(packet, ip), None);
+}
+
+#[test]
+fn test_invalid_announcer() {
+    let name = "x".repeat(MAX_ANNOUNCEMENT_SIZE);
+    let e = LanAnnouncer::new(name, 3536, "game").err().unwrap();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    let e = LanAnnouncer::new("Living room", 3536, "").err().unwrap();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+}
@@ -1,14 +1,149 @@
+#[cfg(any(feature = "client", feature = "server"))]
+mod barrier;
+#[cfg(feature = "bot")]
+mod bot;
+#[cfg(any(feature = "client", feature = "server"))]
+mod capture;
+#[cfg(any(feature = "client", feature = "server"))]
+mod chat;
 #[cfg(feature = "client")]
 mod client;
+#[cfg(all(feature = "debug_overlay", any(feature = "client", feature = "server")))]
+mod debug_overlay;
+#[cfg(any(feature = "client", feature = "server"))]
+mod delivery;
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub mod fuzzing;
+#[cfg(all(feature = "lan", any(feature = "client", feature = "server")))]
+mod lan;
+#[cfg(any(feature = "client", feature = "server"))]
+mod loopback;
+#[cfg(any(feature = "client", feature = "server"))]
+mod mute;
+#[cfg(all(feature = "net_debug", any(feature = "client", feature = "server")))]
+mod net_debug;
+#[cfg(any(feature = "client", feature = "server"))]
+mod quick;
+#[cfg(any(feature = "client", feature = "server"))]
+mod relay;
+#[cfg(any(feature = "client", feature = "server"))]
+pub mod renet_compat;
+#[cfg(any(feature = "client", feature = "server"))]
+mod room_url;
+#[cfg(any(feature = "client", feature = "server"))]
+mod send_failure;
 #[cfg(feature = "server")]
 mod server;
 #[cfg(any(feature = "client", feature = "server"))]
+mod shaping;
+#[cfg(any(feature = "client", feature = "server"))]
 pub mod shared;
+#[cfg(feature = "signaling")]
+mod signaling;
+#[cfg(any(feature = "client", feature = "server"))]
+mod stats;
+#[cfg(feature = "server")]
+mod stats_log;
+#[cfg(feature = "test_utils")]
+pub mod test_utils;
+#[cfg(any(feature = "client", feature = "server"))]
+mod throttle;
+#[cfg(any(feature = "client", feature = "server"))]
+mod transfer;
+#[cfg(any(feature = "client", feature = "server"))]
+mod user_channel;
+#[cfg(any(feature = "client", feature = "server"))]
+mod voice;
+#[cfg(feature = "client")]
+mod wait_connected;
+#[cfg(any(feature = "client", feature = "server"))]
+mod watchdog;
 
+#[cfg(feature = "bot")]
+pub use bot::{BotPlugin, BotSwarm, BotTraffic};
+#[cfg(any(feature = "client", feature = "server"))]
+pub use capture::{CaptureDirection, CaptureReader, CaptureRecord};
+#[cfg(any(feature = "client", feature = "server"))]
+pub use chat::{ChatFilter, ChatLimits, ChatMessage};
 #[cfg(feature = "client")]
 pub use client::*;
+#[cfg(all(feature = "debug_overlay", any(feature = "client", feature = "server")))]
+pub use debug_overlay::MatchboxDebugOverlayPlugin;
+#[cfg(any(feature = "client", feature = "server"))]
+pub use delivery::{DeliveryId, MessageDelivered};
+#[cfg(all(feature = "lan", any(feature = "client", feature = "server")))]
+pub use lan::{DiscoveredHost, LAN_DISCOVERY_PORT, LanAnnouncer, LanDiscovery, LanDiscoveryPlugin};
+#[cfg(any(feature = "client", feature = "server"))]
+pub use loopback::{LoopbackClient, LoopbackHost};
+#[cfg(any(feature = "client", feature = "server"))]
+pub use mute::MutedChannel;
+#[cfg(all(feature = "net_debug", any(feature = "client", feature = "server")))]
+pub use net_debug::{MessageReceived, MessageSent};
+#[cfg(any(feature = "client", feature = "server"))]
+pub use quick::RepliconMatchboxQuickPlugin;
+#[cfg(any(feature = "client", feature = "server"))]
+pub use room_url::{IntoRoomUrl, RoomUrl, RoomUrlError};
+#[cfg(any(feature = "client", feature = "server"))]
+pub use send_failure::SendFailurePolicy;
 #[cfg(feature = "server")]
 pub use server::*;
 
 #[cfg(any(feature = "client", feature = "server"))]
-pub use shared::RepliconMatchboxPlugins;
+pub use relay::{DirectMessage, RelayFilter, RelayedMessage};
+#[cfg(any(feature = "client", feature = "server"))]
+pub use shaping::{BandwidthLimit, TokenBucket};
+#[cfg(any(feature = "client", feature = "server"))]
+pub use shared::{
+    ConfigError, DisconnectKind, HostLost, MatchboxConfig, RepliconMatchboxPlugins, SignalingLost,
+};
+#[cfg(feature = "signaling")]
+pub use signaling::{EmbeddedSignalingServer, SignalingMetrics, SignalingServerPlugin};
+#[cfg(any(feature = "client", feature = "server"))]
+pub use stats::{
+    BandwidthReport, BandwidthReports, ChannelTraffic, ChannelUsage, ConnectionStats, HostStats,
+    MessageKind, SizeHistogram,
+};
+#[cfg(any(feature = "client", feature = "server"))]
+pub use throttle::ReconnectThrottle;
+#[cfg(any(feature = "client", feature = "server"))]
+pub use transfer::{
+    TransferCancelled, TransferCompleted, TransferCorrupted, TransferId, TransferProgress,
+};
+#[cfg(any(feature = "client", feature = "server"))]
+pub use user_channel::{Fanout, UserChannelHandle, UserMessage};
+#[cfg(any(feature = "client", feature = "server"))]
+pub use voice::{JitterBuffer, VoiceFrame, VoiceFrameHeader};
+#[cfg(feature = "client")]
+pub use wait_connected::WaitConnected;
+#[cfg(any(feature = "client", feature = "server"))]
+pub use watchdog::UnresponsiveTimeout;
+
+/// Commonly used types, import with `use bevy_replicon_matchbox::prelude::*;`.
+pub mod prelude {
+    #[cfg(any(feature = "client", feature = "server"))]
+    pub use crate::{
+        BandwidthLimit, BandwidthReports, ChatLimits, ChatMessage, ConnectionStats, DirectMessage,
+        DisconnectKind, HostLost, HostStats, IntoRoomUrl, MatchboxConfig, MessageDelivered,
+        MutedChannel, ReconnectThrottle, RelayedMessage, RepliconMatchboxPlugins,
+        RepliconMatchboxQuickPlugin, RoomUrl, SignalingLost, TokenBucket, TransferCancelled,
+        TransferCompleted, TransferCorrupted, TransferProgress, UnresponsiveTimeout, UserMessage,
+        VoiceFrame,
+    };
+    #[cfg(feature = "client")]
+    pub use crate::{
+        ClientConnectedToHost, ClientConnectionFailed, ConnectionPhase, ConnectionProgress,
+        DeferredMatchboxClient, DisconnectedFromHost, HostAssigned, HostFailover, MatchboxClient,
+        RepliconMatchboxClientPlugin, StartClient, WaitConnected, matchbox_client_connected,
+    };
+    #[cfg(feature = "server")]
+    pub use crate::{
+        ClientDisconnected, DeferredMatchboxHost, MatchboxClientConnection, MatchboxHost,
+        PeerDisconnected, PeerUnresponsive, RepliconMatchboxServerPlugin, StartHost,
+        SuggestedSendInterval,
+    };
+    #[cfg(feature = "signaling")]
+    pub use crate::{EmbeddedSignalingServer, SignalingServerPlugin};
+    #[cfg(any(feature = "client", feature = "server"))]
+    pub use bevy_matchbox::prelude::PeerId;
+}
@@ -0,0 +1,156 @@
+use bevy::prelude::*;
+use bevy_replicon::prelude::*;
+use bytes::Bytes;
+use std::sync::{Arc, Mutex};
+
+#[cfg(feature = "server")]
+use crate::server::ServerPluginSettings;
+
+/// Messages between a host and a client in the same process.
+#[derive(Default)]
+pub(crate) struct LoopbackQueues {
+    pub(crate) to_host: Vec<(usize, Bytes)>,
+    pub(crate) to_client: Vec<(usize, Bytes)>,
+    closed: bool,
+}
+
+pub(crate) type SharedQueues = Arc<Mutex<LoopbackQueues>>;
+
+/// Host side of in-memory connections, for single-player and listen servers that also play.
+///
+/// Each client returned by [`Self::connect`] becomes a regular replicon client entity and
+/// goes through the same replication as remote clients, but without WebRTC or signaling.
+/// Works alone for single-player and next to a [`MatchboxHost`](crate::MatchboxHost).
+/// Since a [`World`] holds only one replicon client, the local player needs its own
+/// [`App`] with the [`LoopbackClient`] as resource.
+#[derive(Resource, Default)]
+pub struct LoopbackHost {
+    pending: Vec<SharedQueues>,
+}
+
+impl LoopbackHost {
+    /// Creates a client connected to this host, the client entity is spawned on the next
+    /// update.
+    pub fn connect(&mut self) -> LoopbackClient {
+        let queues = SharedQueues::default();
+        self.pending.push(queues.clone());
+        LoopbackClient(queues)
+    }
+}
+
+/// Client side of an in-memory connection created by [`LoopbackHost::connect`].
+///
+/// Removing the resource disconnects the client.
+#[derive(Resource)]
+pub struct LoopbackClient(pub(crate) SharedQueues);
+
+impl Drop for LoopbackClient {
+    fn drop(&mut self) {
+        self.0.lock().unwrap().closed = true;
+    }
+}
+
+/// Connection of a client entity created by a [`LoopbackHost`].
+#[cfg(feature = "server")]
+#[derive(Component)]
+pub(crate) struct LoopbackConnection(pub(crate) SharedQueues);
+
+#[cfg(feature = "server")]
+pub(crate) fn set_loopback_running(mut replicon_server: ResMut<RepliconServer>) {
+    replicon_server.set_running(true);
+}
+
+#[cfg(feature = "server")]
+pub(crate) fn receive_loopback_on_host(
+    mut commands: Commands,
+    mut host: ResMut<LoopbackHost>,
+    mut replicon_server: ResMut<RepliconServer>,
+    mut disconnect_requests: EventReader<DisconnectRequest>,
+    connections: Query<(Entity, &LoopbackConnection)>,
+    settings: Res<ServerPluginSettings>,
+) {
+    for queues in host.pending.drain(..) {
+        let client_entity = commands
+            .spawn((
+                ConnectedClient {
+                    max_size: settings.max_size,
+                },
+                LoopbackConnection(queues),
+            ))
+            .id();
+        trace!("new loopback client: {client_entity}");
+    }
+
+    for request in disconnect_requests.read() {
+        if let Ok((_, connection)) = connections.get(request.client_entity) {
+            connection.0.lock().unwrap().closed = true;
+        }
+    }
+
+    for (client_entity, connection) in &connections {
+        let mut queues = connection.0.lock().unwrap();
+        for (channel_id, message) in queues.to_host.drain(..) {
+            replicon_server.insert_received(client_entity, channel_id, message);
+        }
+        if queues.closed {
+            trace!("loopback client disconnected: {client_entity}");
+            commands.entity(client_entity).despawn();
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+pub(crate) fn send_loopback_on_host(
+    mut replicon_server: ResMut<RepliconServer>,
+    connections: Query<&LoopbackConnection>,
+) {
+    // the socket transport drains the rest, so messages of other clients are put back in order
+    let sent: Vec<_> = replicon_server.drain_sent().collect();
+    for (client_entity, channel_id, message) in sent {
+        match connections.get(client_entity) {
+            Ok(connection) => connection
+                .0
+                .lock()
+                .unwrap()
+                .to_client
+                .push((channel_id, message)),
+            Err(_) => replicon_server.send(client_entity, channel_id, message),
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+pub(crate) fn receive_loopback_on_client(
+    client: Res<LoopbackClient>,
+    mut replicon_client: ResMut<RepliconClient>,
+) {
+    let mut queues = client.0.lock().unwrap();
+    if queues.closed {
+        if !replicon_client.is_disconnected() {
+            replicon_client.set_status(RepliconClientStatus::Disconnected);
+        }
+        return;
+    }
+    if !replicon_client.is_connected() {
+        replicon_client.set_status(RepliconClientStatus::Connected);
+    }
+    for (channel_id, message) in queues.to_client.drain(..) {
+        replicon_client.insert_received(channel_id, message);
+    }
+}
+
+#[cfg(feature = "client")]
+pub(crate) fn send_loopback_on_client(
+    client: Res<LoopbackClient>,
+    mut replicon_client: ResMut<RepliconClient>,
+) {
+    let mut queues = client.0.lock().unwrap();
+    for (channel_id, message) in replicon_client.drain_sent() {
+        queues.to_host.push((channel_id, message));
+    }
+}
+
+#[cfg(feature = "client")]
+pub(crate) fn set_loopback_disconnected(mut replicon_client: ResMut<RepliconClient>) {
+    replicon_client.set_status(RepliconClientStatus::Disconnected);
+}
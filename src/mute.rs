@@ -0,0 +1,116 @@
+use bytes::Bytes;
+use std::collections::HashSet;
+use std::hash::Hash;
+
+/// Replicon channel to mute, see `MatchboxHost::mute` and `MatchboxClient::mute`.
+///
+/// Channel IDs follow the replicon registry: on the host outbound channels are server
+/// channels and inbound channels are client channels, on the client the other way around.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MutedChannel {
+    /// Holds messages sent on the channel and sends them in order once it's unmuted.
+    Outbound(usize),
+    /// Discards messages received on the channel while it's muted.
+    Inbound(usize),
+}
+
+/// Muted replicon channels, globally and per `K`, with the messages held back on them.
+///
+/// The host keys peers by client entity, the client has only the host and uses `()`.
+pub(crate) struct ChannelMutes<K> {
+    channels: HashSet<MutedChannel>,
+    peers: HashSet<(K, MutedChannel)>,
+    held: Vec<(K, usize, Bytes)>,
+}
+
+impl<K> Default for ChannelMutes<K> {
+    fn default() -> Self {
+        Self {
+            channels: HashSet::new(),
+            peers: HashSet::new(),
+            held: Vec::new(),
+        }
+    }
+}
+
+impl<K: Copy + Eq + Hash> ChannelMutes<K> {
+    pub(crate) fn mute(&mut self, peer: Option<K>, channel: MutedChannel) {
+        match peer {
+            Some(peer) => self.peers.insert((peer, channel)),
+            None => self.channels.insert(channel),
+        };
+    }
+
+    pub(crate) fn unmute(&mut self, peer: Option<K>, channel: MutedChannel) {
+        match peer {
+            Some(peer) => self.peers.remove(&(peer, channel)),
+            None => self.channels.remove(&channel),
+        };
+    }
+
+    /// Whether the channel is muted for `peer`, globally or on its own.
+    fn is_muted(&self, peer: K, channel: MutedChannel) -> bool {
+        self.channels.contains(&channel) || self.peers.contains(&(peer, channel))
+    }
+
+    pub(crate) fn discards(&self, peer: K, channel_id: usize) -> bool {
+        self.is_muted(peer, MutedChannel::Inbound(channel_id))
+    }
+
+    /// Holds `message` back if the channel is muted for `peer`, otherwise returns it.
+    pub(crate) fn hold(&mut self, peer: K, channel_id: usize, message: Bytes) -> Option<Bytes> {
+        if self.is_muted(peer, MutedChannel::Outbound(channel_id)) {
+            self.held.push((peer, channel_id, message));
+            None
+        } else {
+            Some(message)
+        }
+    }
+
+    /// Takes the held messages of channels that were unmuted, in the order they were sent.
+    pub(crate) fn release(&mut self) -> Vec<(K, usize, Bytes)> {
+        if self.held.is_empty() {
+            return Vec::new();
+        }
+        let (held, released): (Vec<_>, Vec<_>) = std::mem::take(&mut self.held)
+            .into_iter()
+            .partition(|&(peer, channel_id, _)| {
+                self.is_muted(peer, MutedChannel::Outbound(channel_id))
+            });
+        self.held = held;
+        released
+    }
+
+    /// Drops the mutes and held messages of a peer that is gone.
+    #[cfg(feature = "server")]
+    pub(crate) fn forget(&mut self, peer: K) {
+        self.peers.retain(|&(muted, _)| muted != peer);
+        self.held.retain(|&(held, ..)| held != peer);
+    }
+
+    pub(crate) fn held_messages(&self) -> usize {
+        self.held.len()
+    }
+}
+
+#[test]
+fn test_channel_mutes() {
+    let mut mutes = ChannelMutes::<u8>::default();
+    let message = Bytes::from_static(b"message");
+    assert!(mutes.hold(1, 0, message.clone()).is_some());
+
+    mutes.mute(None, MutedChannel::Outbound(0));
+    mutes.mute(Some(1), MutedChannel::Outbound(0));
+    mutes.mute(Some(1), MutedChannel::Inbound(0));
+    assert!(mutes.discards(1, 0));
+    assert!(!mutes.discards(2, 0), "other peers keep inbound traffic");
+    assert!(mutes.hold(1, 0, message.clone()).is_none());
+    assert!(mutes.hold(2, 0, message.clone()).is_none());
+    assert!(mutes.hold(2, 1, message).is_some());
+
+    mutes.unmute(None, MutedChannel::Outbound(0));
+    let released = mutes.release();
+    assert_eq!(released.len(), 1, "peer 1 is still muted on its own");
+    assert_eq!(released[0].0, 2);
+    assert_eq!(mutes.held_messages(), 1);
+}
@@ -0,0 +1,65 @@
+use bevy::diagnostic::FrameCount;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy_matchbox::matchbox_socket::PeerId;
+
+/// A replicon message was handed to the transport.
+///
+/// Emitted before bandwidth shaping, so the message may go out on a later frame.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct MessageSent {
+    pub peer_id: PeerId,
+    /// Replicon channel ID.
+    pub channel_id: usize,
+    /// Size in bytes, without the transport's marker byte.
+    pub size: usize,
+    /// [`FrameCount`] at the time of sending, zero without the `FrameCountPlugin`.
+    pub frame: u32,
+}
+
+/// A replicon message was received from the socket.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct MessageReceived {
+    pub peer_id: PeerId,
+    /// Replicon channel ID.
+    pub channel_id: usize,
+    /// Size in bytes, without the transport's marker byte.
+    pub size: usize,
+    /// [`FrameCount`] at the time of receiving, zero without the `FrameCountPlugin`.
+    pub frame: u32,
+}
+
+#[derive(SystemParam)]
+pub(crate) struct NetDebugWriter<'w> {
+    sent: EventWriter<'w, MessageSent>,
+    received: EventWriter<'w, MessageReceived>,
+    frame_count: Option<Res<'w, FrameCount>>,
+}
+
+impl NetDebugWriter<'_> {
+    fn frame(&self) -> u32 {
+        self.frame_count
+            .as_ref()
+            .map_or(0, |frame_count| frame_count.0)
+    }
+
+    pub(crate) fn sent(&mut self, peer_id: PeerId, channel_id: usize, size: usize) {
+        let frame = self.frame();
+        self.sent.write(MessageSent {
+            peer_id,
+            channel_id,
+            size,
+            frame,
+        });
+    }
+
+    pub(crate) fn received(&mut self, peer_id: PeerId, channel_id: usize, size: usize) {
+        let frame = self.frame();
+        self.received.write(MessageReceived {
+            peer_id,
+            channel_id,
+            size,
+            frame,
+        });
+    }
+}
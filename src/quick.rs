@@ -0,0 +1,90 @@
+use crate::room_url::{IntoRoomUrl, RoomUrl, RoomUrlError};
+use crate::shared::MatchboxConfig;
+use bevy::prelude::*;
+
+#[cfg(feature = "client")]
+use crate::client::DeferredMatchboxClient;
+#[cfg(feature = "server")]
+use crate::server::DeferredMatchboxHost;
+
+/// Creates a [`MatchboxHost`](crate::MatchboxHost) or [`MatchboxClient`](crate::MatchboxClient)
+/// when the app runs, replacing the startup system that would call the constructor.
+///
+/// Inserts a [`DeferredMatchboxHost`](crate::DeferredMatchboxHost) or
+/// [`DeferredMatchboxClient`](crate::DeferredMatchboxClient), so channels are read once all
+/// plugins are built. Add it next to
+/// [`RepliconMatchboxPlugins`](crate::RepliconMatchboxPlugins) in any order:
+///
+/// ```no_run
+/// # use bevy::prelude::*;
+/// # use bevy_replicon::prelude::*;
+/// # use bevy_replicon_matchbox::*;
+/// App::new().add_plugins((
+///     MinimalPlugins,
+///     RepliconPlugins,
+///     RepliconMatchboxPlugins,
+///     RepliconMatchboxQuickPlugin::client("ws://localhost:3536/game"),
+/// ));
+/// ```
+///
+/// An invalid room URL panics when the plugin is built, an invalid configuration fails with
+/// the constructor's error on the first update.
+pub struct RepliconMatchboxQuickPlugin {
+    role: QuickRole,
+    room_url: Result<RoomUrl, RoomUrlError>,
+    config: MatchboxConfig,
+}
+
+enum QuickRole {
+    #[cfg(feature = "server")]
+    Host,
+    #[cfg(feature = "client")]
+    Client,
+}
+
+impl RepliconMatchboxQuickPlugin {
+    /// Hosts a game in `room_url`.
+    #[cfg(feature = "server")]
+    pub fn host(room_url: impl IntoRoomUrl) -> Self {
+        Self {
+            role: QuickRole::Host,
+            room_url: room_url.into_room_url(),
+            config: Default::default(),
+        }
+    }
+
+    /// Joins the host of `room_url`.
+    #[cfg(feature = "client")]
+    pub fn client(room_url: impl IntoRoomUrl) -> Self {
+        Self {
+            role: QuickRole::Client,
+            room_url: room_url.into_room_url(),
+            config: Default::default(),
+        }
+    }
+
+    pub fn with_config(mut self, config: MatchboxConfig) -> Self {
+        self.config = config;
+        self
+    }
+}
+
+impl Plugin for RepliconMatchboxQuickPlugin {
+    fn build(&self, app: &mut App) {
+        let room_url = match &self.room_url {
+            Ok(room_url) => room_url.as_str(),
+            Err(e) => panic!("invalid room URL for `RepliconMatchboxQuickPlugin`: {e}"),
+        };
+        let config = self.config.clone();
+        match self.role {
+            #[cfg(feature = "server")]
+            QuickRole::Host => {
+                app.insert_resource(DeferredMatchboxHost::with_config(room_url, config));
+            }
+            #[cfg(feature = "client")]
+            QuickRole::Client => {
+                app.insert_resource(DeferredMatchboxClient::with_config(room_url, config));
+            }
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use crate::shared::*;
+use bevy::prelude::*;
+use bevy_matchbox::matchbox_socket::{Packet, PeerId};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+/// Upper bound of the serialized envelope fields besides the payload.
+const ENVELOPE_OVERHEAD: usize = 32;
+
+/// Payload that another client sent through the host.
+///
+/// Received on the client, see `MatchboxClient::send_relayed`.
+#[derive(Event, Debug, Clone)]
+pub struct RelayedMessage {
+    /// Client that sent the payload.
+    pub from: PeerId,
+    pub payload: Bytes,
+}
+
+/// Payload that another client sent directly, bypassing the host.
+///
+/// Received on the client, see `MatchboxClient::send_direct`.
+#[derive(Event, Debug, Clone)]
+pub struct DirectMessage {
+    pub from: PeerId,
+    pub payload: Bytes,
+}
+
+/// Decides whether the host forwards a payload from one client to another.
+///
+/// Receives the sender, the addressed client and the payload.
+pub type RelayFilter = Box<dyn Fn(PeerId, PeerId, &[u8]) -> bool + Send + Sync>;
+
+/// Wire format on the relay channel.
+///
+/// From a client `peer_id` addresses the recipient, from the host it names the sender.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct RelayEnvelope<'a> {
+    pub(crate) peer_id: PeerId,
+    #[serde(borrow)]
+    pub(crate) payload: &'a [u8],
+}
+
+impl RelayEnvelope<'_> {
+    pub(crate) fn to_packet(&self) -> Packet {
+        to_vec_packet(self, self.payload.len() + ENVELOPE_OVERHEAD)
+    }
+}
+
+#[test]
+fn test_envelope() {
+    let peer_id = PeerId(Default::default());
+    let packet = RelayEnvelope {
+        peer_id,
+        payload: &[1, 2, 3],
+    }
+    .to_packet();
+    let envelope: RelayEnvelope = from_packet(&packet).unwrap();
+    assert_eq!(envelope.peer_id, peer_id);
+    assert_eq!(envelope.payload, [1, 2, 3]);
+}
@@ -0,0 +1,71 @@
+//! Names of `bevy_replicon_renet`, for projects switching from the renet backend.
+//!
+//! Replace `bevy_replicon_renet` imports with this module and the renet setup with a room
+//! URL:
+//!
+//! | renet backend | this backend |
+//! |---------------|--------------|
+//! | `RenetServer` and `NetcodeServerTransport` | [`RenetServer::new`] with a room URL |
+//! | `RenetClient` and `NetcodeClientTransport` | [`RenetClient::new`] with a room URL |
+//! | `ConnectionConfig` from `RepliconChannels` | channels are read from `RepliconChannels` |
+//! | `ServerEvent` of `bevy_renet` | [`ServerEvent`], added by [`RenetCompatPlugin`] |
+//! | `ClientId` | `NetworkId` on the client entity, derived from the matchbox `PeerId` |
+//!
+//! Unlike the renet resources, both are removed when the session ends. Game code written
+//! against replicon's `ConnectedClient` and `NetworkId` doesn't change.
+
+#[cfg(feature = "server")]
+use bevy::prelude::*;
+#[cfg(feature = "server")]
+use bevy_replicon::prelude::*;
+
+pub use crate::RepliconMatchboxPlugins as RepliconRenetPlugins;
+#[cfg(feature = "client")]
+pub use crate::client::{
+    MatchboxClient as RenetClient, RepliconMatchboxClientPlugin as RepliconRenetClientPlugin,
+};
+#[cfg(feature = "server")]
+pub use crate::server::{
+    MatchboxHost as RenetServer, RepliconMatchboxServerPlugin as RepliconRenetServerPlugin,
+};
+
+/// Client connections in the shape of the `ServerEvent` of `bevy_renet`.
+///
+/// Sent for matchbox and loopback clients alike. The reason of a disconnect is available
+/// from [`ClientDisconnected`](crate::ClientDisconnected).
+#[cfg(feature = "server")]
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServerEvent {
+    ClientConnected { client_entity: Entity },
+    ClientDisconnected { client_entity: Entity },
+}
+
+/// Sends [`ServerEvent`] when client entities are spawned and despawned.
+#[cfg(feature = "server")]
+pub struct RenetCompatPlugin;
+
+#[cfg(feature = "server")]
+impl Plugin for RenetCompatPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ServerEvent>()
+            .add_observer(send_connected)
+            .add_observer(send_disconnected);
+    }
+}
+
+#[cfg(feature = "server")]
+fn send_connected(trigger: Trigger<OnAdd, ConnectedClient>, mut events: EventWriter<ServerEvent>) {
+    events.write(ServerEvent::ClientConnected {
+        client_entity: trigger.target(),
+    });
+}
+
+#[cfg(feature = "server")]
+fn send_disconnected(
+    trigger: Trigger<OnRemove, ConnectedClient>,
+    mut events: EventWriter<ServerEvent>,
+) {
+    events.write(ServerEvent::ClientDisconnected {
+        client_entity: trigger.target(),
+    });
+}
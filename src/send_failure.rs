@@ -0,0 +1,60 @@
+use crate::stats::ConnectionStats;
+use bevy::prelude::*;
+use bevy_matchbox::MatchboxSocket;
+use bevy_matchbox::matchbox_socket::{Packet, PeerId};
+
+/// What happens to a replicon message the socket refuses to send.
+///
+/// Matchbox refuses messages once the socket stopped, e.g. while it closes. Errors of a
+/// peer's data channel, like a full buffer, happen in the background and are only logged by
+/// matchbox. Every refusal counts towards `ConnectionStats::send_failures`.
+///
+/// There is no policy to retry: a stopped socket refuses every later attempt as well, and
+/// resending a message after newer ones went out would reorder ordered channels.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SendFailurePolicy {
+    /// Drops the message.
+    #[default]
+    Drop,
+    /// Drops the message and disconnects the peer with `DisconnectKind::SendFailed`.
+    Disconnect,
+}
+
+/// Refused messages of a single peer.
+#[derive(Component, Default)]
+pub(crate) struct SendFailures {
+    disconnect: bool,
+}
+
+impl SendFailures {
+    /// Sends a packet of a replicon channel and applies the channel's `policy` if the socket
+    /// refuses it.
+    pub(crate) fn send(
+        &mut self,
+        socket: &mut MatchboxSocket,
+        stats: &mut ConnectionStats,
+        policy: SendFailurePolicy,
+        socket_channel_id: usize,
+        packet: Packet,
+        peer_id: PeerId,
+    ) {
+        if socket
+            .channel_mut(socket_channel_id)
+            .try_send(packet, peer_id)
+            .is_ok()
+        {
+            return;
+        }
+        stats.send_failures += 1;
+        debug!("socket refused message on channel {socket_channel_id} for peer {peer_id}");
+        if policy == SendFailurePolicy::Disconnect {
+            self.disconnect = true;
+        }
+    }
+
+    /// Returns `true` once after a refused message of a channel with
+    /// [`SendFailurePolicy::Disconnect`].
+    pub(crate) fn take_disconnect(&mut self) -> bool {
+        std::mem::take(&mut self.disconnect)
+    }
+}
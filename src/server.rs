@@ -1,4 +1,26 @@
+use crate::barrier::TickCounter;
+use crate::capture::*;
+use crate::chat::*;
+use crate::delivery::PendingDeliveries;
+use crate::loopback::*;
+use crate::mute::{ChannelMutes, MutedChannel};
+#[cfg(feature = "net_debug")]
+use crate::net_debug::*;
+use crate::relay::{RelayEnvelope, RelayFilter};
+use crate::room_url::{IntoRoomUrl, redact_room_url};
+use crate::send_failure::SendFailures;
+use crate::shaping::{ChannelBuckets, ChannelPacer, SendQueue};
 use crate::shared::*;
+#[cfg(feature = "signaling")]
+use crate::signaling::EmbeddedSignalingServer;
+use crate::stats::{BandwidthProfile, BandwidthReports, ConnectionStats, HostStats, MessageKind};
+use crate::stats_log::{StatsLog, log_snapshot};
+use crate::throttle::ConnectionThrottle;
+use crate::transfer::*;
+use crate::user_channel::*;
+use crate::voice::*;
+use crate::watchdog::{Watchdog, WatchdogAlarm};
+use bevy::ecs::schedule::{InternedScheduleLabel, InternedSystemSet, ScheduleLabel};
 use bevy::prelude::*;
 use bevy::tasks::futures_lite::io;
 use bevy_matchbox::MatchboxSocket;
@@ -6,42 +28,143 @@ use bevy_matchbox::matchbox_socket::Packet;
 use bevy_matchbox::prelude::{PeerId, PeerState};
 use bevy_replicon::prelude::*;
 use bevy_replicon::shared::backend::connected_client::NetworkId;
-use std::collections::HashMap;
+use bevy_replicon::shared::backend::replicon_channels::ClientChannel;
+use bevy_replicon::shared::protocol::ProtocolHash;
+use bytes::Bytes;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{BuildHasher, RandomState};
+use std::time::Duration;
 
-pub struct RepliconMatchboxServerPlugin;
+/// Relative deviation from the current send interval below which no suggestion is made.
+const SUGGESTION_TOLERANCE: f64 = 0.9;
+
+/// Kick reason of connection attempts rejected by [`MatchboxConfig::reconnect_throttle`].
+const THROTTLED_REASON: &str = "too many connection attempts, try again later";
+
+/// Kick reason of peers rejected because of [`MatchboxConfig::max_clients`].
+const FULL_REASON: &str = "host is full";
+
+/// How long a connected peer has to send `SystemChannelMessage::Join` before it's rejected.
+const JOIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Adds a matchbox server backend to `bevy_replicon`.
+pub struct RepliconMatchboxServerPlugin {
+    /// Maximum size of a replicon message sent to a client, see [`ConnectedClient::max_size`].
+    pub max_size: usize,
+    /// Schedule of the systems reading from the socket.
+    pub receive_schedule: InternedScheduleLabel,
+    /// Set of the systems reading from the socket.
+    ///
+    /// Must run before replicon processes received messages, which happens in
+    /// [`ServerSet::Receive`] in [`PreUpdate`].
+    pub receive_set: InternedSystemSet,
+    /// Schedule of the systems writing to the socket.
+    pub send_schedule: InternedScheduleLabel,
+    /// Set of the systems writing to the socket.
+    ///
+    /// Must run after replicon produced messages, which happens in [`ServerSet::Send`] in
+    /// [`PostUpdate`].
+    pub send_set: InternedSystemSet,
+}
+
+impl Default for RepliconMatchboxServerPlugin {
+    fn default() -> Self {
+        Self {
+            max_size: 1200,
+            receive_schedule: PreUpdate.intern(),
+            receive_set: ServerSet::ReceivePackets.intern(),
+            send_schedule: PostUpdate.intern(),
+            send_set: ServerSet::SendPackets.intern(),
+        }
+    }
+}
 
 impl Plugin for RepliconMatchboxServerPlugin {
     fn build(&self, app: &mut App) {
+        app.add_event::<SignalingLost>()
+            .add_event::<VoiceFrame>()
+            .add_event::<ChatMessage>()
+            .add_event::<UserMessage>()
+            .add_event::<TransferProgress>()
+            .add_event::<TransferCompleted>()
+            .add_event::<TransferCorrupted>()
+            .add_event::<TransferCancelled>()
+            .add_event::<SuggestedSendInterval>()
+            .add_event::<ClientDisconnected>()
+            .add_event::<PeerUnresponsive>()
+            .init_resource::<BandwidthReports>()
+            .register_type::<ConnectionStats>();
+        #[cfg(feature = "net_debug")]
+        app.add_event::<MessageSent>()
+            .add_event::<MessageReceived>();
+        app.insert_resource(ServerPluginSettings {
+            max_size: self.max_size,
+        });
         app.add_systems(
-            PreUpdate,
+            self.receive_schedule,
             (
+                build_deferred_host.run_if(resource_exists::<DeferredMatchboxHost>),
                 set_running.run_if(resource_added::<MatchboxHost>),
+                store_protocol_hash.run_if(resource_added::<MatchboxHost>),
                 receive_system_channel_packets.run_if(resource_exists::<MatchboxHost>),
+                watch_unresponsive_clients.run_if(resource_exists::<MatchboxHost>),
                 receive_packets.run_if(resource_exists::<MatchboxHost>),
+                release_held_packets
+                    .run_if(resource_exists::<MatchboxHost>.and(fixed_tick_this_frame)),
+                relay_packets.run_if(resource_exists::<MatchboxHost>),
+                forward_voice.run_if(resource_exists::<MatchboxHost>),
+                receive_chat_on_host.run_if(resource_exists::<MatchboxHost>),
+                receive_user_on_host.run_if(resource_exists::<MatchboxHost>),
+                update_user_handles_on_host.run_if(resource_exists::<MatchboxHost>),
+                update_host_transfers.run_if(resource_exists::<MatchboxHost>),
                 received_disconnect.run_if(resource_exists::<MatchboxHost>),
+                despawn_expired_clients.run_if(resource_exists::<MatchboxHost>),
+                forget_disconnected_clients.run_if(resource_exists::<MatchboxHost>),
+                check_channel_registry.run_if(
+                    resource_exists::<MatchboxHost>.and(resource_changed::<RepliconChannels>),
+                ),
+                set_loopback_running.run_if(resource_added::<LoopbackHost>),
+                receive_loopback_on_host.run_if(resource_exists::<LoopbackHost>),
             )
                 .chain()
-                .in_set(ServerSet::ReceivePackets),
+                .in_set(self.receive_set),
         );
         app.add_systems(
-            PostUpdate,
+            self.send_schedule,
             (
-                update_client_presence
-                    .in_set(ServerSet::SendPackets)
-                    .run_if(resource_exists::<MatchboxHost>),
+                update_client_presence.run_if(resource_exists::<MatchboxHost>),
                 send_packets
-                    .in_set(ServerSet::SendPackets)
                     .run_if(resource_exists::<MatchboxHost>)
                     .after(update_client_presence)
                     .before(received_disconnect),
-                set_stopped
-                    .in_set(ServerSet::Send)
-                    .run_if(resource_removed::<MatchboxHost>),
+                send_loopback_on_host
+                    .run_if(resource_exists::<LoopbackHost>)
+                    .before(send_packets),
+                update_bandwidth_report
+                    .run_if(resource_exists::<MatchboxHost>)
+                    .after(send_packets),
+                log_stats
+                    .run_if(resource_exists::<MatchboxHost>)
+                    .after(send_packets),
+            )
+                .in_set(self.send_set),
+        );
+        app.add_systems(
+            PostUpdate,
+            set_stopped.in_set(ServerSet::Send).run_if(
+                (resource_removed::<MatchboxHost>.or(resource_removed::<LoopbackHost>))
+                    .and(not(resource_exists::<MatchboxHost>))
+                    .and(not(resource_exists::<LoopbackHost>)),
             ),
         );
     }
 }
 
+#[derive(Resource)]
+pub(crate) struct ServerPluginSettings {
+    pub(crate) max_size: usize,
+}
+
 fn set_stopped(mut server: ResMut<RepliconServer>) {
     trace!("server stopped");
     server.set_running(false);
@@ -51,55 +174,335 @@ fn set_running(mut server: ResMut<RepliconServer>) {
     server.set_running(true);
 }
 
-fn update_client_presence(mut commands: Commands, mut server: ResMut<MatchboxHost>) {
+fn build_deferred_host(
+    mut commands: Commands,
+    deferred: Res<DeferredMatchboxHost>,
+    channels: Res<RepliconChannels>,
+) -> Result {
+    commands.remove_resource::<DeferredMatchboxHost>();
+    let host = MatchboxHost::with_config(&deferred.room_url, &channels, deferred.config.clone())?;
+    commands.insert_resource(host);
+    Ok(())
+}
+
+fn check_channel_registry(server: Res<MatchboxHost>, channels: Res<RepliconChannels>) {
+    if !server.layout.covers(&channels) {
+        warn!(
+            "replicon channels changed after the host was created, \
+            recreate the host so clients can use them"
+        );
+    }
+}
+
+/// Keeps the hash for the handshake if [`MatchboxConfig::check_protocol_hash`] is enabled.
+fn store_protocol_hash(mut server: ResMut<MatchboxHost>, protocol_hash: Option<Res<ProtocolHash>>) {
+    if server.config.check_protocol_hash {
+        if protocol_hash.is_none() {
+            warn!("replicon's protocol hash is missing, clients won't check it");
+        }
+        server.protocol_hash = protocol_hash.map(|hash| *hash);
+    }
+}
+
+fn update_client_presence(
+    mut commands: Commands,
+    mut server: ResMut<MatchboxHost>,
+    mut signaling_lost: EventWriter<SignalingLost>,
+    mut disconnected: EventWriter<ClientDisconnected>,
+    disconnected_clients: Query<(Entity, &MatchboxClientConnection), With<PeerDisconnected>>,
+    time: Res<Time<Real>>,
+    settings: Res<ServerPluginSettings>,
+) {
     let Ok(updated_peers) = server.socket.try_update_peers() else {
-        for client_entity in server.client_entities.values() {
-            commands.entity(*client_entity).despawn();
+        let clients = server
+            .client_entities
+            .iter()
+            .map(|(&peer_id, &client_entity)| (client_entity, peer_id))
+            .chain(
+                disconnected_clients
+                    .iter()
+                    .map(|(client_entity, connection)| (client_entity, connection.peer_id)),
+            );
+        for (client_entity, peer_id) in clients {
+            commands.entity(client_entity).despawn();
+            disconnected.write(ClientDisconnected {
+                client_entity,
+                peer_id,
+                kind: DisconnectKind::SignalingLost,
+            });
+        }
+        for (peer_id, closing) in server.closing.drain() {
+            disconnected.write(closing.into_event(peer_id));
         }
         error!("sockets closed, shutting down");
+        signaling_lost.write(SignalingLost);
         commands.remove_resource::<MatchboxHost>();
         return;
     };
 
+    let mut queue_changed = false;
     for (peer, state) in updated_peers {
         match state {
             PeerState::Connected => {
-                if server.client_entities.contains_key(&peer) {
+                if server.client_entities.contains_key(&peer) || server.waiting.contains(&peer) {
                     continue;
                 }
-                let network_id = NetworkId::new(uuid_to_u64_truncated(peer));
-                let client_entity = commands
-                    .spawn((
-                        ConnectedClient { max_size: 1200 },
-                        network_id,
-                        MatchboxClientConnection { peer_id: peer },
-                    ))
-                    .id();
-                trace!(
-                    "new client peer: {}, network_id: {:?} entity: {}",
-                    peer, network_id, client_entity
-                );
-                server.client_entities.insert(peer, client_entity);
-                let mut buf = [0u8; 1];
-                let packet: Packet =
-                    to_packet(&SystemChannelMessage::ConnectedToHost, &mut buf).into();
+                // decided on once the peer tells whether it rejoins
+                server.joining.insert(peer, time.elapsed());
+                let packet = system_packet(&SystemChannelMessage::RequestJoin);
                 server
                     .socket
                     .channel_mut(SYSTEM_CHANNEL_ID)
                     .send(packet, peer);
             }
             PeerState::Disconnected => {
+                if server.joining.remove(&peer).is_some() {
+                    trace!("peer {peer} left before joining");
+                    continue;
+                }
+                if let Some(index) = server.waiting.iter().position(|&id| id == peer) {
+                    trace!("peer {peer} left the waiting room");
+                    server.waiting.remove(index);
+                    queue_changed = true;
+                    continue;
+                }
+                if let Some(closing) = server.closing.remove(&peer) {
+                    disconnected.write(closing.into_event(peer));
+                    continue;
+                }
                 let Some(client_entity) = server.client_entities.remove(&peer) else {
                     continue;
                 };
-                trace!("client disconnected {:?}: {}", peer, client_entity);
-                commands.entity(client_entity).despawn();
+                if server.config.rejoin_grace_period.is_zero() {
+                    trace!("client disconnected {:?}: {}", peer, client_entity);
+                    commands.entity(client_entity).despawn();
+                    disconnected.write(ClientDisconnected {
+                        client_entity,
+                        peer_id: peer,
+                        kind: DisconnectKind::IceFailed,
+                    });
+                } else {
+                    trace!(
+                        "client dropped {:?}: {}, awaiting rejoin",
+                        peer, client_entity
+                    );
+                    commands.entity(client_entity).insert(PeerDisconnected {
+                        since: time.elapsed(),
+                        kind: DisconnectKind::IceFailed,
+                    });
+                    server.rejoin_backlog.insert(client_entity, Vec::new());
+                }
             }
         }
     }
+
+    for (peer, rejoin_token) in std::mem::take(&mut server.joins) {
+        join(
+            &mut commands,
+            &mut server,
+            &disconnected_clients,
+            peer,
+            rejoin_token,
+            settings.max_size,
+            time.elapsed(),
+        );
+    }
+
+    let expired: Vec<_> = server
+        .joining
+        .iter()
+        .filter(|&(_, &since)| time.elapsed() - since >= JOIN_TIMEOUT)
+        .map(|(&peer, _)| peer)
+        .collect();
+    for peer in expired {
+        debug!("peer {peer} didn't join in time, rejecting it");
+        server.joining.remove(&peer);
+        server.reject(peer, None);
+    }
+
+    let mut admitted = false;
+    while !server.is_full() {
+        let Some(peer) = server.waiting.pop_front() else {
+            break;
+        };
+        debug!("admitting peer {peer} from the waiting room");
+        admit_client(
+            &mut commands,
+            &mut server,
+            peer,
+            settings.max_size,
+            time.elapsed(),
+        );
+        admitted = true;
+    }
+    if admitted || queue_changed {
+        for index in 0..server.waiting.len() {
+            server.send_queue_position(index);
+        }
+    }
 }
 
-fn receive_system_channel_packets(mut commands: Commands, mut server: ResMut<MatchboxHost>) {
+/// Reattaches a rejoining peer to its client entity, or admits it like a new client.
+fn join(
+    commands: &mut Commands,
+    server: &mut MatchboxHost,
+    disconnected_clients: &Query<(Entity, &MatchboxClientConnection), With<PeerDisconnected>>,
+    peer: PeerId,
+    rejoin_token: Option<u64>,
+    max_size: usize,
+    now: Duration,
+) {
+    server.joining.remove(&peer);
+    if server.client_entities.contains_key(&peer) || server.waiting.contains(&peer) {
+        return;
+    }
+    if let Some(session_token) = rejoin_token {
+        if !server.allow_attempt(session_token, now) {
+            debug!("throttling rejoin of peer {peer}");
+            server.reject(peer, Some(THROTTLED_REASON.into()));
+            return;
+        }
+        let previous_entity = disconnected_clients
+            .iter()
+            .find(|(_, connection)| connection.session_token == session_token)
+            .map(|(entity, _)| entity);
+        if let Some(previous_entity) = previous_entity {
+            // the slot of a client awaiting a rejoin stays reserved, so the cap doesn't apply
+            trace!("peer {peer} rejoined as client {previous_entity}");
+            commands
+                .entity(previous_entity)
+                .remove::<PeerDisconnected>()
+                .insert((
+                    MatchboxClientConnection {
+                        peer_id: peer,
+                        session_token,
+                    },
+                    NetworkId::new(uuid_to_u64_truncated(peer)),
+                    Watchdog::new(now),
+                ));
+            server.client_entities.insert(peer, previous_entity);
+            server.resume_backlog(previous_entity);
+            let packet = server.connected_packet(session_token);
+            server
+                .socket
+                .channel_mut(SYSTEM_CHANNEL_ID)
+                .send(packet, peer);
+            return;
+        }
+        trace!("no client awaiting rejoin for peer {peer}");
+    }
+    if !server.is_full() {
+        admit_client(commands, server, peer, max_size, now);
+    } else if server.config.waiting_room {
+        server.waiting.push_back(peer);
+        let index = server.waiting.len() - 1;
+        debug!("host is full, peer {peer} waits at position {}", index + 1);
+        server.send_queue_position(index);
+    } else {
+        debug!("host is full, rejecting peer {peer}");
+        server.reject(peer, Some(FULL_REASON.into()));
+    }
+}
+
+/// Spawns the client entity of a peer and introduces the host to it.
+fn admit_client(
+    commands: &mut Commands,
+    server: &mut MatchboxHost,
+    peer: PeerId,
+    max_size: usize,
+    now: Duration,
+) {
+    let network_id = NetworkId::new(uuid_to_u64_truncated(peer));
+    let session_token = new_session_token(peer);
+    let client_entity = commands
+        .spawn((
+            ConnectedClient { max_size },
+            network_id,
+            MatchboxClientConnection {
+                peer_id: peer,
+                session_token,
+            },
+            ChannelBuckets::default(),
+            ChannelPacer::default(),
+            SendQueue::new(now),
+            SendFailures::default(),
+            ConnectionStats::default(),
+            Watchdog::new(now),
+            TickCounter::default(),
+        ))
+        .id();
+    trace!(
+        "new client peer: {}, network_id: {:?} entity: {}",
+        peer, network_id, client_entity
+    );
+    server.client_entities.insert(peer, client_entity);
+    let packet = server.connected_packet(session_token);
+    server
+        .socket
+        .channel_mut(SYSTEM_CHANNEL_ID)
+        .send(packet, peer);
+}
+
+fn despawn_expired_clients(
+    mut commands: Commands,
+    mut server: ResMut<MatchboxHost>,
+    mut disconnected: EventWriter<ClientDisconnected>,
+    disconnected_clients: Query<(Entity, &MatchboxClientConnection, &PeerDisconnected)>,
+    time: Res<Time<Real>>,
+) {
+    for (client_entity, connection, peer_disconnected) in &disconnected_clients {
+        if time.elapsed() - peer_disconnected.since >= server.config.rejoin_grace_period {
+            trace!("rejoin grace period of client {} expired", client_entity);
+            commands.entity(client_entity).despawn();
+            disconnected.write(ClientDisconnected {
+                client_entity,
+                peer_id: connection.peer_id,
+                kind: DisconnectKind::Timeout,
+            });
+        }
+    }
+
+    let timeout = server.config.disconnect_ack_timeout;
+    let expired: Vec<_> = server
+        .closing
+        .iter()
+        .filter(|(_, closing)| time.elapsed() - closing.since >= timeout)
+        .map(|(&peer_id, _)| peer_id)
+        .collect();
+    for peer_id in expired {
+        trace!("client {peer_id} didn't acknowledge the disconnect");
+        let closing = server.closing.remove(&peer_id).unwrap();
+        disconnected.write(closing.into_event(peer_id));
+    }
+}
+
+/// Drops the mutes, rejoin backlogs, pending deliveries and chat allowances of clients that
+/// left.
+fn forget_disconnected_clients(
+    mut server: ResMut<MatchboxHost>,
+    mut disconnected: EventReader<ClientDisconnected>,
+) {
+    for event in disconnected.read() {
+        server.mutes.forget(event.client_entity);
+        server.rejoin_backlog.remove(&event.client_entity);
+        server.deliveries.forget(event.peer_id);
+        server.chat.forget(event.peer_id);
+    }
+}
+
+fn new_session_token(peer_id: PeerId) -> u64 {
+    RandomState::new().hash_one(peer_id)
+}
+
+fn receive_system_channel_packets(
+    mut commands: Commands,
+    mut server: ResMut<MatchboxHost>,
+    mut clients: Query<(&mut ConnectionStats, &SendQueue)>,
+    mut watchdogs: Query<&mut Watchdog>,
+    mut suggested_intervals: EventWriter<SuggestedSendInterval>,
+    mut disconnected: EventWriter<ClientDisconnected>,
+    time: Res<Time<Real>>,
+) {
     if server.socket.all_channels_closed() {
         trace!("matchbox socket was closed");
         return;
@@ -110,7 +513,13 @@ fn receive_system_channel_packets(mut commands: Commands, mut server: ResMut<Mat
     };
     for (peer_id, packet) in channel.receive() {
         let Ok(message) = from_packet(&packet) else {
-            error!("failed to deserialize system message {}", packet.len());
+            if let Some((mut stats, _)) = server
+                .client_entities
+                .get(&peer_id)
+                .and_then(|&client_entity| clients.get_mut(client_entity).ok())
+            {
+                protocol_violation(&mut server, peer_id, &mut stats, "malformed system message");
+            }
             continue;
         };
         trace!(
@@ -119,79 +528,608 @@ fn receive_system_channel_packets(mut commands: Commands, mut server: ResMut<Mat
         );
 
         match message {
-            SystemChannelMessage::ClientDisconnects => {
+            SystemChannelMessage::ClientDisconnects | SystemChannelMessage::ProtocolMismatch => {
+                if let Some(closing) = server.closing.remove(&peer_id) {
+                    trace!("client {peer_id} acknowledged the disconnect");
+                    disconnected.write(closing.into_event(peer_id));
+                    continue;
+                }
                 let Some(client_entity) = server.client_entities.remove(&peer_id) else {
                     continue;
                 };
+                let kind = if message == SystemChannelMessage::ProtocolMismatch {
+                    info!("client {peer_id} has a different protocol");
+                    DisconnectKind::ProtocolMismatch
+                } else {
+                    DisconnectKind::RemoteClosed
+                };
                 trace!("client disconnected {peer_id}: {client_entity}");
                 commands.entity(client_entity).despawn();
+                disconnected.write(ClientDisconnected {
+                    client_entity,
+                    peer_id,
+                    kind,
+                });
+            }
+            SystemChannelMessage::Join { rejoin_token } => {
+                server.joins.push((peer_id, rejoin_token));
+            }
+            SystemChannelMessage::Ping { sent_at_micros } => {
+                let packet = system_packet(&SystemChannelMessage::Pong { sent_at_micros });
+                server
+                    .socket
+                    .channel_mut(SYSTEM_CHANNEL_ID)
+                    .send(packet, peer_id);
+            }
+            SystemChannelMessage::Pong { sent_at_micros } => {
+                let Some(&client_entity) = server.client_entities.get(&peer_id) else {
+                    continue;
+                };
+                if let Ok((mut stats, _)) = clients.get_mut(client_entity) {
+                    let sent_at = Duration::from_micros(sent_at_micros);
+                    stats.record_rtt(time.elapsed().saturating_sub(sent_at));
+                }
+                if let Ok(mut watchdog) = watchdogs.get_mut(client_entity) {
+                    watchdog.record_pong(time.elapsed());
+                }
+            }
+            SystemChannelMessage::ReceiveReport { bytes_per_second } => {
+                let Some(&client_entity) = server.client_entities.get(&peer_id) else {
+                    continue;
+                };
+                let Ok((mut stats, queue)) = clients.get_mut(client_entity) else {
+                    continue;
+                };
+                stats.apply_report(bytes_per_second.into());
+                let limit = server.config.bandwidth_limit.as_ref().map(|limit| {
+                    limit
+                        .bytes_per_second(time.elapsed() - queue.created_at)
+                        .into()
+                });
+                let (Some(send_interval), Some(interval)) =
+                    (stats.send_interval, stats.suggested_send_interval(limit))
+                else {
+                    continue;
+                };
+                let change = interval.as_secs_f64() / send_interval.as_secs_f64();
+                if !(SUGGESTION_TOLERANCE..=1.0 / SUGGESTION_TOLERANCE).contains(&change) {
+                    suggested_intervals.write(SuggestedSendInterval {
+                        client_entity,
+                        interval,
+                    });
+                }
+            }
+            SystemChannelMessage::Delivered { id } => {
+                if let Some(delivered) = server.deliveries.confirm(peer_id, id) {
+                    commands.trigger(delivered);
+                }
             }
             _ => {
-                error!("Unexpected message {message:?} received from client {peer_id}");
+                if let Some((mut stats, _)) = server
+                    .client_entities
+                    .get(&peer_id)
+                    .and_then(|&client_entity| clients.get_mut(client_entity).ok())
+                {
+                    protocol_violation(
+                        &mut server,
+                        peer_id,
+                        &mut stats,
+                        "unexpected system message",
+                    );
+                }
             }
         }
     }
 }
 
+/// Disconnects clients whose connection looks open but that stopped answering pings.
+fn watch_unresponsive_clients(
+    mut server: ResMut<MatchboxHost>,
+    mut clients: Query<
+        (Entity, &MatchboxClientConnection, &mut Watchdog),
+        Without<PeerDisconnected>,
+    >,
+    mut unresponsive: EventWriter<PeerUnresponsive>,
+    time: Res<Time<Real>>,
+) {
+    let MatchboxHost {
+        client_entities,
+        clients_to_disconnect,
+        config,
+        ..
+    } = &mut *server;
+    let Some(timeout) = &config.unresponsive_timeout else {
+        return;
+    };
+    for (client_entity, connection, mut watchdog) in &mut clients {
+        // only connections matchbox still considers open, dropped peers are handled there
+        if !client_entities.contains_key(&connection.peer_id) {
+            continue;
+        }
+        match watchdog.check(timeout, time.elapsed()) {
+            Some(WatchdogAlarm::Unresponsive) => {
+                warn!("client {client_entity} stopped responding");
+                unresponsive.write(PeerUnresponsive {
+                    client_entity,
+                    peer_id: connection.peer_id,
+                });
+            }
+            Some(WatchdogAlarm::Expired) => {
+                debug!("disconnecting unresponsive client {client_entity}");
+                clients_to_disconnect.push((connection.peer_id, DisconnectKind::Timeout));
+            }
+            None => (),
+        }
+    }
+}
+
 fn receive_packets(
     mut replicon_server: ResMut<RepliconServer>,
     mut server: ResMut<MatchboxHost>,
-    channels: Res<RepliconChannels>,
+    mut stats: Query<&mut ConnectionStats>,
+    time: Res<Time<Real>>,
+    #[cfg(feature = "net_debug")] mut net_debug: NetDebugWriter,
 ) {
-    for (channel_id, _) in channels.client_channels().iter().enumerate() {
-        let socket_channel_id = 1 + channels.server_channels().len() + channel_id;
+    // server channels only carry messages to clients
+    for channel_id in 0..server.layout.server_channel_count() {
+        let Some(socket_channel_id) = server.layout.server_channel(channel_id) else {
+            continue;
+        };
+        for (id, _) in server.socket.channel_mut(socket_channel_id).receive() {
+            let Some(&client_entity) = server.client_entities.get(&id) else {
+                continue;
+            };
+            if let Ok(mut stats) = stats.get_mut(client_entity) {
+                protocol_violation(&mut server, id, &mut stats, "message on a server channel");
+            }
+        }
+    }
+
+    for channel_id in 0..server.layout.client_channel_count() {
+        let Some(socket_channel_id) = server.layout.client_channel(channel_id) else {
+            continue;
+        };
         for (id, packet) in server.socket.channel_mut(socket_channel_id).receive() {
-            let Some(client_entity) = server.client_entities.get(&id) else {
+            let Some(&client_entity) = server.client_entities.get(&id) else {
                 trace!("received packet from unknown client {}", id);
+                server.dropped_messages += 1;
                 continue;
             };
-            replicon_server.insert_received(*client_entity, channel_id, strip_marker(&packet));
+            if let Ok(mut stats) = stats.get_mut(client_entity) {
+                stats.record_received(channel_id, packet.len());
+            }
+            if server.mutes.discards(client_entity, channel_id) {
+                trace!("discarding packet from {id} on muted channel {channel_id}");
+                continue;
+            }
+            let message = strip_marker(&packet);
+            if message.len() > server.config.max_inbound_message_size {
+                if let Ok(mut stats) = stats.get_mut(client_entity) {
+                    protocol_violation(&mut server, id, &mut stats, "oversized message");
+                }
+                server.dropped_messages += 1;
+                continue;
+            }
+            // replicon acknowledges mutations on its own, even while the player is idle
+            let activity = channel_id != ClientChannel::MutationAcks as usize;
+            if let Some(mut stats) = stats.get_mut(client_entity).ok().filter(|_| activity) {
+                stats.record_activity(time.elapsed());
+            }
+            #[cfg(feature = "net_debug")]
+            net_debug.received(id, channel_id, message.len());
+            record(
+                &mut server.capture,
+                CaptureDirection::Received,
+                time.elapsed(),
+                id,
+                channel_id,
+                &message,
+            );
+            if server.config.hold_until_fixed_update {
+                server
+                    .held_packets
+                    .push((client_entity, channel_id, message));
+            } else {
+                replicon_server.insert_received(client_entity, channel_id, message);
+            }
         }
     }
 }
 
+/// Counts a protocol violation of a client and disconnects it if configured.
+pub(crate) fn protocol_violation(
+    server: &mut MatchboxHost,
+    peer_id: PeerId,
+    stats: &mut ConnectionStats,
+    violation: &str,
+) {
+    if stats.record_violation() {
+        warn!(
+            "protocol violation by client {peer_id}: {violation}, {} in total",
+            stats.protocol_violations
+        );
+    }
+    if server.config.disconnect_on_protocol_violation
+        && !server
+            .clients_to_disconnect
+            .iter()
+            .any(|&(id, _)| id == peer_id)
+    {
+        server
+            .clients_to_disconnect
+            .push((peer_id, DisconnectKind::ProtocolViolation));
+    }
+}
+
+/// Counts a protocol violation like [`protocol_violation`], looking up the stats of the
+/// client.
+pub(crate) fn client_violation(
+    server: &mut MatchboxHost,
+    stats: &mut Query<&mut ConnectionStats>,
+    peer_id: PeerId,
+    violation: &str,
+) {
+    if let Some(mut stats) = server
+        .client_entities
+        .get(&peer_id)
+        .and_then(|&client_entity| stats.get_mut(client_entity).ok())
+    {
+        protocol_violation(server, peer_id, &mut stats, violation);
+    }
+}
+
+fn relay_packets(mut server: ResMut<MatchboxHost>, mut stats: Query<&mut ConnectionStats>) {
+    // direct messages are only meant for clients
+    let direct_channel = server.layout.direct_channel();
+    server.socket.channel_mut(direct_channel).receive();
+
+    let relay_channel = server.layout.relay_channel();
+    for (from, packet) in server.socket.channel_mut(relay_channel).receive() {
+        if !server.client_entities.contains_key(&from) {
+            trace!("received relay message from unknown client {}", from);
+            continue;
+        }
+        if packet.len() > server.config.max_inbound_message_size {
+            client_violation(&mut server, &mut stats, from, "oversized relay message");
+            continue;
+        }
+        let Ok(envelope) = from_packet::<RelayEnvelope>(&packet) else {
+            client_violation(&mut server, &mut stats, from, "malformed relay message");
+            continue;
+        };
+        let to = envelope.peer_id;
+        if !server.client_entities.contains_key(&to) {
+            trace!("dropping relay message from {from} to unknown client {to}");
+            continue;
+        }
+        if let Some(filter) = &server.relay_filter {
+            if !filter(from, to, envelope.payload) {
+                trace!("relay message from {from} to {to} filtered");
+                continue;
+            }
+        }
+        let packet = RelayEnvelope {
+            peer_id: from,
+            payload: envelope.payload,
+        }
+        .to_packet();
+        server.forward(to, relay_channel, packet);
+    }
+}
+
+fn release_held_packets(
+    mut replicon_server: ResMut<RepliconServer>,
+    mut server: ResMut<MatchboxHost>,
+) {
+    for (client_entity, channel_id, message) in server.held_packets.drain(..) {
+        replicon_server.insert_received(client_entity, channel_id, message);
+    }
+}
+
 fn send_packets(
     mut commands: Commands,
     mut replicon_server: ResMut<RepliconServer>,
     mut server: ResMut<MatchboxHost>,
-    clients: Query<&MatchboxClientConnection>,
+    mut clients: Query<(
+        &MatchboxClientConnection,
+        &mut ChannelBuckets,
+        &mut ChannelPacer,
+        &mut SendQueue,
+        &mut SendFailures,
+        &mut ConnectionStats,
+        &mut TickCounter,
+    )>,
+    channels: Res<RepliconChannels>,
+    time: Res<Time<Real>>,
+    #[cfg(feature = "net_debug")] mut net_debug: NetDebugWriter,
 ) {
-    for (client_entity, channel_id, message) in replicon_server.drain_sent() {
-        let Ok(connection) = clients.get(client_entity) else {
+    let MatchboxHost {
+        socket,
+        client_entities,
+        clients_to_disconnect,
+        closing,
+        config,
+        capture,
+        dropped_messages,
+        stats: host_stats,
+        mutes,
+        rejoin_backlog,
+        resumed,
+        forwarded,
+        layout,
+        ..
+    } = &mut *server;
+    let released = mutes.release();
+    for (client_entity, channel_id, message) in resumed
+        .drain(..)
+        .chain(released)
+        .chain(replicon_server.drain_sent())
+    {
+        let Ok((connection, mut buckets, mut pacer, mut queue, mut failures, mut stats, mut ticks)) =
+            clients.get_mut(client_entity)
+        else {
             trace!("client {} not connected", client_entity);
+            *dropped_messages += 1;
+            continue;
+        };
+        let Some(socket_channel_id) = layout.server_channel(channel_id) else {
+            warn_once!(
+                "dropping messages on server channel {channel_id}, \
+                it was registered after the socket was created"
+            );
+            *dropped_messages += 1;
             continue;
         };
-        if !server.client_entities.contains_key(&connection.peer_id) {
+        if let Some(backlog) = rejoin_backlog.get_mut(&client_entity) {
+            // replicon considers reliable messages delivered, so they must reach the client
+            // once it rejoins, unreliable ones are sent again by replicon
+            if matches!(channels.server_channels()[channel_id], Channel::Unreliable) {
+                *dropped_messages += 1;
+            } else {
+                backlog.push((channel_id, message));
+            }
+            continue;
+        }
+        if !client_entities.contains_key(&connection.peer_id) {
             trace!("client {} was disconnected", client_entity);
+            *dropped_messages += 1;
+            continue;
+        }
+        let Some(message) = mutes.hold(client_entity, channel_id, message) else {
             continue;
+        };
+        if config.tick_barrier.is_some()
+            && !matches!(channels.server_channels()[channel_id], Channel::Unreliable)
+        {
+            ticks.count(channel_id);
         }
+        #[cfg(feature = "net_debug")]
+        net_debug.sent(connection.peer_id, channel_id, message.len());
+        record(
+            capture,
+            CaptureDirection::Sent,
+            time.elapsed(),
+            connection.peer_id,
+            channel_id,
+            &message,
+        );
+        let packet = add_marker(message.as_ref());
         trace!(
             "sending packet to client {}: c:{} - {:?}",
             client_entity,
             channel_id,
-            add_marker(message.as_ref()).len()
+            packet.len()
         );
-        let socket_channel_id = 1 + channel_id;
-        server
-            .socket
-            .channel_mut(socket_channel_id)
-            .send(add_marker(message.as_ref()), connection.peer_id);
+        if config.immediate_channels.contains(&channel_id) {
+            stats.record_sent(channel_id, packet.len());
+            failures.send(
+                socket,
+                &mut stats,
+                config.send_failure_policy(channel_id),
+                socket_channel_id,
+                packet,
+                connection.peer_id,
+            );
+            continue;
+        }
+        match (
+            config.channel_limits.get(&channel_id),
+            config.channel_pacing.get(&channel_id),
+        ) {
+            (Some(bucket), _) => buckets.push(channel_id, bucket, packet),
+            (None, Some(&interval)) => pacer.push(channel_id, interval, packet),
+            (None, None) => enqueue(&mut queue, config, channel_id, packet, time.elapsed()),
+        }
     }
-    let disconnect_ids: Vec<_> = server.clients_to_disconnect.drain(..).collect();
 
-    for peer_id in disconnect_ids {
-        let Some(client_entity) = server.client_entities.remove(&peer_id) else {
+    for (peer_id, socket_channel_id, packet) in forwarded.drain(..) {
+        let Some(&client_entity) = client_entities.get(&peer_id) else {
             continue;
         };
-        let mut buf = [0u8; 1];
-        let packet: Packet =
-            to_packet(&SystemChannelMessage::HostRequestsDisconnect, &mut buf).into();
-        server
-            .socket
-            .channel_mut(SYSTEM_CHANNEL_ID)
-            .send(packet, peer_id);
+        if let Ok((_, _, _, mut queue, ..)) = clients.get_mut(client_entity) {
+            queue.push(socket_channel_id, packet, None);
+        }
+    }
+
+    for (connection, mut buckets, mut pacer, mut queue, mut failures, mut stats, mut ticks) in
+        &mut clients
+    {
+        let rates_updated = stats.update_rates(time.elapsed());
+        if !client_entities.contains_key(&connection.peer_id) {
+            continue;
+        }
+        if let Some(counts) = ticks.take() {
+            // postcard varints take up to 10 bytes per channel ID and 5 per count
+            let max_size = 4 + 15 * counts.len();
+            let packet = to_vec_packet(&SystemChannelMessage::TickBarrier { counts }, max_size);
+            socket
+                .channel_mut(SYSTEM_CHANNEL_ID)
+                .send(packet, connection.peer_id);
+        }
+        if rates_updated {
+            let packet = system_packet(&SystemChannelMessage::ReceiveReport {
+                bytes_per_second: stats.received_bytes_per_second as u32,
+            });
+            socket
+                .channel_mut(SYSTEM_CHANNEL_ID)
+                .send(packet, connection.peer_id);
+            let packet = system_packet(&SystemChannelMessage::Ping {
+                sent_at_micros: time.elapsed().as_micros() as u64,
+            });
+            socket
+                .channel_mut(SYSTEM_CHANNEL_ID)
+                .send(packet, connection.peer_id);
+        }
+        buckets.release(
+            &config.channel_limits,
+            time.delta(),
+            |channel_id, packet| enqueue(&mut queue, config, channel_id, packet, time.elapsed()),
+        );
+        pacer.release(
+            &config.channel_pacing,
+            time.delta(),
+            |channel_id, packet| enqueue(&mut queue, config, channel_id, packet, time.elapsed()),
+        );
+        let mut send = |socket_channel_id: usize, packet: Packet| {
+            // the queue holds socket channels, replicon's follow the system channel
+            let channel_id = socket_channel_id - 1;
+            if channel_id >= layout.server_channel_count() {
+                // forwarded from another client, see `MatchboxHost::forward`
+                socket
+                    .channel_mut(socket_channel_id)
+                    .send(packet, connection.peer_id);
+                return;
+            }
+            stats.record_sent(channel_id, packet.len());
+            failures.send(
+                socket,
+                &mut stats,
+                config.send_failure_policy(channel_id),
+                socket_channel_id,
+                packet,
+                connection.peer_id,
+            );
+        };
+        match &config.bandwidth_limit {
+            Some(bandwidth_limit) => {
+                let bytes_per_second =
+                    bandwidth_limit.bytes_per_second(time.elapsed() - queue.created_at);
+                queue.refill(
+                    (bytes_per_second as f64 * time.delta_secs_f64()) as u64,
+                    bandwidth_limit.max_carry_over.into(),
+                );
+                *dropped_messages += queue.discard_expired(time.elapsed()) as u64;
+                while let Some((socket_channel_id, packet)) = queue.pop() {
+                    send(socket_channel_id, packet);
+                }
+            }
+            None => {
+                for (socket_channel_id, packet) in queue.drain() {
+                    send(socket_channel_id, packet);
+                }
+            }
+        }
+        if failures.take_disconnect() {
+            clients_to_disconnect.push((connection.peer_id, DisconnectKind::SendFailed));
+        }
+    }
+
+    for (peer_id, kind) in clients_to_disconnect.drain(..) {
+        let Some(client_entity) = client_entities.remove(&peer_id) else {
+            continue;
+        };
+        if let Ok((_, mut buckets, mut pacer, mut queue, ..)) = clients.get_mut(client_entity) {
+            // flush so the client receives everything sent before the disconnect
+            for (socket_channel_id, packet) in queue.drain() {
+                socket.channel_mut(socket_channel_id).send(packet, peer_id);
+            }
+            for (channel_id, packet) in buckets.drain().chain(pacer.drain()) {
+                socket.channel_mut(1 + channel_id).send(packet, peer_id);
+            }
+        }
+        let reason = match &kind {
+            DisconnectKind::Kicked { reason } => reason.clone(),
+            _ => None,
+        };
+        let packet = system_packet(&SystemChannelMessage::HostRequestsDisconnect { reason });
+        socket.channel_mut(SYSTEM_CHANNEL_ID).send(packet, peer_id);
         trace!("disconnecting client `{}`", client_entity);
         commands.entity(client_entity).despawn();
+        closing.insert(
+            peer_id,
+            ClosingClient {
+                client_entity,
+                kind,
+                since: time.elapsed(),
+            },
+        );
+    }
+
+    host_stats.dropped_messages = *dropped_messages;
+    host_stats.aggregate(
+        clients
+            .iter()
+            .filter(|(connection, ..)| client_entities.contains_key(&connection.peer_id))
+            .map(|(.., stats, _)| stats),
+    );
+}
+
+fn update_bandwidth_report(
+    mut server: ResMut<MatchboxHost>,
+    mut reports: ResMut<BandwidthReports>,
+    mut stats: Query<&mut ConnectionStats>,
+    time: Res<Time<Real>>,
+) {
+    let Some(window) = server.config.bandwidth_profile_window else {
+        return;
+    };
+    for mut stats in &mut stats {
+        server.profile.collect(&mut stats);
+    }
+    if let Some(report) = server.profile.finish(
+        window,
+        time.elapsed(),
+        MessageKind::of_server_channel,
+        MessageKind::of_client_channel,
+    ) {
+        reports.host = Some(report);
+    }
+}
+
+fn log_stats(
+    mut server: ResMut<MatchboxHost>,
+    clients: Query<(&MatchboxClientConnection, &ConnectionStats)>,
+    time: Res<Time<Real>>,
+) {
+    let MatchboxHost {
+        client_entities,
+        stats_log,
+        ..
+    } = &mut *server;
+    log_snapshot(
+        stats_log,
+        time.elapsed(),
+        clients
+            .iter()
+            .filter(|(connection, _)| client_entities.contains_key(&connection.peer_id))
+            .map(|(connection, stats)| (connection.peer_id, stats)),
+    );
+}
+
+/// Queues a packet of a replicon channel, ahead of throttled traffic if the channel is
+/// unthrottled.
+fn enqueue(
+    queue: &mut SendQueue,
+    config: &MatchboxConfig,
+    channel_id: usize,
+    packet: Packet,
+    now: Duration,
+) {
+    if config.unthrottled_channels.contains(&channel_id) {
+        queue.push_unthrottled(1 + channel_id, packet);
+    } else {
+        let expires_at = config.send_ttl.get(&channel_id).map(|&ttl| now + ttl);
+        queue.push(1 + channel_id, packet, expires_at);
     }
 }
 
@@ -208,7 +1146,9 @@ fn received_disconnect(
             "queuing disconnecting client `{}` by request",
             event.client_entity
         );
-        server.clients_to_disconnect.push(connection.peer_id);
+        server
+            .clients_to_disconnect
+            .push((connection.peer_id, DisconnectKind::Kicked { reason: None }));
     }
 }
 
@@ -217,21 +1157,194 @@ fn received_disconnect(
 pub struct MatchboxHost {
     pub socket: MatchboxSocket,
     pub client_entities: HashMap<PeerId, Entity>,
-    pub clients_to_disconnect: Vec<PeerId>,
+    pub clients_to_disconnect: Vec<(PeerId, DisconnectKind)>,
+    closing: HashMap<PeerId, ClosingClient>,
+    throttle: ConnectionThrottle,
+    config: MatchboxConfig,
+    held_packets: Vec<(Entity, usize, Bytes)>,
+    /// Reliable replicon messages for clients awaiting a rejoin, by client entity.
+    rejoin_backlog: HashMap<Entity, Vec<(usize, Bytes)>>,
+    /// Backlogs of rejoined clients, sent ahead of new messages.
+    resumed: Vec<(Entity, usize, Bytes)>,
+    /// Packets forwarded from one client to another with their socket channel, queued in
+    /// the [`SendQueue`] of the recipient in the send schedule.
+    forwarded: Vec<(PeerId, usize, Packet)>,
+    pub(crate) layout: ChannelLayout,
+    pub(crate) voice: VoiceState,
+    pub(crate) transfers: Transfers,
+    pub(crate) user_handles: UserChannelHandles,
+    pub(crate) chat: ChatState,
+    pub(crate) deliveries: PendingDeliveries,
+    mutes: ChannelMutes<Entity>,
+    relay_filter: Option<RelayFilter>,
+    capture: Option<PacketCapture>,
+    stats_log: Option<StatsLog>,
+    /// Peers beyond [`MatchboxConfig::max_clients`] in the waiting room, in arrival order.
+    waiting: VecDeque<PeerId>,
+    /// Connected peers that haven't sent `SystemChannelMessage::Join` yet, with the time they
+    /// connected at.
+    joining: HashMap<PeerId, Duration>,
+    /// Received `SystemChannelMessage::Join`s with the session token to rejoin with, admitted
+    /// in the send schedule.
+    joins: Vec<(PeerId, Option<u64>)>,
+    dropped_messages: u64,
+    stats: HostStats,
+    profile: BandwidthProfile,
+    protocol_hash: Option<ProtocolHash>,
 }
 
 impl MatchboxHost {
+    /// Fails with [`io::ErrorKind::InvalidInput`] if `room_url` isn't a valid
+    /// [`RoomUrl`](crate::RoomUrl).
     pub fn new(
-        room_url: impl Into<String>,
+        room_url: impl IntoRoomUrl,
         replicon_channels: &RepliconChannels,
     ) -> io::Result<Self> {
-        let socket = create_matchbox_socket(room_url, replicon_channels);
+        Self::with_config(room_url, replicon_channels, MatchboxConfig::default())
+    }
+
+    pub fn with_config(
+        room_url: impl IntoRoomUrl,
+        replicon_channels: &RepliconChannels,
+        config: MatchboxConfig,
+    ) -> io::Result<Self> {
+        let room_url = parse_room_url(room_url)?;
+        validate_config(
+            &config,
+            replicon_channels,
+            replicon_channels.server_channels().len(),
+        )?;
+        config
+            .validate_send_ttl(replicon_channels.server_channels())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let socket = create_matchbox_socket(room_url, replicon_channels, &config);
+        let layout = ChannelLayout::new(replicon_channels, &config);
 
         Ok(Self {
             socket,
             // unreliable_socket,
             client_entities: HashMap::new(),
             clients_to_disconnect: Vec::new(),
+            closing: HashMap::new(),
+            throttle: ConnectionThrottle::default(),
+            config,
+            held_packets: Vec::new(),
+            rejoin_backlog: HashMap::new(),
+            resumed: Vec::new(),
+            forwarded: Vec::new(),
+            layout,
+            voice: VoiceState::default(),
+            transfers: Transfers::default(),
+            user_handles: UserChannelHandles::default(),
+            chat: ChatState::default(),
+            deliveries: PendingDeliveries::default(),
+            mutes: ChannelMutes::default(),
+            relay_filter: None,
+            capture: None,
+            stats_log: None,
+            dropped_messages: 0,
+            waiting: VecDeque::new(),
+            joining: HashMap::new(),
+            joins: Vec::new(),
+            stats: HostStats::default(),
+            profile: BandwidthProfile::default(),
+            protocol_hash: None,
+        })
+    }
+
+    /// Starts a signaling server at `bind_addr` and creates a host in `room_name` on it.
+    ///
+    /// Insert both as resources, the server stops when [`EmbeddedSignalingServer`] is dropped.
+    /// With port zero the OS picks a free port, advertise it with
+    /// [`EmbeddedSignalingServer::room_url`].
+    #[cfg(feature = "signaling")]
+    pub fn with_embedded_signaling(
+        bind_addr: std::net::SocketAddr,
+        room_name: &str,
+        replicon_channels: &RepliconChannels,
+    ) -> io::Result<(Self, EmbeddedSignalingServer)> {
+        let mut signaling = EmbeddedSignalingServer::new(bind_addr, None);
+        signaling.start().map_err(io::Error::other)?;
+        let room_url = signaling
+            .room_url(room_name)
+            .expect("server should be running after start");
+        let host = Self::new(room_url, replicon_channels)?;
+        Ok((host, signaling))
+    }
+
+    /// Sets a filter for payloads clients send each other through the host.
+    ///
+    /// Payloads are only forwarded when the filter returns `true`. Without a filter
+    /// everything between connected clients is forwarded.
+    pub fn set_relay_filter(
+        &mut self,
+        filter: impl Fn(PeerId, PeerId, &[u8]) -> bool + Send + Sync + 'static,
+    ) {
+        self.relay_filter = Some(Box::new(filter));
+    }
+
+    pub fn config(&self) -> &MatchboxConfig {
+        &self.config
+    }
+
+    /// Records all replicon messages exchanged with clients to `writer`.
+    ///
+    /// Replaces a running capture. See [`CaptureReader`] for the format.
+    pub fn start_capture(
+        &mut self,
+        writer: impl std::io::Write + Send + Sync + 'static,
+    ) -> io::Result<()> {
+        self.capture = Some(PacketCapture::new(Box::new(writer))?);
+        Ok(())
+    }
+
+    /// Stops recording and flushes the capture.
+    pub fn stop_capture(&mut self) -> io::Result<()> {
+        match self.capture.take() {
+            Some(mut capture) => capture.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Appends per-client and per-channel [`ConnectionStats`] snapshots as CSV to `writer`
+    /// every `interval`, e.g. to a `File` opened in append mode to analyze a play session
+    /// afterwards.
+    ///
+    /// Replaces a running log. The columns are described by the header line the log starts
+    /// with, counters are totals since each client connected.
+    pub fn start_stats_log(
+        &mut self,
+        writer: impl std::io::Write + Send + Sync + 'static,
+        interval: Duration,
+    ) -> io::Result<()> {
+        self.stats_log = Some(StatsLog::new(Box::new(writer), interval)?);
+        Ok(())
+    }
+
+    /// Stops logging and flushes the stats log.
+    pub fn stop_stats_log(&mut self) -> io::Result<()> {
+        match self.stats_log.take() {
+            Some(mut log) => log.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Totals across all clients, updated each frame after sending.
+    pub fn stats(&self) -> &HostStats {
+        &self.stats
+    }
+
+    fn allow_attempt(&mut self, session_token: u64, now: Duration) -> bool {
+        let Some(throttle) = &self.config.reconnect_throttle else {
+            return true;
+        };
+        self.throttle.attempt(throttle, session_token, now)
+    }
+
+    fn connected_packet(&self, session_token: u64) -> Packet {
+        system_packet(&SystemChannelMessage::ConnectedToHost {
+            session_token,
+            protocol_hash: self.protocol_hash,
         })
     }
 
@@ -239,13 +1352,301 @@ impl MatchboxHost {
         self.client_entities.len()
     }
 
+    /// Peers queued in the waiting room, see [`MatchboxConfig::waiting_room`].
+    pub fn waiting_peers(&self) -> impl Iterator<Item = PeerId> + '_ {
+        self.waiting.iter().copied()
+    }
+
+    /// Clients awaiting a rejoin count as well, their slot stays reserved.
+    fn is_full(&self) -> bool {
+        self.config.max_clients.is_some_and(|max_clients| {
+            self.client_entities.len() + self.rejoin_backlog.len() >= max_clients
+        })
+    }
+
+    /// Asks a peer without a client entity to disconnect.
+    fn reject(&mut self, peer_id: PeerId, reason: Option<String>) {
+        let packet = system_packet(&SystemChannelMessage::HostRequestsDisconnect { reason });
+        self.socket
+            .channel_mut(SYSTEM_CHANNEL_ID)
+            .send(packet, peer_id);
+    }
+
+    /// Sends a packet of another client on, subject to the recipient's
+    /// [`MatchboxConfig::bandwidth_limit`] like replicon messages.
+    pub(crate) fn forward(&mut self, peer_id: PeerId, socket_channel_id: usize, packet: Packet) {
+        self.forwarded.push((peer_id, socket_channel_id, packet));
+    }
+
+    /// Sends the messages kept for a client while it was away ahead of new ones.
+    fn resume_backlog(&mut self, client_entity: Entity) {
+        let Some(backlog) = self.rejoin_backlog.remove(&client_entity) else {
+            return;
+        };
+        trace!(
+            "resending {} messages to client {client_entity}",
+            backlog.len()
+        );
+        self.resumed.extend(
+            backlog
+                .into_iter()
+                .map(|(channel_id, message)| (client_entity, channel_id, message)),
+        );
+    }
+
+    /// Tells the peer at `index` of the waiting room its position.
+    fn send_queue_position(&mut self, index: usize) {
+        let packet = system_packet(&SystemChannelMessage::Queued {
+            position: index as u32 + 1,
+        });
+        self.socket
+            .channel_mut(SYSTEM_CHANNEL_ID)
+            .send(packet, self.waiting[index]);
+    }
+
+    /// Disconnects a client, showing it `reason` as [`DisconnectKind::Kicked`].
+    ///
+    /// The reason arrives reliably, but the client closes its socket as soon as it does.
+    /// Each data channel is ordered on its own, so messages sent before the kick can still
+    /// be in flight on other channels and are lost then. Reasons longer than 200 bytes are
+    /// truncated.
+    pub fn kick(&mut self, client_entity: Entity, reason: impl Into<String>) {
+        let Some(peer_id) = self
+            .client_entities
+            .iter()
+            .find_map(|(&peer_id, &entity)| (entity == client_entity).then_some(peer_id))
+        else {
+            return;
+        };
+        let mut reason = reason.into();
+        if reason.len() > MAX_KICK_REASON_LEN {
+            let mut len = MAX_KICK_REASON_LEN;
+            while !reason.is_char_boundary(len) {
+                len -= 1;
+            }
+            reason.truncate(len);
+        }
+        self.clients_to_disconnect.push((
+            peer_id,
+            DisconnectKind::Kicked {
+                reason: Some(reason),
+            },
+        ));
+    }
+
+    /// Disconnected clients that haven't acknowledged the disconnect yet.
+    ///
+    /// Wait for it to reach zero before removing the host, otherwise the final messages to
+    /// these clients may never arrive.
+    pub fn pending_disconnects(&self) -> usize {
+        self.closing.len()
+    }
+
+    /// Mutes a replicon channel for all clients until [`Self::unmute`], e.g. to pause bulk
+    /// replication during a loading screen while events keep flowing.
+    ///
+    /// See [`MutedChannel`] for how each direction is treated. Mutes of single clients from
+    /// [`Self::mute_client`] apply on top.
+    pub fn mute(&mut self, channel: MutedChannel) {
+        self.mutes.mute(None, channel);
+    }
+
+    pub fn unmute(&mut self, channel: MutedChannel) {
+        self.mutes.unmute(None, channel);
+    }
+
+    /// Mutes a replicon channel for one client until [`Self::unmute_client`].
+    pub fn mute_client(&mut self, client_entity: Entity, channel: MutedChannel) {
+        self.mutes.mute(Some(client_entity), channel);
+    }
+
+    pub fn unmute_client(&mut self, client_entity: Entity, channel: MutedChannel) {
+        self.mutes.unmute(Some(client_entity), channel);
+    }
+
+    /// Messages held back on muted outbound channels, they pile up while a channel stays
+    /// muted.
+    pub fn muted_messages(&self) -> usize {
+        self.mutes.held_messages()
+    }
+
     pub fn disconnect_all(&mut self) {
-        self.clients_to_disconnect
-            .extend(self.client_entities.keys().cloned());
+        self.clients_to_disconnect.extend(
+            self.client_entities
+                .keys()
+                .map(|&peer_id| (peer_id, DisconnectKind::LocalClosed)),
+        );
+    }
+}
+
+/// Summarizes the socket state, omitting the socket and relay filter themselves.
+impl std::fmt::Debug for MatchboxHost {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MatchboxHost")
+            .field("client_entities", &self.client_entities)
+            .field("clients_to_disconnect", &self.clients_to_disconnect)
+            .field("closing", &self.closing)
+            .field("config", &self.config)
+            .field("layout", &self.layout)
+            .field("held_packets", &self.held_packets.len())
+            .field("rejoin_backlogs", &self.rejoin_backlog.len())
+            .field("muted_messages", &self.mutes.held_messages())
+            .field("relay_filter", &self.relay_filter.is_some())
+            .field("capturing", &self.capture.is_some())
+            .field("logging_stats", &self.stats_log.is_some())
+            .field("waiting", &self.waiting)
+            .field("joining", &self.joining)
+            .field("dropped_messages", &self.dropped_messages)
+            .field("stats", &self.stats)
+            .field("protocol_hash", &self.protocol_hash)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Settings of a [`MatchboxHost`] that is created once the app runs, when all replicon
+/// channels are registered.
+///
+/// Insert it during setup, e.g. from a plugin, instead of ordering a startup system after
+/// every plugin that registers channels. The host replaces it in the first
+/// [`RepliconMatchboxServerPlugin::receive_schedule`] run; an invalid room URL or
+/// configuration fails that system with the constructor's error.
+#[derive(Resource)]
+pub struct DeferredMatchboxHost {
+    room_url: String,
+    config: MatchboxConfig,
+}
+
+impl DeferredMatchboxHost {
+    pub fn new(room_url: impl Into<String>) -> Self {
+        Self::with_config(room_url, MatchboxConfig::default())
+    }
+
+    pub fn with_config(room_url: impl Into<String>, config: MatchboxConfig) -> Self {
+        Self {
+            room_url: room_url.into(),
+            config,
+        }
+    }
+}
+
+/// Command that creates a [`MatchboxHost`] with the world's current [`RepliconChannels`].
+///
+/// Unlike [`MatchboxHost::with_config`], setup code doesn't need the channels as a system
+/// parameter and can't pass an outdated copy. An invalid room URL or configuration fails
+/// the command with the constructor's error.
+///
+/// ```no_run
+/// # use bevy::prelude::*;
+/// # use bevy_replicon_matchbox::*;
+/// fn host(mut commands: Commands) {
+///     commands.queue(StartHost::new("ws://localhost:3536/game"));
+/// }
+/// ```
+pub struct StartHost {
+    pub room_url: String,
+    pub config: MatchboxConfig,
+}
+
+impl StartHost {
+    pub fn new(room_url: impl Into<String>) -> Self {
+        Self {
+            room_url: room_url.into(),
+            config: MatchboxConfig::default(),
+        }
+    }
+}
+
+impl Command<Result> for StartHost {
+    fn apply(self, world: &mut World) -> Result {
+        let channels = world.resource::<RepliconChannels>();
+        let host = MatchboxHost::with_config(self.room_url, channels, self.config)?;
+        world.insert_resource(host);
+        Ok(())
+    }
+}
+
+/// Prints the room URL redacted.
+impl std::fmt::Debug for DeferredMatchboxHost {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DeferredMatchboxHost")
+            .field("room_url", &redact_room_url(&self.room_url))
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+/// Marks a client entity whose peer dropped and that awaits a rejoin.
+///
+/// Only inserted when [`MatchboxConfig::rejoin_grace_period`] is non-zero. The entity is
+/// despawned once the grace period expires.
+#[derive(Component, Debug, Clone)]
+pub struct PeerDisconnected {
+    /// [`Time<Real>`] elapsed time at which the peer dropped.
+    pub since: Duration,
+    pub kind: DisconnectKind,
+}
+
+/// Sent on the host when the connection of a client ended.
+///
+/// Clients the host disconnects are despawned immediately, but the event is only sent once
+/// they acknowledged, see [`MatchboxConfig::disconnect_ack_timeout`]. Not sent for clients
+/// that drop while a rejoin grace period is configured, only once the grace period
+/// expires, see [`PeerDisconnected`].
+#[derive(Event, Debug, Clone)]
+pub struct ClientDisconnected {
+    pub client_entity: Entity,
+    pub peer_id: PeerId,
+    pub kind: DisconnectKind,
+}
+
+/// Sent on the host when a client stopped answering pings while its connection still
+/// looks open.
+///
+/// The client is disconnected with [`DisconnectKind::Timeout`] unless it responds again
+/// within [`UnresponsiveTimeout::grace_period`](crate::UnresponsiveTimeout::grace_period),
+/// see [`MatchboxConfig::unresponsive_timeout`].
+#[derive(Event, Debug, Clone, Copy)]
+pub struct PeerUnresponsive {
+    pub client_entity: Entity,
+    pub peer_id: PeerId,
+}
+
+/// Advisory send interval for a client, derived from its bandwidth estimate.
+///
+/// Sent about once per second while the traffic to the client doesn't fit its link or the
+/// configured [`MatchboxConfig::bandwidth_limit`], or when there is room to send more often.
+/// Apply it by adjusting the replicon tick rate or visibility for this client; the next
+/// suggestions take the new traffic into account. See
+/// [`ConnectionStats::suggested_send_interval`].
+#[derive(Event, Debug, Clone, Copy)]
+pub struct SuggestedSendInterval {
+    pub client_entity: Entity,
+    pub interval: Duration,
+}
+
+/// Client the host disconnected, awaiting the acknowledgement.
+#[derive(Debug)]
+struct ClosingClient {
+    client_entity: Entity,
+    kind: DisconnectKind,
+    since: Duration,
+}
+
+impl ClosingClient {
+    fn into_event(self, peer_id: PeerId) -> ClientDisconnected {
+        ClientDisconnected {
+            client_entity: self.client_entity,
+            peer_id,
+            kind: self.kind,
+        }
     }
 }
 
-#[derive(Component)]
-struct MatchboxClientConnection {
+/// Matchbox identity of a connected client entity.
+#[derive(Component, Debug)]
+pub struct MatchboxClientConnection {
+    /// Current peer of the client, updated when the client rejoins.
     pub peer_id: PeerId,
+    /// Token the client presents to rejoin as this entity.
+    pub(crate) session_token: u64,
 }
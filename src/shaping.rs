@@ -0,0 +1,380 @@
+use bevy::prelude::*;
+use bevy_matchbox::matchbox_socket::Packet;
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+/// Limits how fast the host sends to each client.
+///
+/// Messages over the limit are queued and sent in order on later frames, so a limit that is
+/// permanently below the produced traffic makes the queue grow without bound.
+#[derive(Clone, Debug)]
+pub struct BandwidthLimit {
+    /// Bytes per second sent to a client.
+    pub bytes_per_second: u32,
+    /// Elevated limit while a client is freshly connected, so initial replication finishes
+    /// quickly without permanently raising [`Self::bytes_per_second`].
+    pub initial_sync_bytes_per_second: u32,
+    /// How long after connecting [`Self::initial_sync_bytes_per_second`] applies, zero
+    /// disables the elevated limit.
+    pub initial_sync_duration: Duration,
+    /// Bytes of unused budget carried over from quiet frames.
+    ///
+    /// Lets bursts after quiet frames go out at once instead of being spread over the
+    /// following frames, up to this cap.
+    pub max_carry_over: u32,
+}
+
+impl BandwidthLimit {
+    /// Limit for a client that connected `connected_for` ago.
+    pub fn bytes_per_second(&self, connected_for: Duration) -> u32 {
+        if connected_for < self.initial_sync_duration {
+            self.initial_sync_bytes_per_second
+        } else {
+            self.bytes_per_second
+        }
+    }
+}
+
+/// Token bucket limiting the traffic of a single channel.
+///
+/// Messages over the limit are delayed, not dropped, so reliable channels stay intact.
+#[derive(Clone, Copy, Debug)]
+pub struct TokenBucket {
+    /// Sustained rate in bytes per second.
+    pub bytes_per_second: u32,
+    /// Bytes that can be sent at once after the channel was idle, must not be zero.
+    pub burst: u32,
+}
+
+/// Per-channel token buckets of a single peer, keyed by replicon channel ID.
+#[derive(Component, Default)]
+pub(crate) struct ChannelBuckets {
+    buckets: HashMap<usize, BucketState>,
+}
+
+struct BucketState {
+    tokens: f64,
+    packets: VecDeque<Packet>,
+}
+
+impl ChannelBuckets {
+    pub(crate) fn push(&mut self, channel_id: usize, bucket: &TokenBucket, packet: Packet) {
+        self.buckets
+            .entry(channel_id)
+            .or_insert_with(|| BucketState {
+                tokens: bucket.burst.into(),
+                packets: VecDeque::new(),
+            })
+            .packets
+            .push_back(packet);
+    }
+
+    /// Refills the buckets for the elapsed `delta` and passes packets that fit to `send`.
+    pub(crate) fn release(
+        &mut self,
+        limits: &HashMap<usize, TokenBucket>,
+        delta: Duration,
+        mut send: impl FnMut(usize, Packet),
+    ) {
+        for (&channel_id, state) in &mut self.buckets {
+            let Some(bucket) = limits.get(&channel_id) else {
+                // limit was removed, let everything through
+                state
+                    .packets
+                    .drain(..)
+                    .for_each(|packet| send(channel_id, packet));
+                continue;
+            };
+            state.tokens = (state.tokens + bucket.bytes_per_second as f64 * delta.as_secs_f64())
+                .min(bucket.burst.into());
+            while state.tokens > 0.0 {
+                let Some(packet) = state.packets.pop_front() else {
+                    break;
+                };
+                state.tokens -= packet.len() as f64;
+                send(channel_id, packet);
+            }
+        }
+    }
+
+    /// Removes all queued packets regardless of the limits.
+    pub(crate) fn drain(&mut self) -> impl Iterator<Item = (usize, Packet)> + '_ {
+        self.buckets.iter_mut().flat_map(|(&channel_id, state)| {
+            state
+                .packets
+                .drain(..)
+                .map(move |packet| (channel_id, packet))
+        })
+    }
+}
+
+/// Per-channel pacing of a single peer, keyed by replicon channel ID.
+///
+/// Spreads the packets a channel produced over the configured interval at a steady rate,
+/// instead of handing a whole tick's output to the socket at once.
+#[derive(Component, Default)]
+pub(crate) struct ChannelPacer {
+    channels: HashMap<usize, PacedChannel>,
+}
+
+#[derive(Default)]
+struct PacedChannel {
+    packets: VecDeque<Packet>,
+    queued_bytes: usize,
+    /// Bytes per second to empty the queue within the interval.
+    rate: f64,
+    budget: f64,
+}
+
+impl ChannelPacer {
+    pub(crate) fn push(&mut self, channel_id: usize, interval: Duration, packet: Packet) {
+        let channel = self.channels.entry(channel_id).or_default();
+        channel.queued_bytes += packet.len();
+        channel.packets.push_back(packet);
+        // new packets during a paced burst only speed it up, so the older ones aren't
+        // delayed beyond their interval
+        channel.rate = channel
+            .rate
+            .max(channel.queued_bytes as f64 / interval.as_secs_f64());
+    }
+
+    /// Adds the budget for the elapsed `delta` and passes packets that fit to `send`.
+    pub(crate) fn release(
+        &mut self,
+        pacing: &HashMap<usize, Duration>,
+        delta: Duration,
+        mut send: impl FnMut(usize, Packet),
+    ) {
+        for (&channel_id, channel) in &mut self.channels {
+            if !pacing.contains_key(&channel_id) {
+                // pacing was disabled, let everything through
+                channel.budget = f64::INFINITY;
+            } else {
+                channel.budget += channel.rate * delta.as_secs_f64();
+            }
+            while channel.budget > 0.0 {
+                let Some(packet) = channel.packets.pop_front() else {
+                    break;
+                };
+                channel.budget -= packet.len() as f64;
+                channel.queued_bytes -= packet.len();
+                send(channel_id, packet);
+            }
+            if channel.packets.is_empty() {
+                // the next burst starts without leftover budget
+                channel.rate = 0.0;
+                channel.budget = channel.budget.min(0.0);
+            }
+        }
+    }
+
+    /// Removes all queued packets regardless of pacing.
+    pub(crate) fn drain(&mut self) -> impl Iterator<Item = (usize, Packet)> + '_ {
+        self.channels.iter_mut().flat_map(|(&channel_id, channel)| {
+            channel.queued_bytes = 0;
+            channel.rate = 0.0;
+            channel
+                .packets
+                .drain(..)
+                .map(move |packet| (channel_id, packet))
+        })
+    }
+}
+
+/// Outgoing packets of a single peer waiting for send budget.
+#[derive(Component)]
+pub(crate) struct SendQueue {
+    /// Packets with the time after which they are discarded instead of sent.
+    packets: VecDeque<(usize, Packet, Option<Duration>)>,
+    /// Packets sent regardless of budget, ahead of [`Self::packets`].
+    unthrottled: VecDeque<(usize, Packet)>,
+    budget: i64,
+    pub(crate) created_at: Duration,
+}
+
+impl SendQueue {
+    pub(crate) fn new(created_at: Duration) -> Self {
+        Self {
+            packets: VecDeque::new(),
+            unthrottled: VecDeque::new(),
+            budget: 0,
+            created_at,
+        }
+    }
+
+    pub(crate) fn push(
+        &mut self,
+        socket_channel_id: usize,
+        packet: Packet,
+        expires_at: Option<Duration>,
+    ) {
+        self.packets
+            .push_back((socket_channel_id, packet, expires_at));
+    }
+
+    /// Queues a packet that is sent even without budget, still spending it so the other
+    /// packets make room.
+    pub(crate) fn push_unthrottled(&mut self, socket_channel_id: usize, packet: Packet) {
+        self.unthrottled.push_back((socket_channel_id, packet));
+    }
+
+    /// Adds `bytes` to the budget of this frame, keeping up to `max_carry_over` unused bytes
+    /// from previous frames.
+    pub(crate) fn refill(&mut self, bytes: u64, max_carry_over: u64) {
+        let bytes = bytes as i64;
+        self.budget = (self.budget + bytes).min(bytes + max_carry_over as i64);
+    }
+
+    /// Pops the next packet while budget is left.
+    ///
+    /// A packet larger than the remaining budget is still sent and the deficit is paid off
+    /// by the following refills, so packets larger than a single frame's budget can't stall.
+    pub(crate) fn pop(&mut self) -> Option<(usize, Packet)> {
+        let (socket_channel_id, packet) = match self.unthrottled.pop_front() {
+            Some(unthrottled) => unthrottled,
+            None if self.budget <= 0 => return None,
+            None => {
+                let (socket_channel_id, packet, _) = self.packets.pop_front()?;
+                (socket_channel_id, packet)
+            }
+        };
+        self.budget -= packet.len() as i64;
+        Some((socket_channel_id, packet))
+    }
+
+    /// Discards packets that expired before `now`, returns how many.
+    pub(crate) fn discard_expired(&mut self, now: Duration) -> usize {
+        let len = self.packets.len();
+        self.packets
+            .retain(|&(.., expires_at)| expires_at.is_none_or(|expires_at| expires_at >= now));
+        len - self.packets.len()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.unthrottled.len() + self.packets.len()
+    }
+
+    /// Removes all queued packets regardless of budget.
+    pub(crate) fn drain(&mut self) -> impl Iterator<Item = (usize, Packet)> + '_ {
+        self.unthrottled.drain(..).chain(
+            self.packets
+                .drain(..)
+                .map(|(socket_channel_id, packet, _)| (socket_channel_id, packet)),
+        )
+    }
+}
+
+#[test]
+fn test_send_queue_budget() {
+    let mut queue = SendQueue::new(Duration::ZERO);
+    for _ in 0..3 {
+        queue.push(1, vec![0; 10].into(), None);
+    }
+
+    assert!(queue.pop().is_none(), "no budget before the first refill");
+
+    queue.refill(15, 0);
+    assert!(queue.pop().is_some());
+    assert!(
+        queue.pop().is_some(),
+        "budget left, packet is sent on deficit"
+    );
+    assert!(queue.pop().is_none());
+
+    queue.refill(15, 0);
+    assert_eq!(queue.budget, 10, "deficit is paid off");
+    queue.refill(15, 0);
+    assert_eq!(queue.budget, 15, "unused budget doesn't carry over");
+    assert!(queue.pop().is_some());
+    assert!(queue.drain().next().is_none());
+}
+
+#[test]
+fn test_send_queue_unthrottled() {
+    let mut queue = SendQueue::new(Duration::ZERO);
+    queue.push(1, vec![0; 10].into(), None);
+    queue.push_unthrottled(2, vec![0; 10].into());
+    queue.push_unthrottled(2, vec![0; 10].into());
+
+    assert_eq!(queue.pop().map(|(channel, _)| channel), Some(2));
+    assert_eq!(queue.pop().map(|(channel, _)| channel), Some(2));
+    assert!(queue.pop().is_none(), "throttled packet waits for budget");
+
+    queue.refill(25, 0);
+    assert_eq!(queue.budget, 5, "unthrottled packets spent the budget");
+    assert_eq!(queue.pop().map(|(channel, _)| channel), Some(1));
+}
+
+#[test]
+fn test_send_queue_expiry() {
+    let mut queue = SendQueue::new(Duration::ZERO);
+    queue.push(1, vec![0; 10].into(), Some(Duration::from_millis(100)));
+    queue.push(1, vec![0; 10].into(), None);
+    queue.push(1, vec![0; 10].into(), Some(Duration::from_millis(300)));
+
+    assert_eq!(queue.discard_expired(Duration::from_millis(100)), 0);
+    assert_eq!(queue.discard_expired(Duration::from_millis(200)), 1);
+    assert_eq!(queue.len(), 2);
+    assert_eq!(queue.discard_expired(Duration::from_secs(1)), 1);
+    assert_eq!(queue.len(), 1, "packets without expiry stay");
+}
+
+#[test]
+fn test_send_queue_carry_over() {
+    let mut queue = SendQueue::new(Duration::ZERO);
+    for _ in 0..3 {
+        queue.refill(10, 25);
+    }
+    assert_eq!(queue.budget, 30);
+    queue.refill(10, 25);
+    assert_eq!(queue.budget, 35, "carry over is capped");
+}
+
+#[test]
+fn test_channel_buckets() {
+    let limits = HashMap::from([(
+        0,
+        TokenBucket {
+            bytes_per_second: 100,
+            burst: 20,
+        },
+    )]);
+    let mut buckets = ChannelBuckets::default();
+    for _ in 0..5 {
+        buckets.push(0, &limits[&0], vec![0; 10].into());
+    }
+
+    let mut sent = 0;
+    buckets.release(&limits, Duration::ZERO, |_, _| sent += 1);
+    assert_eq!(sent, 2, "burst is available right away");
+
+    buckets.release(&limits, Duration::from_millis(100), |_, _| sent += 1);
+    assert_eq!(sent, 3, "refilled at the sustained rate");
+
+    assert_eq!(buckets.drain().count(), 2);
+}
+
+#[test]
+fn test_channel_pacer() {
+    let interval = Duration::from_millis(100);
+    let pacing = HashMap::from([(0, interval)]);
+    let mut pacer = ChannelPacer::default();
+    for _ in 0..4 {
+        pacer.push(0, interval, vec![0; 10].into());
+    }
+
+    let mut sent = 0;
+    pacer.release(&pacing, Duration::from_millis(25), |_, _| sent += 1);
+    assert_eq!(sent, 1, "a quarter of the interval releases a quarter");
+
+    pacer.release(&pacing, Duration::from_millis(50), |_, _| sent += 1);
+    assert_eq!(sent, 3);
+
+    pacer.release(&pacing, Duration::from_millis(25), |_, _| sent += 1);
+    assert_eq!(sent, 4, "everything is sent within the interval");
+
+    pacer.push(0, interval, vec![0; 10].into());
+    pacer.release(&HashMap::new(), Duration::ZERO, |_, _| sent += 1);
+    assert_eq!(sent, 5, "disabled pacing lets everything through");
+    assert_eq!(pacer.drain().count(), 0);
+}
@@ -1,21 +1,633 @@
+use crate::chat::ChatLimits;
+use crate::delivery::DeliveryId;
+use crate::room_url::{IntoRoomUrl, RoomUrl};
+use crate::send_failure::SendFailurePolicy;
+use crate::shaping::{BandwidthLimit, TokenBucket};
+use crate::throttle::ReconnectThrottle;
+use crate::user_channel::Fanout;
+use crate::watchdog::UnresponsiveTimeout;
 use bevy::app::{PluginGroup, PluginGroupBuilder};
+use bevy::prelude::{Event, Fixed, Res, Time, Virtual};
 use bevy_matchbox::MatchboxSocket;
-use bevy_matchbox::matchbox_socket::{ChannelConfig, Packet};
+use bevy_matchbox::matchbox_socket::{ChannelConfig, Packet, PeerId, RtcIceServerConfig};
 use bevy_replicon::postcard;
 use bevy_replicon::prelude::{Channel, RepliconChannels};
+use bevy_replicon::shared::protocol::ProtocolHash;
 use bytes::Bytes;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::time::Duration;
 
 //Required to communicate which peer is the host before we start using replicon
 pub(super) const SYSTEM_CHANNEL_ID: usize = 0;
 
+/// Upper bound of a serialized [`SystemChannelMessage`].
+pub(super) const SYSTEM_MESSAGE_MAX_SIZE: usize = 256;
+
+/// Longest kick reason in bytes, longer ones are truncated.
+pub(super) const MAX_KICK_REASON_LEN: usize = 200;
+
+/// Data channels a peer connection can have.
+///
+/// Matchbox uses the channel index as SCTP stream ID, and browsers only negotiate 1024
+/// streams per association.
+const MAX_DATA_CHANNELS: usize = 1024;
+
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub(super) enum SystemChannelMessage {
-    ConnectedToHost,
-    HostRequestsDisconnect,
+    ConnectedToHost {
+        session_token: u64,
+        /// Replicon protocol of the host, see [`MatchboxConfig::check_protocol_hash`].
+        protocol_hash: Option<ProtocolHash>,
+    },
+    HostRequestsDisconnect {
+        reason: Option<String>,
+    },
+    /// Sent when the client leaves, also acknowledges a [`SystemChannelMessage::HostRequestsDisconnect`].
     ClientDisconnects,
+    /// Sent instead of [`SystemChannelMessage::ClientDisconnects`] when the client leaves
+    /// because its protocol differs from the host's.
+    ProtocolMismatch,
+    /// Sent by the host once the channels to a peer are open, asking it to join.
+    RequestJoin,
+    /// Answers [`SystemChannelMessage::RequestJoin`], the host only admits the peer after
+    /// this. Carries the session token of the previous connection when rejoining.
+    Join {
+        rejoin_token: Option<u64>,
+    },
+    /// Receive rate of the sending peer, used for bandwidth estimation.
+    ReceiveReport {
+        bytes_per_second: u32,
+    },
+    /// Round-trip time measurement, answered with a `Pong` carrying the same timestamp.
+    Ping {
+        sent_at_micros: u64,
+    },
+    Pong {
+        sent_at_micros: u64,
+    },
+    /// Position of the client in the host's waiting room, starting at one.
+    Queued {
+        position: u32,
+    },
+    /// Replicon messages the host sent per server channel since the previous barrier, see
+    /// [`MatchboxConfig::tick_barrier`].
+    TickBarrier {
+        counts: Vec<(usize, u32)>,
+    },
+    /// Acknowledges a user message sent with an acknowledgment request.
+    Delivered {
+        id: DeliveryId,
+    },
+}
+
+/// Socket configuration shared by the host and the client.
+///
+/// Durations and rates are measured with [`Time<Real>`](bevy::time::Real), so tests can step
+/// them deterministically with `TimeUpdateStrategy::ManualDuration` instead of sleeping. Only
+/// the signaling keep-alive and reconnect backoff run on wall-clock time inside matchbox.
+#[derive(Clone)]
+pub struct MatchboxConfig {
+    /// STUN/TURN servers used for ICE candidate gathering.
+    ///
+    /// Candidates are trickled as they are found, so there is no gathering timeout to tune.
+    /// Unreachable servers still delay connectivity checks, so only list servers that are
+    /// reachable from the players' networks. Relay candidates are only gathered when a TURN
+    /// server is configured.
+    pub ice_server: RtcIceServerConfig,
+    /// Interval of keep-alive messages on the signaling WebSocket.
+    ///
+    /// Peer connections are owned by the signaling loop, so losing the signaling connection
+    /// closes every data channel. Keep-alives prevent idle proxies and load balancers from
+    /// dropping an otherwise healthy session. `None` disables them.
+    pub signaling_keep_alive_interval: Option<Duration>,
+    /// Attempts to connect to the signaling server before giving up, `None` retries forever.
+    pub reconnect_attempts: Option<u16>,
+    /// How long the host keeps the entity of a dropped client around, awaiting a rejoin.
+    ///
+    /// During this window the entity keeps its replicon state and is marked with
+    /// `PeerDisconnected`. Messages replicon sends it on reliable channels meanwhile are kept
+    /// and sent once the client rejoins, so changes made during the gap still replicate;
+    /// only reliable messages that were in flight when the connection dropped are lost.
+    /// Clients that leave or are kicked are always despawned immediately. Zero disables the
+    /// grace period.
+    pub rejoin_grace_period: Duration,
+    /// How long the host waits for a disconnected client to acknowledge the disconnect.
+    ///
+    /// The kick reason arrives before the acknowledgement, messages on other channels aren't
+    /// waited for. `ClientDisconnected` is sent once the client acknowledged or the timeout
+    /// expired.
+    pub disconnect_ack_timeout: Duration,
+    /// Cool-downs for clients that keep reconnecting, `None` accepts every attempt.
+    pub reconnect_throttle: Option<ReconnectThrottle>,
+    /// Clients the host accepts at once, `None` accepts everyone. Ignored on the client.
+    ///
+    /// Clients awaiting a rejoin keep their slot and are let back in even while the host is
+    /// full, see [`Self::rejoin_grace_period`]. Peers beyond the limit are rejected with
+    /// `DisconnectKind::Kicked`, or queued if [`Self::waiting_room`] is enabled.
+    pub max_clients: Option<usize>,
+    /// Queues peers beyond [`Self::max_clients`] instead of rejecting them.
+    ///
+    /// Queued peers stay connected to the host without a client entity and are admitted in
+    /// the order they arrived as soon as a slot frees up. Clients are told their position
+    /// with `ConnectionProgress::Queued`.
+    pub waiting_room: bool,
+    /// Disconnects clients that stop answering the host's pings while their connection
+    /// still looks open, `None` (the default) relies on WebRTC to detect dead connections.
+    pub unresponsive_timeout: Option<UnresponsiveTimeout>,
+    /// How long a client waits for its data channels to the host to open after the signaling
+    /// server assigned it an ID, `None` waits forever.
+    ///
+    /// A connection can stall halfway, e.g. when only some channels get through a firewall,
+    /// or never start when none do. The client then disconnects with
+    /// [`DisconnectKind::ChannelOpenTimeout`], or fails over to the next of its
+    /// [`MatchboxClient::with_fallbacks`](crate::MatchboxClient::with_fallbacks). This also
+    /// limits how long a client waits for a host to join its room.
+    pub channel_open_timeout: Option<Duration>,
+    /// Times the client automatically rejoins after losing the host or signaling connection,
+    /// e.g. when switching from Wi-Fi to cellular. Zero disables automatic rejoins.
+    ///
+    /// The client reconnects with a new socket and resumes its session while the host still
+    /// awaits it, see [`Self::rejoin_grace_period`]. The replicon client stays in
+    /// `RepliconClientStatus::Connecting` meanwhile and keeps its replicated state. Reliable
+    /// messages that were in flight during the switch are lost. If the host no longer knows
+    /// the session, the client disconnects.
+    pub auto_rejoin_attempts: u16,
+    /// Limits how fast the host sends to each client, `None` sends everything immediately.
+    pub bandwidth_limit: Option<BandwidthLimit>,
+    /// Token buckets for individual channels, keyed by the replicon channel ID of the sending
+    /// side: server channels on the host and client channels on the client.
+    ///
+    /// On the host each client has its own buckets.
+    pub channel_limits: HashMap<usize, TokenBucket>,
+    /// Intervals to spread the messages of individual channels over, keyed like
+    /// [`Self::channel_limits`].
+    ///
+    /// Instead of handing a whole tick's output to the socket at once, messages are sent at
+    /// a steady rate that empties the queue within the interval, which reduces burst loss on
+    /// constrained links. Pacing spans frames, so it needs frames between ticks; use an
+    /// interval up to the tick interval to not delay the next tick. Channels with a token
+    /// bucket aren't paced.
+    pub channel_pacing: HashMap<usize, Duration>,
+    /// Channels whose messages are handed to the socket as soon as replicon sends them, keyed
+    /// like [`Self::channel_limits`].
+    ///
+    /// For latency-critical channels such as input commands. Their messages skip token
+    /// buckets, pacing and [`Self::bandwidth_limit`], so they are never held back behind
+    /// other traffic, at the cost of exceeding the limits.
+    pub immediate_channels: HashSet<usize>,
+    /// Server channels the host keeps sending on when [`Self::bandwidth_limit`] is exhausted,
+    /// e.g. for small, gameplay-critical triggers.
+    ///
+    /// Their messages go out ahead of throttled traffic and still count against the limit,
+    /// so replication is held back instead. Ignored on the client.
+    pub unthrottled_channels: HashSet<usize>,
+    /// Time messages of unreliable server channels may wait for [`Self::bandwidth_limit`],
+    /// keyed by server channel ID.
+    ///
+    /// Messages still queued when their time is up are discarded rather than sent late, so
+    /// e.g. position updates stay fresh under temporary congestion. Reliable channels can't
+    /// have one, as dropping their messages would break replication. Ignored on the client.
+    pub send_ttl: HashMap<usize, Duration>,
+    /// What happens when the socket refuses a message, keyed like [`Self::channel_limits`].
+    /// Channels without an entry drop the message.
+    pub send_failure_policies: HashMap<usize, SendFailurePolicy>,
+    /// Length of the windows the replicon traffic is profiled over, `None` disables
+    /// profiling.
+    ///
+    /// At the end of every window the traffic of each channel is summarized in the
+    /// `BandwidthReports` resource, e.g. to find the channel that dominates the bandwidth.
+    pub bandwidth_profile_window: Option<Duration>,
+    /// Holds received messages until a frame that runs [`FixedMain`](bevy::app::FixedMain).
+    ///
+    /// Messages that arrive between two fixed ticks are then handed to replicon together in the
+    /// receive set of that frame, so replicon processes them right before the fixed tick.
+    /// This aligns message delivery with the fixed timestep. Frames without a fixed tick
+    /// receive nothing.
+    pub hold_until_fixed_update: bool,
+    /// Delivers the replicon messages the host sent in the same frame to the client in the
+    /// same frame, across reliable channels, `None` delivers each message as it arrives.
+    ///
+    /// Reliable channels don't wait for each other, so e.g. a trigger can be observed before
+    /// the replication of the same tick. With a barrier the host tells the client how many
+    /// messages each channel carried, and the client holds reliable messages until all of
+    /// them arrived, for up to the given time. Unreliable channels are never held. Host and
+    /// client must both enable it.
+    ///
+    /// Can't be combined with [`Self::channel_limits`], [`Self::channel_pacing`] or
+    /// [`Self::bandwidth_limit`]: they delay messages past the barrier of their frame, which
+    /// would hold every tick until the timeout.
+    pub tick_barrier: Option<Duration>,
+    /// Limits the host enforces on chat messages of each client.
+    pub chat_limits: ChatLimits,
+    /// Disconnects clients that violate the protocol, e.g. by sending on a channel reserved
+    /// for the host or sending malformed system messages.
+    ///
+    /// Violations are always counted in `ConnectionStats::protocol_violations`.
+    pub disconnect_on_protocol_violation: bool,
+    /// Sends the host's replicon [`ProtocolHash`] along with the handshake.
+    ///
+    /// Clients with a different protocol disconnect with `DisconnectKind::ProtocolMismatch`
+    /// right away, before replicon authorizes them, instead of failing to deserialize
+    /// replication later. Meant for [`AuthMethod::ProtocolCheck`](bevy_replicon::prelude::AuthMethod),
+    /// which still catches clients that don't check. Ignored on the client, which checks
+    /// whenever the host sends a hash.
+    pub check_protocol_hash: bool,
+    /// Largest replicon message accepted from a peer, larger ones are dropped and count as a
+    /// protocol violation.
+    pub max_inbound_message_size: usize,
+    /// Bytes of incoming transfers a single peer may have in progress at once.
+    ///
+    /// Transfers exceeding it are rejected before any memory is reserved for them and count as
+    /// a protocol violation.
+    pub max_inbound_transfer_bytes: u64,
+    /// Extra data channels for game data outside of replicon, e.g. a lobby chat, addressed
+    /// by their index.
+    ///
+    /// Opened after all other channels, so host and client must configure the same list.
+    /// Messages arrive as `UserMessage`.
+    pub user_channels: Vec<Channel>,
+    /// User channels whose client messages reach the other clients without going through
+    /// the host's game logic, keyed by index in [`Self::user_channels`].
+    ///
+    /// Clients send with `MatchboxClient::broadcast`. Host and client must configure the
+    /// same fanouts.
+    pub fanout_channels: HashMap<usize, Fanout>,
+}
+
+impl Default for MatchboxConfig {
+    fn default() -> Self {
+        Self {
+            ice_server: Default::default(),
+            signaling_keep_alive_interval: Some(Duration::from_secs(10)),
+            reconnect_attempts: Some(3),
+            rejoin_grace_period: Duration::ZERO,
+            disconnect_ack_timeout: Duration::from_secs(1),
+            channel_open_timeout: Some(Duration::from_secs(10)),
+            reconnect_throttle: Some(Default::default()),
+            max_clients: None,
+            waiting_room: false,
+            unresponsive_timeout: None,
+            auto_rejoin_attempts: 0,
+            bandwidth_limit: None,
+            channel_limits: HashMap::new(),
+            channel_pacing: HashMap::new(),
+            immediate_channels: HashSet::new(),
+            unthrottled_channels: HashSet::new(),
+            send_ttl: HashMap::new(),
+            send_failure_policies: HashMap::new(),
+            bandwidth_profile_window: None,
+            hold_until_fixed_update: false,
+            tick_barrier: None,
+            chat_limits: Default::default(),
+            disconnect_on_protocol_violation: false,
+            check_protocol_hash: false,
+            max_inbound_message_size: 256 * 1024,
+            max_inbound_transfer_bytes: 64 * 1024 * 1024,
+            user_channels: Vec::new(),
+            fanout_channels: HashMap::new(),
+        }
+    }
 }
 
+/// Prints the TURN credential redacted.
+impl fmt::Debug for MatchboxConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        // Destructured to not miss new fields.
+        let Self {
+            ice_server,
+            signaling_keep_alive_interval,
+            reconnect_attempts,
+            rejoin_grace_period,
+            disconnect_ack_timeout,
+            reconnect_throttle,
+            max_clients,
+            waiting_room,
+            unresponsive_timeout,
+            channel_open_timeout,
+            auto_rejoin_attempts,
+            bandwidth_limit,
+            channel_limits,
+            channel_pacing,
+            immediate_channels,
+            unthrottled_channels,
+            send_ttl,
+            send_failure_policies,
+            bandwidth_profile_window,
+            hold_until_fixed_update,
+            tick_barrier,
+            chat_limits,
+            disconnect_on_protocol_violation,
+            check_protocol_hash,
+            max_inbound_message_size,
+            max_inbound_transfer_bytes,
+            user_channels,
+            fanout_channels,
+        } = self;
+        f.debug_struct("MatchboxConfig")
+            .field("ice_server", &RedactedIceServer(ice_server))
+            .field(
+                "signaling_keep_alive_interval",
+                signaling_keep_alive_interval,
+            )
+            .field("reconnect_attempts", reconnect_attempts)
+            .field("rejoin_grace_period", rejoin_grace_period)
+            .field("disconnect_ack_timeout", disconnect_ack_timeout)
+            .field("reconnect_throttle", reconnect_throttle)
+            .field("max_clients", max_clients)
+            .field("waiting_room", waiting_room)
+            .field("unresponsive_timeout", unresponsive_timeout)
+            .field("channel_open_timeout", channel_open_timeout)
+            .field("auto_rejoin_attempts", auto_rejoin_attempts)
+            .field("bandwidth_limit", bandwidth_limit)
+            .field("channel_limits", channel_limits)
+            .field("channel_pacing", channel_pacing)
+            .field("immediate_channels", immediate_channels)
+            .field("unthrottled_channels", unthrottled_channels)
+            .field("send_ttl", send_ttl)
+            .field("send_failure_policies", send_failure_policies)
+            .field("bandwidth_profile_window", bandwidth_profile_window)
+            .field("hold_until_fixed_update", hold_until_fixed_update)
+            .field("tick_barrier", tick_barrier)
+            .field("chat_limits", chat_limits)
+            .field(
+                "disconnect_on_protocol_violation",
+                disconnect_on_protocol_violation,
+            )
+            .field("check_protocol_hash", check_protocol_hash)
+            .field("max_inbound_message_size", max_inbound_message_size)
+            .field("max_inbound_transfer_bytes", max_inbound_transfer_bytes)
+            .field("user_channels", user_channels)
+            .field("fanout_channels", fanout_channels)
+            .finish()
+    }
+}
+
+struct RedactedIceServer<'a>(&'a RtcIceServerConfig);
+
+impl fmt::Debug for RedactedIceServer<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RtcIceServerConfig")
+            .field("urls", &self.0.urls)
+            .field("username", &self.0.username)
+            .field(
+                "credential",
+                &self.0.credential.as_ref().map(|_| "<redacted>"),
+            )
+            .finish()
+    }
+}
+
+impl MatchboxConfig {
+    /// Checks for settings that would stall or break connections, with `sending_channels`
+    /// replicon channels on this side: server channels on the host, client channels on the
+    /// client.
+    pub(super) fn validate(&self, sending_channels: usize) -> Result<(), ConfigError> {
+        if self.signaling_keep_alive_interval == Some(Duration::ZERO) {
+            return Err(ConfigError::Zero("signaling_keep_alive_interval"));
+        }
+        if self.disconnect_ack_timeout.is_zero() {
+            return Err(ConfigError::Zero("disconnect_ack_timeout"));
+        }
+        if self.channel_open_timeout == Some(Duration::ZERO) {
+            return Err(ConfigError::Zero("channel_open_timeout"));
+        }
+        if self.max_clients == Some(0) {
+            return Err(ConfigError::Zero("max_clients"));
+        }
+        if self.tick_barrier == Some(Duration::ZERO) {
+            return Err(ConfigError::Zero("tick_barrier"));
+        }
+        if self.tick_barrier.is_some() {
+            let shaping = [
+                ("bandwidth_limit", self.bandwidth_limit.is_some()),
+                ("channel_limits", !self.channel_limits.is_empty()),
+                ("channel_pacing", !self.channel_pacing.is_empty()),
+            ];
+            if let Some(&(setting, _)) = shaping.iter().find(|&&(_, enabled)| enabled) {
+                return Err(ConfigError::Conflict("tick_barrier", setting));
+            }
+        }
+        if let Some(timeout) = &self.unresponsive_timeout {
+            if timeout.after.is_zero() {
+                return Err(ConfigError::Zero("unresponsive_timeout.after"));
+            }
+        }
+        if let Some(limit) = &self.bandwidth_limit {
+            if limit.bytes_per_second == 0 {
+                return Err(ConfigError::Zero("bandwidth_limit.bytes_per_second"));
+            }
+            if limit.initial_sync_bytes_per_second == 0 && !limit.initial_sync_duration.is_zero() {
+                return Err(ConfigError::Zero(
+                    "bandwidth_limit.initial_sync_bytes_per_second",
+                ));
+            }
+        }
+        for (&channel_id, bucket) in &self.channel_limits {
+            if channel_id >= sending_channels {
+                return Err(ConfigError::UnknownChannel {
+                    channel_id,
+                    channels: sending_channels,
+                });
+            }
+            if bucket.bytes_per_second == 0 {
+                return Err(ConfigError::Zero("channel_limits.bytes_per_second"));
+            }
+            if bucket.burst == 0 {
+                return Err(ConfigError::Zero("channel_limits.burst"));
+            }
+        }
+        for (&channel_id, interval) in &self.channel_pacing {
+            if channel_id >= sending_channels {
+                return Err(ConfigError::UnknownChannel {
+                    channel_id,
+                    channels: sending_channels,
+                });
+            }
+            if interval.is_zero() {
+                return Err(ConfigError::Zero("channel_pacing"));
+            }
+        }
+        if let Some(&channel_id) = self
+            .immediate_channels
+            .iter()
+            .find(|&&channel_id| channel_id >= sending_channels)
+        {
+            return Err(ConfigError::UnknownChannel {
+                channel_id,
+                channels: sending_channels,
+            });
+        }
+        if let Some(&channel_id) = self
+            .send_failure_policies
+            .keys()
+            .find(|&&channel_id| channel_id >= sending_channels)
+        {
+            return Err(ConfigError::UnknownChannel {
+                channel_id,
+                channels: sending_channels,
+            });
+        }
+        if let Some(&channel_id) = self
+            .fanout_channels
+            .keys()
+            .find(|&&channel_id| channel_id >= self.user_channels.len())
+        {
+            return Err(ConfigError::UnknownChannel {
+                channel_id,
+                channels: self.user_channels.len(),
+            });
+        }
+        if self.max_inbound_message_size == 0 {
+            return Err(ConfigError::Zero("max_inbound_message_size"));
+        }
+        Ok(())
+    }
+
+    pub(super) fn send_failure_policy(&self, channel_id: usize) -> SendFailurePolicy {
+        self.send_failure_policies
+            .get(&channel_id)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Checks [`Self::send_ttl`] against the server channels.
+    pub(super) fn validate_send_ttl(&self, server_channels: &[Channel]) -> Result<(), ConfigError> {
+        for (&channel_id, ttl) in &self.send_ttl {
+            match server_channels.get(channel_id) {
+                None => {
+                    return Err(ConfigError::UnknownChannel {
+                        channel_id,
+                        channels: server_channels.len(),
+                    });
+                }
+                Some(Channel::Unreliable) => (),
+                Some(_) => return Err(ConfigError::ReliableChannel { channel_id }),
+            }
+            if ttl.is_zero() {
+                return Err(ConfigError::Zero("send_ttl"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Invalid [`MatchboxConfig`], returned as [`io::ErrorKind::InvalidInput`] by the host and
+/// client constructors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    /// A setting is zero, which would never send or time out immediately.
+    Zero(&'static str),
+    /// [`MatchboxConfig::channel_limits`], [`MatchboxConfig::channel_pacing`],
+    /// [`MatchboxConfig::immediate_channels`] or [`MatchboxConfig::send_failure_policies`] has
+    /// an entry for a channel that isn't registered.
+    UnknownChannel { channel_id: usize, channels: usize },
+    /// [`MatchboxConfig::send_ttl`] has an entry for a reliable channel.
+    ReliableChannel { channel_id: usize },
+    /// More replicon channels are registered than the socket can open data channels for,
+    /// counting server and client channels together.
+    TooManyChannels {
+        replicon_channels: usize,
+        max: usize,
+    },
+    /// Two settings are enabled that can't be combined.
+    Conflict(&'static str, &'static str),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Zero(setting) => write!(f, "`{setting}` must not be zero"),
+            Self::UnknownChannel {
+                channel_id,
+                channels,
+            } => write!(
+                f,
+                "channel {channel_id} is configured, but only {channels} channels are registered"
+            ),
+            Self::TooManyChannels {
+                replicon_channels,
+                max,
+            } => write!(
+                f,
+                "{replicon_channels} replicon channels are registered, but at most {max} are supported"
+            ),
+            Self::ReliableChannel { channel_id } => {
+                write!(f, "channel {channel_id} is reliable and can't have a TTL")
+            }
+            Self::Conflict(setting, other) => {
+                write!(f, "`{setting}` can't be combined with `{other}`")
+            }
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+/// Why a connection between host and client ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DisconnectKind {
+    /// The host's rejoin grace period expired before the client came back.
+    Timeout,
+    /// The host disconnected the client.
+    Kicked { reason: Option<String> },
+    /// The connection to the signaling server was lost.
+    SignalingLost,
+    /// The peer connection dropped without the other side closing it.
+    ///
+    /// Matchbox doesn't report the state of the underlying peer connection, so this covers
+    /// failed ICE connectivity as well as peers that went away without a goodbye.
+    IceFailed,
+    /// The other side closed the connection.
+    RemoteClosed,
+    /// This side closed the connection.
+    LocalClosed,
+    /// The host disconnected the client after a protocol violation, see
+    /// [`MatchboxConfig::disconnect_on_protocol_violation`].
+    ProtocolViolation,
+    /// The socket refused a message on a channel with [`SendFailurePolicy::Disconnect`].
+    SendFailed,
+    /// The data channels to the host didn't open within
+    /// [`MatchboxConfig::channel_open_timeout`].
+    ChannelOpenTimeout,
+    /// The client's replicon protocol differs from the host's, see
+    /// [`MatchboxConfig::check_protocol_hash`].
+    ProtocolMismatch,
+}
+
+/// Sent when the connection to the signaling server is lost.
+///
+/// Peer connections don't outlive the signaling connection, so the host or client resource is
+/// removed in the same frame. Not sent while the client rejoins automatically, see
+/// [`MatchboxConfig::auto_rejoin_attempts`].
+#[derive(Event, Debug, Clone, Copy)]
+pub struct SignalingLost;
+
+/// Sent on the client when the host peer disconnected while signaling was still up.
+///
+/// Like [`SignalingLost`], not sent while the client rejoins automatically.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct HostLost {
+    pub host_peer_id: PeerId,
+}
+
+/// Plugin group with the server and client backends, depending on the enabled features.
+///
+/// Individual plugins can be configured or disabled like with any other group:
+///
+/// ```
+/// # use bevy::prelude::*;
+/// # use bevy_replicon_matchbox::*;
+/// let plugins = RepliconMatchboxPlugins
+///     .set(RepliconMatchboxServerPlugin {
+///         max_size: 1000,
+///         ..Default::default()
+///     })
+///     .disable::<RepliconMatchboxClientPlugin>();
+/// ```
 pub struct RepliconMatchboxPlugins;
 
 impl PluginGroup for RepliconMatchboxPlugins {
@@ -25,13 +637,13 @@ impl PluginGroup for RepliconMatchboxPlugins {
         #[cfg(feature = "server")]
         {
             use crate::server::RepliconMatchboxServerPlugin;
-            group = group.add(RepliconMatchboxServerPlugin);
+            group = group.add(RepliconMatchboxServerPlugin::default());
         }
 
         #[cfg(feature = "client")]
         {
             use crate::client::RepliconMatchboxClientPlugin;
-            group = group.add(RepliconMatchboxClientPlugin);
+            group = group.add(RepliconMatchboxClientPlugin::default());
         }
 
         group
@@ -52,35 +664,170 @@ impl<'a> RepliconChannelsExt<'a> for RepliconChannels {
     }
 }
 
+/// Positions of the socket channels.
+///
+/// The system channel comes first, followed by the replicon server and client channels, the
+/// channels of the transport's own subsystems and the user channels.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct ChannelLayout {
+    server_channels: usize,
+    client_channels: usize,
+    user_channels: usize,
+}
+
+impl ChannelLayout {
+    pub(super) fn new(replicon_channels: &RepliconChannels, config: &MatchboxConfig) -> Self {
+        Self {
+            server_channels: replicon_channels.server_channels().len(),
+            client_channels: replicon_channels.client_channels().len(),
+            user_channels: config.user_channels.len(),
+        }
+    }
+
+    /// Checks whether the socket still covers all replicon channels.
+    ///
+    /// Data channels can't be added to live peer connections, so channels registered after
+    /// the socket was created stay unusable until a new socket is created.
+    pub(super) fn covers(&self, replicon_channels: &RepliconChannels) -> bool {
+        self.server_channels == replicon_channels.server_channels().len()
+            && self.client_channels == replicon_channels.client_channels().len()
+    }
+
+    /// Socket channel of a replicon server channel, `None` if the channel was registered
+    /// after the socket was created.
+    pub(super) fn server_channel(&self, channel_id: usize) -> Option<usize> {
+        (channel_id < self.server_channels).then_some(1 + channel_id)
+    }
+
+    /// Socket channel of a replicon client channel, `None` if the channel was registered
+    /// after the socket was created.
+    pub(super) fn client_channel(&self, channel_id: usize) -> Option<usize> {
+        (channel_id < self.client_channels).then_some(1 + self.server_channels + channel_id)
+    }
+
+    pub(super) fn server_channel_count(&self) -> usize {
+        self.server_channels
+    }
+
+    pub(super) fn client_channel_count(&self) -> usize {
+        self.client_channels
+    }
+
+    /// Reliable channel for payloads clients send to each other through the host.
+    pub(super) fn relay_channel(&self) -> usize {
+        1 + self.server_channels + self.client_channels
+    }
+
+    /// Unreliable channel for payloads clients send each other directly.
+    pub(super) fn direct_channel(&self) -> usize {
+        self.relay_channel() + 1
+    }
+
+    /// Unreliable, unordered channel for voice frames.
+    pub(super) fn voice_channel(&self) -> usize {
+        self.relay_channel() + 2
+    }
+
+    /// Reliable channel for chat messages.
+    pub(super) fn chat_channel(&self) -> usize {
+        self.relay_channel() + 3
+    }
+
+    /// Reliable channel for chunked transfers.
+    pub(super) fn transfer_channel(&self) -> usize {
+        self.relay_channel() + 4
+    }
+
+    /// Channel with index `channel` in [`MatchboxConfig::user_channels`], `None` if there is
+    /// none.
+    pub(super) fn user_channel(&self, channel: usize) -> Option<usize> {
+        (channel < self.user_channels).then(|| self.transfer_channel() + 1 + channel)
+    }
+
+    pub(super) fn user_channel_count(&self) -> usize {
+        self.user_channels
+    }
+
+    /// Fails if the socket would need more data channels than WebRTC peers negotiate.
+    pub(super) fn check_channel_count(&self) -> Result<(), ConfigError> {
+        let replicon_channels = self.server_channels + self.client_channels;
+        // the system channel, the channels of the subsystems and the user channels
+        let reserved = self.transfer_channel() + 1 + self.user_channels - replicon_channels;
+        if replicon_channels + reserved > MAX_DATA_CHANNELS {
+            return Err(ConfigError::TooManyChannels {
+                replicon_channels,
+                max: MAX_DATA_CHANNELS - reserved,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Checks the config and that all channels fit into a connection, for a host or client
+/// constructor.
+pub(super) fn validate_config(
+    config: &MatchboxConfig,
+    replicon_channels: &RepliconChannels,
+    sending_channels: usize,
+) -> io::Result<()> {
+    ChannelLayout::new(replicon_channels, config)
+        .check_channel_count()
+        .and_then(|()| config.validate(sending_channels))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+pub(super) fn parse_room_url(room_url: impl IntoRoomUrl) -> io::Result<RoomUrl> {
+    room_url
+        .into_room_url()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+/// Creates the socket with the system channel, the replicon channels, the channels of the
+/// subsystems and the user channels.
+///
+/// `matchbox_socket` creates data channels pre-negotiated, with the channel index as the
+/// SCTP stream ID, so neither side announces channels in-band and simultaneous creation
+/// can't race. This requires host and client to build the exact same list, which holds as
+/// long as both register the same replicon channels in the same order.
 pub(super) fn create_matchbox_socket(
     room_url: impl Into<String>,
     replicon_channels: &RepliconChannels,
+    config: &MatchboxConfig,
 ) -> MatchboxSocket {
-    let mut web_rtc_socket = bevy_matchbox::matchbox_socket::WebRtcSocketBuilder::new(room_url);
+    let mut web_rtc_socket = bevy_matchbox::matchbox_socket::WebRtcSocketBuilder::new(room_url)
+        .ice_server(config.ice_server.clone())
+        .signaling_keep_alive_interval(config.signaling_keep_alive_interval)
+        .reconnect_attempts(config.reconnect_attempts);
     //add system channel
     web_rtc_socket = web_rtc_socket.add_reliable_channel();
     for &channel in replicon_channels.all_channels() {
-        match channel {
-            Channel::Unreliable => {
-                web_rtc_socket = web_rtc_socket.add_unreliable_channel();
-            }
-            Channel::Unordered => {
-                web_rtc_socket = web_rtc_socket.add_channel(ChannelConfig {
-                    ordered: false,
-                    max_retransmits: None,
-                });
-            }
-            Channel::Ordered => {
-                web_rtc_socket = web_rtc_socket.add_reliable_channel();
-            }
-        };
+        web_rtc_socket = web_rtc_socket.add_channel(channel_config(channel));
+    }
+    //add relay, direct, voice, chat and transfer channels
+    web_rtc_socket = web_rtc_socket
+        .add_reliable_channel()
+        .add_unreliable_channel()
+        .add_unreliable_channel()
+        .add_reliable_channel()
+        .add_reliable_channel();
+    for &channel in &config.user_channels {
+        web_rtc_socket = web_rtc_socket.add_channel(channel_config(channel));
     }
     let socket = web_rtc_socket.build();
     MatchboxSocket::from(socket)
 }
 
-#[cfg(feature = "server")]
-use bevy_matchbox::matchbox_socket::PeerId;
+fn channel_config(channel: Channel) -> ChannelConfig {
+    match channel {
+        Channel::Unreliable => ChannelConfig::unreliable(),
+        Channel::Unordered => ChannelConfig {
+            ordered: false,
+            max_retransmits: None,
+        },
+        Channel::Ordered => ChannelConfig::reliable(),
+    }
+}
+
 #[cfg(feature = "server")]
 pub(super) fn uuid_to_u64_truncated(peer_id: PeerId) -> u64 {
     let bytes = peer_id.0.as_bytes();
@@ -97,7 +844,7 @@ pub(super) fn add_marker(data: &[u8]) -> Packet {
 
 ///Marker stripped as matchbox seems to drop 0 sized packages
 pub(super) fn strip_marker(packet: &[u8]) -> Bytes {
-    Bytes::copy_from_slice(&packet[1..])
+    Bytes::copy_from_slice(packet.get(1..).unwrap_or_default())
 }
 
 pub(super) fn to_packet<'a, T: Serialize>(msg: &T, buf: &'a mut [u8]) -> &'a [u8] {
@@ -105,6 +852,32 @@ pub(super) fn to_packet<'a, T: Serialize>(msg: &T, buf: &'a mut [u8]) -> &'a [u8
     to_slice(msg, buf).expect("serialize failed")
 }
 
+pub(super) fn system_packet(msg: &SystemChannelMessage) -> Packet {
+    let mut buf = [0u8; SYSTEM_MESSAGE_MAX_SIZE];
+    to_packet(msg, &mut buf).into()
+}
+
+/// Serializes a message with a variable sized payload.
+pub(super) fn to_vec_packet<T: Serialize>(msg: &T, max_size: usize) -> Packet {
+    let mut buf = vec![0u8; max_size];
+    let len = to_packet(msg, &mut buf).len();
+    buf.truncate(len);
+    buf.into()
+}
+
+/// Run condition that is `true` if [`FixedMain`](bevy::app::FixedMain) runs at least once
+/// this frame.
+///
+/// `Time<Fixed>` only accumulates the delta of the frame after
+/// [`PreUpdate`](bevy::prelude::PreUpdate), so its overstep is still the one the previous
+/// frame left.
+pub(super) fn fixed_tick_this_frame(
+    fixed_time: Res<Time<Fixed>>,
+    virtual_time: Res<Time<Virtual>>,
+) -> bool {
+    fixed_time.overstep() + virtual_time.delta() >= fixed_time.timestep()
+}
+
 pub(super) fn from_packet<'a, T: Deserialize<'a>>(
     data: &'a [u8],
 ) -> bevy::prelude::Result<T, postcard::Error> {
@@ -114,13 +887,204 @@ pub(super) fn from_packet<'a, T: Deserialize<'a>>(
 #[test]
 fn test_packaging() {
     let messages = [
-        SystemChannelMessage::ConnectedToHost,
-        SystemChannelMessage::HostRequestsDisconnect,
+        SystemChannelMessage::ConnectedToHost {
+            session_token: u64::MAX,
+            protocol_hash: None,
+        },
+        SystemChannelMessage::HostRequestsDisconnect { reason: None },
+        SystemChannelMessage::HostRequestsDisconnect {
+            reason: Some("é".repeat(MAX_KICK_REASON_LEN / 2)),
+        },
+        SystemChannelMessage::ProtocolMismatch,
+        SystemChannelMessage::RequestJoin,
+        SystemChannelMessage::Join {
+            rejoin_token: Some(u64::MAX),
+        },
+        SystemChannelMessage::ReceiveReport {
+            bytes_per_second: u32::MAX,
+        },
+        SystemChannelMessage::Ping {
+            sent_at_micros: u64::MAX,
+        },
+        SystemChannelMessage::Pong { sent_at_micros: 0 },
+        SystemChannelMessage::Queued { position: u32::MAX },
+        SystemChannelMessage::TickBarrier {
+            counts: vec![(usize::MAX, u32::MAX); 8],
+        },
+        SystemChannelMessage::Delivered {
+            id: DeliveryId(u64::MAX),
+        },
     ];
     for msg in messages.iter() {
-        let mut buf = [0u8; 1];
-        let p = to_packet(&msg, &mut buf);
-        let deserialized: SystemChannelMessage = from_packet(p).unwrap();
+        let p = system_packet(msg);
+        let deserialized: SystemChannelMessage = from_packet(&p).unwrap();
         assert_eq!(*msg, deserialized);
     }
 }
+
+#[test]
+fn test_config_validation() {
+    assert_eq!(MatchboxConfig::default().validate(0), Ok(()));
+
+    let config = MatchboxConfig {
+        disconnect_ack_timeout: Duration::ZERO,
+        ..Default::default()
+    };
+    assert_eq!(
+        config.validate(0),
+        Err(ConfigError::Zero("disconnect_ack_timeout"))
+    );
+
+    let config = MatchboxConfig {
+        max_clients: Some(0),
+        ..Default::default()
+    };
+    assert_eq!(config.validate(0), Err(ConfigError::Zero("max_clients")));
+
+    let config = MatchboxConfig {
+        tick_barrier: Some(Duration::ZERO),
+        ..Default::default()
+    };
+    assert_eq!(config.validate(0), Err(ConfigError::Zero("tick_barrier")));
+
+    let mut config = MatchboxConfig {
+        bandwidth_limit: Some(BandwidthLimit {
+            bytes_per_second: 1000,
+            initial_sync_bytes_per_second: 0,
+            initial_sync_duration: Duration::ZERO,
+            max_carry_over: 0,
+        }),
+        ..Default::default()
+    };
+    assert_eq!(config.validate(0), Ok(()), "no initial sync");
+    config
+        .bandwidth_limit
+        .as_mut()
+        .unwrap()
+        .initial_sync_duration = Duration::from_secs(1);
+    assert_eq!(
+        config.validate(0),
+        Err(ConfigError::Zero(
+            "bandwidth_limit.initial_sync_bytes_per_second"
+        ))
+    );
+
+    let mut config = MatchboxConfig {
+        tick_barrier: Some(Duration::from_millis(100)),
+        ..Default::default()
+    };
+    config.channel_pacing.insert(0, Duration::from_millis(50));
+    assert_eq!(
+        config.validate(1),
+        Err(ConfigError::Conflict("tick_barrier", "channel_pacing"))
+    );
+
+    let mut config = MatchboxConfig::default();
+    config.channel_limits.insert(
+        2,
+        TokenBucket {
+            bytes_per_second: 1000,
+            burst: 1000,
+        },
+    );
+    assert_eq!(config.validate(3), Ok(()));
+    assert_eq!(
+        config.validate(2),
+        Err(ConfigError::UnknownChannel {
+            channel_id: 2,
+            channels: 2
+        })
+    );
+    config.channel_limits.get_mut(&2).unwrap().burst = 0;
+    assert_eq!(
+        config.validate(3),
+        Err(ConfigError::Zero("channel_limits.burst"))
+    );
+
+    let mut config = MatchboxConfig::default();
+    config.send_ttl.insert(1, Duration::from_millis(100));
+    assert_eq!(
+        config.validate_send_ttl(&[Channel::Ordered, Channel::Unreliable]),
+        Ok(())
+    );
+    assert_eq!(
+        config.validate_send_ttl(&[Channel::Unreliable, Channel::Unordered]),
+        Err(ConfigError::ReliableChannel { channel_id: 1 })
+    );
+
+    let mut config = MatchboxConfig::default();
+    config.immediate_channels.insert(1);
+    assert_eq!(config.validate(2), Ok(()));
+    assert_eq!(
+        config.validate(1),
+        Err(ConfigError::UnknownChannel {
+            channel_id: 1,
+            channels: 1
+        })
+    );
+
+    let mut config = MatchboxConfig {
+        user_channels: vec![Channel::Ordered],
+        ..Default::default()
+    };
+    config.fanout_channels.insert(0, Fanout::Relayed);
+    assert_eq!(config.validate(0), Ok(()));
+    config.fanout_channels.insert(1, Fanout::Direct);
+    assert_eq!(
+        config.validate(0),
+        Err(ConfigError::UnknownChannel {
+            channel_id: 1,
+            channels: 1
+        })
+    );
+}
+
+#[test]
+fn test_channel_count() {
+    let layout = |server_channels, client_channels| ChannelLayout {
+        server_channels,
+        client_channels,
+        user_channels: 0,
+    };
+    assert_eq!(layout(2, 1).check_channel_count(), Ok(()));
+    assert_eq!(layout(1000, 18).check_channel_count(), Ok(()));
+    assert_eq!(
+        layout(1000, 19).check_channel_count(),
+        Err(ConfigError::TooManyChannels {
+            replicon_channels: 1019,
+            max: 1018
+        })
+    );
+
+    let layout = ChannelLayout {
+        user_channels: 2,
+        ..layout(1000, 16)
+    };
+    assert_eq!(layout.user_channel(1), Some(layout.transfer_channel() + 2));
+    assert_eq!(layout.user_channel(2), None);
+    assert_eq!(layout.check_channel_count(), Ok(()));
+    assert!(
+        ChannelLayout {
+            user_channels: 3,
+            ..layout
+        }
+        .check_channel_count()
+        .is_err()
+    );
+}
+
+#[test]
+fn test_config_debug() {
+    let config = MatchboxConfig {
+        ice_server: RtcIceServerConfig {
+            urls: vec!["turn:turn.example.com:3478".into()],
+            username: Some("player".into()),
+            credential: Some("hunter2".into()),
+        },
+        ..Default::default()
+    };
+    let debug = format!("{config:?}");
+    assert!(debug.contains("turn.example.com"));
+    assert!(debug.contains("player"));
+    assert!(!debug.contains("hunter2"));
+}
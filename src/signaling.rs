@@ -0,0 +1,220 @@
+use bevy::prelude::*;
+use bevy_matchbox::MatchboxServer;
+use bevy_matchbox::matchbox_signaling::{self, SignalingServer};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+
+/// Runs a matchbox signaling server with the client/server topology inside the app, e.g. on
+/// the host of a listen server.
+///
+/// The server starts in [`Startup`] and can be stopped and restarted through
+/// [`EmbeddedSignalingServer`]. Its state is reported in [`SignalingMetrics`].
+pub struct SignalingServerPlugin {
+    /// Address to bind, e.g. `0.0.0.0:3536` for all IPv4 interfaces or `[::]:3536` for all
+    /// IPv6 ones. Port zero picks a free port, see [`EmbeddedSignalingServer::local_addr`].
+    pub address: SocketAddr,
+    /// Connections beyond this many peers are rejected, `None` accepts everyone.
+    pub max_peers: Option<usize>,
+}
+
+impl Plugin for SignalingServerPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(EmbeddedSignalingServer::new(self.address, self.max_peers))
+            .init_resource::<SignalingMetrics>()
+            .add_systems(Startup, start_signaling_server)
+            .add_systems(First, update_signaling_metrics);
+    }
+}
+
+/// Live state of the server started by [`SignalingServerPlugin`], updated every frame.
+#[derive(Resource, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SignalingMetrics {
+    /// Rooms with a host. The client/server topology serves a single room, so this is one
+    /// while a host is connected.
+    pub active_rooms: usize,
+    /// Host and clients currently connected to the server.
+    pub connected_peers: usize,
+    /// Peers that joined since the plugin was added.
+    pub total_joins: u64,
+    /// Connections rejected because of [`SignalingServerPlugin::max_peers`].
+    pub rejected_connections: u64,
+}
+
+/// Signaling server of the [`SignalingServerPlugin`].
+///
+/// Stopping the server closes the signaling connection of every peer. `matchbox_socket`
+/// ends all peer connections with it, so every session is dropped: the host removes its
+/// `MatchboxHost` along with all client entities, and clients have to connect to a new host
+/// once the server runs again. Automatic rejoins can't help, since the host they would
+/// rejoin is gone as well.
+#[derive(Resource)]
+pub struct EmbeddedSignalingServer {
+    address: SocketAddr,
+    max_peers: Option<usize>,
+    /// Written by the server callbacks, which run outside the ECS.
+    metrics: Arc<Mutex<SignalingMetrics>>,
+    server: Option<MatchboxServer>,
+    local_addr: Option<SocketAddr>,
+}
+
+impl EmbeddedSignalingServer {
+    /// Creates a stopped server, see [`SignalingServerPlugin`] for the parameters.
+    pub fn new(address: SocketAddr, max_peers: Option<usize>) -> Self {
+        Self {
+            address,
+            max_peers,
+            metrics: Default::default(),
+            server: None,
+            local_addr: None,
+        }
+    }
+
+    /// Current state of the server, also available as the [`SignalingMetrics`] resource when
+    /// the server was started by the plugin.
+    pub fn metrics(&self) -> SignalingMetrics {
+        self.metrics.lock().unwrap().clone()
+    }
+
+    /// Requested bind address.
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Address the server is bound to, `None` while stopped.
+    ///
+    /// Differs from [`Self::address`] when binding port zero.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    /// URL of `room` for peers on this machine, `None` while stopped.
+    ///
+    /// Unspecified bind addresses like `0.0.0.0` are replaced with the loopback address.
+    pub fn room_url(&self, room: &str) -> Option<String> {
+        self.local_addr.map(|addr| local_room_url(addr, room))
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.server.is_some()
+    }
+
+    /// Starts the server if it isn't running.
+    ///
+    /// Returns an error instead of panicking if the address can't be bound, e.g. because
+    /// the port is still in use.
+    pub fn start(&mut self) -> Result<(), matchbox_signaling::Error> {
+        if self.server.is_some() {
+            return Ok(());
+        }
+        let mut server = self.build_server();
+        let local_addr = server.bind()?;
+        info!("signaling server listening at {local_addr}");
+        self.local_addr = Some(local_addr);
+        self.server = Some(MatchboxServer::from(server));
+        Ok(())
+    }
+
+    /// Stops the server and releases its port.
+    pub fn stop(&mut self) {
+        if self.server.take().is_none() {
+            return;
+        }
+        if let Some(local_addr) = self.local_addr.take() {
+            info!("signaling server at {local_addr} stopped");
+        }
+        let mut metrics = self.metrics.lock().unwrap();
+        metrics.active_rooms = 0;
+        metrics.connected_peers = 0;
+    }
+
+    /// Stops the server and starts it again at `address`.
+    ///
+    /// Drops every session like [`Self::stop`].
+    pub fn restart(&mut self, address: SocketAddr) -> Result<(), matchbox_signaling::Error> {
+        self.stop();
+        self.address = address;
+        self.start()
+    }
+
+    fn build_server(&self) -> SignalingServer {
+        let max_peers = self.max_peers;
+        let metrics = &self.metrics;
+        SignalingServer::client_server_builder(self.address)
+            .on_connection_request({
+                let metrics = metrics.clone();
+                move |_| {
+                    let mut metrics = metrics.lock().unwrap();
+                    if max_peers.is_some_and(|max_peers| metrics.connected_peers >= max_peers) {
+                        metrics.rejected_connections += 1;
+                        return Ok(false);
+                    }
+                    Ok(true)
+                }
+            })
+            .on_host_connected({
+                let metrics = metrics.clone();
+                move |_| {
+                    let mut metrics = metrics.lock().unwrap();
+                    metrics.active_rooms = 1;
+                    metrics.connected_peers += 1;
+                    metrics.total_joins += 1;
+                }
+            })
+            .on_host_disconnected({
+                let metrics = metrics.clone();
+                move |_| {
+                    let mut metrics = metrics.lock().unwrap();
+                    metrics.active_rooms = 0;
+                    metrics.connected_peers = metrics.connected_peers.saturating_sub(1);
+                }
+            })
+            .on_client_connected({
+                let metrics = metrics.clone();
+                move |_| {
+                    let mut metrics = metrics.lock().unwrap();
+                    metrics.connected_peers += 1;
+                    metrics.total_joins += 1;
+                }
+            })
+            .on_client_disconnected({
+                let metrics = metrics.clone();
+                move |_| {
+                    let mut metrics = metrics.lock().unwrap();
+                    metrics.connected_peers = metrics.connected_peers.saturating_sub(1);
+                }
+            })
+            .cors()
+            .build()
+    }
+}
+
+fn local_room_url(mut addr: SocketAddr, room: &str) -> String {
+    if addr.ip().is_unspecified() {
+        addr.set_ip(match addr {
+            SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+            SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+        });
+    }
+    format!("ws://{addr}/{room}")
+}
+
+fn start_signaling_server(mut server: ResMut<EmbeddedSignalingServer>) -> Result<()> {
+    server.start()?;
+    Ok(())
+}
+
+fn update_signaling_metrics(
+    server: Res<EmbeddedSignalingServer>,
+    mut metrics: ResMut<SignalingMetrics>,
+) {
+    metrics.set_if_neq(server.metrics());
+}
+
+#[test]
+fn test_local_room_url() {
+    let url = |addr: &str| local_room_url(addr.parse().unwrap(), "game");
+    assert_eq!(url("0.0.0.0:3536"), "ws://127.0.0.1:3536/game");
+    assert_eq!(url("[::]:3536"), "ws://[::1]:3536/game");
+    assert_eq!(url("192.168.1.5:80"), "ws://192.168.1.5:80/game");
+    assert_eq!(url("[fe80::1]:3536"), "ws://[fe80::1]:3536/game");
+}
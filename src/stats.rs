@@ -0,0 +1,630 @@
+use bevy::prelude::*;
+use bevy_replicon::shared::backend::replicon_channels::{ClientChannel, ServerChannel};
+use std::collections::HashMap;
+use std::time::Duration;
+
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Share of the send rate below which a reported receive rate is treated as congestion.
+const CONGESTION_RATIO: f64 = 0.9;
+
+/// Buckets of [`SizeHistogram`], from 64 bytes up to 16 KiB and one for larger messages.
+const SIZE_BUCKETS: usize = 10;
+
+const SMALLEST_BUCKET_BITS: u32 = 6;
+
+/// Totals across all clients of a host, available from `MatchboxHost::stats`.
+#[derive(Clone, Debug, Default)]
+pub struct HostStats {
+    /// Connected clients, clients awaiting a rejoin don't count.
+    pub connections: usize,
+    pub sent_bytes_per_second: f64,
+    pub received_bytes_per_second: f64,
+    /// Replicon messages dropped because their client was gone, they were oversized, they
+    /// outlived [`MatchboxConfig::send_ttl`](crate::MatchboxConfig::send_ttl) or their
+    /// channel was registered after the socket was created.
+    pub dropped_messages: u64,
+    pub protocol_violations: u64,
+    /// Mean round-trip time of the connected clients, `None` until the first measurement.
+    pub average_rtt: Option<Duration>,
+    /// Totals of [`ConnectionStats::sent_by_channel`], keyed by server channel.
+    pub sent_by_channel: HashMap<usize, ChannelTraffic>,
+    /// Totals of [`ConnectionStats::received_by_channel`], keyed by client channel.
+    pub received_by_channel: HashMap<usize, ChannelTraffic>,
+}
+
+impl HostStats {
+    pub(crate) fn aggregate<'a>(&mut self, clients: impl Iterator<Item = &'a ConnectionStats>) {
+        self.connections = 0;
+        self.sent_bytes_per_second = 0.0;
+        self.received_bytes_per_second = 0.0;
+        self.protocol_violations = 0;
+        self.sent_by_channel.clear();
+        self.received_by_channel.clear();
+        let mut rtt_sum = Duration::ZERO;
+        let mut rtt_count = 0;
+        for stats in clients {
+            self.connections += 1;
+            self.sent_bytes_per_second += stats.sent_bytes_per_second;
+            self.received_bytes_per_second += stats.received_bytes_per_second;
+            self.protocol_violations += stats.protocol_violations;
+            for (&channel_id, traffic) in &stats.sent_by_channel {
+                self.sent_by_channel
+                    .entry(channel_id)
+                    .or_default()
+                    .add(traffic);
+            }
+            for (&channel_id, traffic) in &stats.received_by_channel {
+                self.received_by_channel
+                    .entry(channel_id)
+                    .or_default()
+                    .add(traffic);
+            }
+            if let Some(rtt) = stats.rtt {
+                rtt_sum += rtt;
+                rtt_count += 1;
+            }
+        }
+        self.average_rtt = (rtt_count > 0).then(|| rtt_sum / rtt_count);
+    }
+}
+
+/// Traffic of a single replicon channel.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Reflect)]
+pub struct ChannelTraffic {
+    pub messages: u64,
+    pub bytes: u64,
+    /// Sizes of the messages, e.g. to spot unexpectedly large events or components that
+    /// change entirely on every update.
+    pub sizes: SizeHistogram,
+}
+
+impl ChannelTraffic {
+    fn record(&mut self, bytes: usize) {
+        self.messages += 1;
+        self.bytes += bytes as u64;
+        self.sizes.record(bytes);
+    }
+
+    fn add(&mut self, other: &Self) {
+        self.messages += other.messages;
+        self.bytes += other.bytes;
+        self.sizes.add(&other.sizes);
+    }
+}
+
+/// Message counts in power-of-two size buckets, from up to 64 bytes to more than 16 KiB.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Reflect)]
+pub struct SizeHistogram {
+    counts: [u64; SIZE_BUCKETS],
+}
+
+impl SizeHistogram {
+    /// Upper size bound in bytes and message count of each bucket, the last bound is
+    /// [`usize::MAX`].
+    pub fn buckets(&self) -> impl Iterator<Item = (usize, u64)> + '_ {
+        self.counts.iter().enumerate().map(|(index, &count)| {
+            let bound = if index + 1 < SIZE_BUCKETS {
+                1 << (SMALLEST_BUCKET_BITS as usize + index)
+            } else {
+                usize::MAX
+            };
+            (bound, count)
+        })
+    }
+
+    /// Messages larger than `bytes`, rounded up to the containing bucket.
+    pub fn larger_than(&self, bytes: usize) -> u64 {
+        self.counts[Self::bucket(bytes) + 1..].iter().sum()
+    }
+
+    fn bucket(bytes: usize) -> usize {
+        let bits = usize::BITS - bytes.saturating_sub(1).leading_zeros();
+        (bits.saturating_sub(SMALLEST_BUCKET_BITS) as usize).min(SIZE_BUCKETS - 1)
+    }
+
+    fn record(&mut self, bytes: usize) {
+        self.counts[Self::bucket(bytes)] += 1;
+    }
+
+    fn add(&mut self, other: &Self) {
+        for (count, other) in self.counts.iter_mut().zip(other.counts) {
+            *count += other;
+        }
+    }
+}
+
+/// Traffic counters of a single connection.
+///
+/// On the host it's a component of each client entity, on the client it's available from
+/// `MatchboxClient::stats`. Sizes include the transport's one byte marker. Registered for
+/// reflection, so inspectors can show it on client entities.
+#[derive(Component, Clone, Debug, Default, Reflect)]
+#[reflect(Component, Debug, Default)]
+pub struct ConnectionStats {
+    pub packets_sent: u64,
+    pub packets_received: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// Bytes per second sent, measured over the last full second.
+    pub sent_bytes_per_second: f64,
+    /// Bytes per second received, measured over the last full second.
+    pub received_bytes_per_second: f64,
+    /// Estimated bytes per second the peer can receive, `None` until the first report.
+    ///
+    /// Peers report their receive rate every second. While everything sent arrives, the
+    /// estimate is the highest rate seen so far and thus a lower bound of the available
+    /// bandwidth. Once reports fall behind the send rate, the link is considered saturated
+    /// and the estimate drops to the reported rate. Matchbox doesn't expose WebRTC stats, so
+    /// the estimate is based on the actual traffic only.
+    pub estimated_bandwidth: Option<f64>,
+    /// Bytes per second the peer received according to its last report, `None` until the
+    /// first report.
+    pub reported_bytes_per_second: Option<f64>,
+    /// Average interval between frames that sent packets, measured over the last full second.
+    pub send_interval: Option<Duration>,
+    /// Smoothed round-trip time, measured with a ping every second.
+    pub rtt: Option<Duration>,
+    /// [`Time<Real>`] elapsed time at which the peer last sent a replicon message.
+    ///
+    /// Useful for AFK detection without a heartbeat of its own. Transport-internal traffic
+    /// like keep-alives and receive reports doesn't count, neither do the mutation
+    /// acknowledgments replicon sends on its own and messages that were discarded.
+    pub last_received: Option<Duration>,
+    /// Packets the peer sent on channels it must not use or that were malformed.
+    pub protocol_violations: u64,
+    /// Times the socket refused a replicon message, see
+    /// [`SendFailurePolicy`](crate::SendFailurePolicy).
+    pub send_failures: u64,
+    /// Sent replicon messages by channel ID of the sending side: server channels on the
+    /// host, client channels on the client.
+    ///
+    /// Replication uses replicon's built-in channels, remote events and triggers the
+    /// channels they were registered with. Chat, voice, relay and transfer traffic isn't
+    /// included.
+    pub sent_by_channel: HashMap<usize, ChannelTraffic>,
+    /// Received replicon messages by channel ID of the sending side: client channels on the
+    /// host, server channels on the client.
+    pub received_by_channel: HashMap<usize, ChannelTraffic>,
+    /// Traffic by channel since the last [`BandwidthProfile`] took it.
+    profile_sent: HashMap<usize, ChannelTraffic>,
+    profile_received: HashMap<usize, ChannelTraffic>,
+    window_start: Duration,
+    window_sent: u64,
+    window_received: u64,
+    window_send_frames: u32,
+    last_bytes_sent: u64,
+}
+
+impl ConnectionStats {
+    pub(crate) fn record_sent(&mut self, channel_id: usize, bytes: usize) {
+        self.sent_by_channel
+            .entry(channel_id)
+            .or_default()
+            .record(bytes);
+        self.profile_sent
+            .entry(channel_id)
+            .or_default()
+            .record(bytes);
+        self.packets_sent += 1;
+        self.bytes_sent += bytes as u64;
+        self.window_sent += bytes as u64;
+    }
+
+    pub(crate) fn record_received(&mut self, channel_id: usize, bytes: usize) {
+        self.received_by_channel
+            .entry(channel_id)
+            .or_default()
+            .record(bytes);
+        self.profile_received
+            .entry(channel_id)
+            .or_default()
+            .record(bytes);
+        self.packets_received += 1;
+        self.bytes_received += bytes as u64;
+        self.window_received += bytes as u64;
+    }
+
+    /// Updates [`Self::last_received`] for a message that is handed to replicon.
+    pub(crate) fn record_activity(&mut self, now: Duration) {
+        self.last_received = Some(now);
+    }
+
+    pub(crate) fn record_rtt(&mut self, sample: Duration) {
+        self.rtt = Some(match self.rtt {
+            Some(rtt) => rtt * 7 / 8 + sample / 8,
+            None => sample,
+        });
+    }
+
+    /// Updates the rates once the measuring window is full.
+    ///
+    /// Returns `true` if the rates were updated.
+    pub(crate) fn update_rates(&mut self, now: Duration) -> bool {
+        if self.bytes_sent != self.last_bytes_sent {
+            self.last_bytes_sent = self.bytes_sent;
+            self.window_send_frames += 1;
+        }
+        let elapsed = now.saturating_sub(self.window_start);
+        if elapsed < RATE_WINDOW {
+            return false;
+        }
+        self.sent_bytes_per_second = self.window_sent as f64 / elapsed.as_secs_f64();
+        self.received_bytes_per_second = self.window_received as f64 / elapsed.as_secs_f64();
+        self.send_interval =
+            (self.window_send_frames > 0).then(|| elapsed / self.window_send_frames);
+        self.window_start = now;
+        self.window_sent = 0;
+        self.window_received = 0;
+        self.window_send_frames = 0;
+        true
+    }
+
+    /// Counts a protocol violation, returns `true` if it should be logged.
+    ///
+    /// Logging is limited to every power of two, so a misbehaving peer can't flood the log.
+    pub(crate) fn record_violation(&mut self) -> bool {
+        self.protocol_violations += 1;
+        self.protocol_violations.is_power_of_two()
+    }
+
+    /// Updates the bandwidth estimate from the receive rate reported by the peer.
+    pub(crate) fn apply_report(&mut self, received_bytes_per_second: f64) {
+        self.reported_bytes_per_second = Some(received_bytes_per_second);
+        let congested = received_bytes_per_second < self.sent_bytes_per_second * CONGESTION_RATIO;
+        self.estimated_bandwidth = Some(match self.estimated_bandwidth {
+            Some(estimate) if !congested => estimate.max(received_bytes_per_second),
+            _ => received_bytes_per_second,
+        });
+    }
+
+    /// Share of the bytes sent over the last second that the peer didn't receive, from zero
+    /// to one. `None` until the first report or while nothing is sent.
+    ///
+    /// Compares [`Self::reported_bytes_per_second`] with [`Self::sent_bytes_per_second`].
+    /// Both are measured over slightly different seconds, so small values are noise.
+    pub fn loss(&self) -> Option<f64> {
+        let received = self.reported_bytes_per_second?;
+        if self.sent_bytes_per_second <= 0.0 {
+            return None;
+        }
+        Some((1.0 - received / self.sent_bytes_per_second).clamp(0.0, 1.0))
+    }
+
+    /// Send interval at which the current traffic fits the estimated bandwidth and `limit`.
+    ///
+    /// Longer than [`Self::send_interval`] if the link is saturated, shorter if there is room
+    /// left.
+    pub fn suggested_send_interval(&self, limit: Option<f64>) -> Option<Duration> {
+        let estimate = self.estimated_bandwidth?;
+        let capacity = limit.map_or(estimate, |limit| estimate.min(limit));
+        if capacity <= 0.0 || self.sent_bytes_per_second <= 0.0 {
+            return None;
+        }
+        let send_interval = self.send_interval?;
+        Some(send_interval.mul_f64(self.sent_bytes_per_second / capacity))
+    }
+}
+
+/// What a replicon channel carries.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Reflect)]
+pub enum MessageKind {
+    /// Spawns, despawns, insertions and removals on replicon's reliable replication channel.
+    Updates,
+    /// Component mutations on replicon's unreliable replication channel.
+    Mutations,
+    /// Acknowledgements of received mutations, sent by clients.
+    MutationAcks,
+    /// Remote events and triggers, on the channels they were registered with.
+    Events,
+}
+
+impl MessageKind {
+    pub(crate) fn of_server_channel(channel_id: usize) -> Self {
+        match channel_id {
+            id if id == ServerChannel::Updates as usize => Self::Updates,
+            id if id == ServerChannel::Mutations as usize => Self::Mutations,
+            _ => Self::Events,
+        }
+    }
+
+    pub(crate) fn of_client_channel(channel_id: usize) -> Self {
+        if channel_id == ClientChannel::MutationAcks as usize {
+            Self::MutationAcks
+        } else {
+            Self::Events
+        }
+    }
+}
+
+/// Traffic of a replicon channel during one profiling window.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelUsage {
+    pub channel_id: usize,
+    pub kind: MessageKind,
+    pub traffic: ChannelTraffic,
+    pub bytes_per_second: f64,
+    /// Share of the bytes of all channels in the same direction, from 0 to 1.
+    pub share: f64,
+}
+
+/// Replicon traffic of one profiling window, see
+/// [`MatchboxConfig::bandwidth_profile_window`](crate::MatchboxConfig::bandwidth_profile_window).
+///
+/// Channels are sorted by bytes, largest first. The transport only sees serialized
+/// messages, so replication is attributed to its channel; replicon packs all components
+/// into the same messages. Register chatty events on channels of their own to tell them
+/// apart.
+#[derive(Clone, Debug, Default)]
+pub struct BandwidthReport {
+    /// Actual length of the window, at least the configured one.
+    pub window: Duration,
+    pub sent: Vec<ChannelUsage>,
+    pub received: Vec<ChannelUsage>,
+}
+
+impl BandwidthReport {
+    fn usage(
+        traffic: HashMap<usize, ChannelTraffic>,
+        window: Duration,
+        kind: fn(usize) -> MessageKind,
+    ) -> Vec<ChannelUsage> {
+        let total: u64 = traffic.values().map(|traffic| traffic.bytes).sum();
+        let mut usage: Vec<_> = traffic
+            .into_iter()
+            .map(|(channel_id, traffic)| ChannelUsage {
+                channel_id,
+                kind: kind(channel_id),
+                bytes_per_second: traffic.bytes as f64 / window.as_secs_f64(),
+                share: if total > 0 {
+                    traffic.bytes as f64 / total as f64
+                } else {
+                    0.0
+                },
+                traffic,
+            })
+            .collect();
+        usage.sort_by(|a, b| {
+            b.traffic
+                .bytes
+                .cmp(&a.traffic.bytes)
+                .then(a.channel_id.cmp(&b.channel_id))
+        });
+        usage
+    }
+}
+
+/// Latest [`BandwidthReport`]s, updated at the end of every profiling window.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct BandwidthReports {
+    /// Traffic of the host with all clients.
+    pub host: Option<BandwidthReport>,
+    pub client: Option<BandwidthReport>,
+}
+
+/// Traffic collected from connections for the current profiling window.
+#[derive(Default)]
+pub(crate) struct BandwidthProfile {
+    window_start: Duration,
+    sent: HashMap<usize, ChannelTraffic>,
+    received: HashMap<usize, ChannelTraffic>,
+}
+
+impl BandwidthProfile {
+    /// Takes the traffic `stats` recorded since the last call.
+    pub(crate) fn collect(&mut self, stats: &mut ConnectionStats) {
+        for (channel_id, traffic) in stats.profile_sent.drain() {
+            self.sent.entry(channel_id).or_default().add(&traffic);
+        }
+        for (channel_id, traffic) in stats.profile_received.drain() {
+            self.received.entry(channel_id).or_default().add(&traffic);
+        }
+    }
+
+    /// Returns the report and starts a new window once `window` is over.
+    ///
+    /// `sent_kind` and `received_kind` classify the channels of each direction.
+    pub(crate) fn finish(
+        &mut self,
+        window: Duration,
+        now: Duration,
+        sent_kind: fn(usize) -> MessageKind,
+        received_kind: fn(usize) -> MessageKind,
+    ) -> Option<BandwidthReport> {
+        let elapsed = now.saturating_sub(self.window_start);
+        if elapsed < window || elapsed.is_zero() {
+            return None;
+        }
+        self.window_start = now;
+        Some(BandwidthReport {
+            window: elapsed,
+            sent: BandwidthReport::usage(std::mem::take(&mut self.sent), elapsed, sent_kind),
+            received: BandwidthReport::usage(
+                std::mem::take(&mut self.received),
+                elapsed,
+                received_kind,
+            ),
+        })
+    }
+}
+
+#[test]
+fn test_rates() {
+    let mut stats = ConnectionStats::default();
+    stats.record_sent(0, 100);
+    stats.record_received(0, 50);
+    stats.update_rates(Duration::from_millis(500));
+    assert_eq!(stats.sent_bytes_per_second, 0.0, "window isn't full yet");
+
+    stats.record_sent(1, 100);
+    stats.update_rates(Duration::from_secs(2));
+    assert_eq!(stats.sent_bytes_per_second, 100.0);
+    assert_eq!(stats.received_bytes_per_second, 25.0);
+    assert_eq!(stats.packets_sent, 2);
+    assert_eq!(
+        stats.send_interval,
+        Some(Duration::from_secs(1)),
+        "two frames with sends over two seconds"
+    );
+    assert_eq!(stats.sent_by_channel[&1].messages, 1);
+    assert_eq!(stats.sent_by_channel[&1].bytes, 100);
+    assert_eq!(stats.received_by_channel[&0].bytes, 50);
+}
+
+#[test]
+fn test_bandwidth_estimate() {
+    let mut stats = ConnectionStats {
+        sent_bytes_per_second: 1000.0,
+        ..Default::default()
+    };
+    stats.apply_report(1000.0);
+    assert_eq!(stats.estimated_bandwidth, Some(1000.0));
+
+    stats.sent_bytes_per_second = 100.0;
+    stats.apply_report(100.0);
+    assert_eq!(
+        stats.estimated_bandwidth,
+        Some(1000.0),
+        "quiet periods keep the estimate"
+    );
+
+    stats.sent_bytes_per_second = 2000.0;
+    stats.apply_report(1200.0);
+    assert_eq!(stats.estimated_bandwidth, Some(1200.0), "congested");
+}
+
+#[test]
+fn test_loss() {
+    let mut stats = ConnectionStats {
+        sent_bytes_per_second: 1000.0,
+        ..Default::default()
+    };
+    assert_eq!(stats.loss(), None, "no report yet");
+
+    stats.apply_report(750.0);
+    assert_eq!(stats.loss(), Some(0.25));
+    stats.apply_report(1100.0);
+    assert_eq!(stats.loss(), Some(0.0), "reports ahead of the send rate");
+
+    stats.sent_bytes_per_second = 0.0;
+    assert_eq!(stats.loss(), None, "nothing sent");
+}
+
+#[test]
+fn test_suggested_send_interval() {
+    let mut stats = ConnectionStats {
+        sent_bytes_per_second: 2000.0,
+        send_interval: Some(Duration::from_millis(50)),
+        ..Default::default()
+    };
+    assert_eq!(stats.suggested_send_interval(None), None, "no report yet");
+
+    stats.apply_report(1000.0);
+    assert_eq!(
+        stats.suggested_send_interval(None),
+        Some(Duration::from_millis(100))
+    );
+    assert_eq!(
+        stats.suggested_send_interval(Some(500.0)),
+        Some(Duration::from_millis(200))
+    );
+}
+
+#[test]
+fn test_violation_logging() {
+    let mut stats = ConnectionStats::default();
+    let logged: Vec<_> = (0..8).map(|_| stats.record_violation()).collect();
+    assert_eq!(logged, [true, true, false, true, false, false, false, true]);
+}
+
+#[test]
+fn test_host_stats() {
+    let clients = [
+        ConnectionStats {
+            sent_bytes_per_second: 100.0,
+            rtt: Some(Duration::from_millis(20)),
+            ..Default::default()
+        },
+        ConnectionStats {
+            sent_bytes_per_second: 50.0,
+            protocol_violations: 2,
+            rtt: Some(Duration::from_millis(40)),
+            ..Default::default()
+        },
+        ConnectionStats::default(),
+    ];
+    let mut stats = HostStats::default();
+    stats.aggregate(clients.iter());
+    assert_eq!(stats.connections, 3);
+    assert_eq!(stats.sent_bytes_per_second, 150.0);
+    assert_eq!(stats.protocol_violations, 2);
+    assert_eq!(stats.average_rtt, Some(Duration::from_millis(30)));
+
+    let mut clients = [ConnectionStats::default(), ConnectionStats::default()];
+    clients[0].record_sent(0, 10);
+    clients[1].record_sent(0, 20);
+    clients[1].record_sent(2, 5);
+    stats.aggregate(clients.iter());
+    assert_eq!(stats.sent_by_channel[&0].messages, 2);
+    assert_eq!(stats.sent_by_channel[&0].bytes, 30);
+    assert_eq!(stats.sent_by_channel[&0].sizes.larger_than(0), 2);
+    assert_eq!(stats.sent_by_channel[&2].messages, 1);
+
+    let mut stats = ConnectionStats::default();
+    stats.record_rtt(Duration::from_millis(80));
+    stats.record_rtt(Duration::from_millis(0));
+    assert_eq!(stats.rtt, Some(Duration::from_millis(70)));
+}
+
+#[test]
+fn test_size_histogram() {
+    let mut histogram = SizeHistogram::default();
+    for bytes in [0, 64, 65, 128, 129, 16 * 1024, 16 * 1024 + 1, usize::MAX] {
+        histogram.record(bytes);
+    }
+    let buckets: Vec<_> = histogram.buckets().collect();
+    assert_eq!(buckets.len(), SIZE_BUCKETS);
+    assert_eq!(buckets[0], (64, 2));
+    assert_eq!(buckets[1], (128, 2));
+    assert_eq!(buckets[2], (256, 1));
+    assert_eq!(buckets[8], (16 * 1024, 1));
+    assert_eq!(buckets[9], (usize::MAX, 2));
+    assert_eq!(histogram.larger_than(128), 4);
+    assert_eq!(histogram.larger_than(16 * 1024), 2);
+}
+
+#[test]
+fn test_bandwidth_profile() {
+    let mut profile = BandwidthProfile::default();
+    let mut clients = [ConnectionStats::default(), ConnectionStats::default()];
+    clients[0].record_sent(ServerChannel::Mutations as usize, 300);
+    clients[1].record_sent(ServerChannel::Mutations as usize, 300);
+    clients[1].record_sent(5, 400);
+    clients[1].record_received(ClientChannel::MutationAcks as usize, 10);
+    for stats in &mut clients {
+        profile.collect(stats);
+    }
+    let finish = |profile: &mut BandwidthProfile, now| {
+        profile.finish(
+            Duration::from_secs(2),
+            Duration::from_secs(now),
+            MessageKind::of_server_channel,
+            MessageKind::of_client_channel,
+        )
+    };
+    assert!(finish(&mut profile, 1).is_none(), "window isn't over yet");
+
+    let report = finish(&mut profile, 2).unwrap();
+    assert_eq!(report.window, Duration::from_secs(2));
+    assert_eq!(report.sent.len(), 2);
+    assert_eq!(report.sent[0].kind, MessageKind::Mutations);
+    assert_eq!(report.sent[0].traffic.messages, 2);
+    assert_eq!(report.sent[0].bytes_per_second, 300.0);
+    assert_eq!(report.sent[0].share, 0.6);
+    assert_eq!(report.sent[1].kind, MessageKind::Events);
+    assert_eq!(report.received[0].kind, MessageKind::MutationAcks);
+
+    profile.collect(&mut clients[0]);
+    let report = finish(&mut profile, 4).unwrap();
+    assert!(report.sent.is_empty(), "traffic is only reported once");
+}
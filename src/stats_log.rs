@@ -0,0 +1,138 @@
+use crate::stats::ConnectionStats;
+use bevy::prelude::*;
+use bevy_matchbox::matchbox_socket::PeerId;
+use std::io::{self, Write};
+use std::time::Duration;
+
+const HEADER: &str =
+    "timestamp,peer_id,channel_id,direction,messages,bytes,bytes_per_second,rtt_ms";
+
+/// Appends [`ConnectionStats`] snapshots as CSV to the stats log of a host.
+///
+/// The log starts with a header line, followed by rows for every client at each snapshot:
+///
+/// | column             | description                                                  |
+/// |--------------------|--------------------------------------------------------------|
+/// | `timestamp`        | seconds of [`Time<Real>`] elapsed time                       |
+/// | `peer_id`          | UUID of the client                                           |
+/// | `channel_id`       | replicon channel ID, empty for the totals of the connection  |
+/// | `direction`        | `sent` or `received`                                         |
+/// | `messages`         | messages so far                                              |
+/// | `bytes`            | bytes so far                                                 |
+/// | `bytes_per_second` | rate over the last full second, empty for channels           |
+/// | `rtt_ms`           | smoothed round-trip time, empty for channels and until known |
+///
+/// Counters are totals since the client connected, so trends are the differences between
+/// snapshots.
+pub(crate) struct StatsLog {
+    writer: Box<dyn Write + Send + Sync>,
+    interval: Duration,
+    next_at: Duration,
+}
+
+impl StatsLog {
+    pub(crate) fn new(
+        mut writer: Box<dyn Write + Send + Sync>,
+        interval: Duration,
+    ) -> io::Result<Self> {
+        writeln!(writer, "{HEADER}")?;
+        Ok(Self {
+            writer,
+            interval,
+            next_at: Duration::ZERO,
+        })
+    }
+
+    fn write<'a>(
+        &mut self,
+        now: Duration,
+        clients: impl Iterator<Item = (PeerId, &'a ConnectionStats)>,
+    ) -> io::Result<()> {
+        let timestamp = now.as_secs_f64();
+        for (peer_id, stats) in clients {
+            let rtt = stats
+                .rtt
+                .map(|rtt| format!("{:.1}", rtt.as_secs_f64() * 1000.0))
+                .unwrap_or_default();
+            writeln!(
+                self.writer,
+                "{timestamp:.3},{peer_id},,sent,{},{},{:.0},{rtt}",
+                stats.packets_sent, stats.bytes_sent, stats.sent_bytes_per_second
+            )?;
+            writeln!(
+                self.writer,
+                "{timestamp:.3},{peer_id},,received,{},{},{:.0},{rtt}",
+                stats.packets_received, stats.bytes_received, stats.received_bytes_per_second
+            )?;
+            for (direction, by_channel) in [
+                ("sent", &stats.sent_by_channel),
+                ("received", &stats.received_by_channel),
+            ] {
+                let mut channels: Vec<_> = by_channel.iter().collect();
+                channels.sort_unstable_by_key(|&(&channel_id, _)| channel_id);
+                for (channel_id, traffic) in channels {
+                    writeln!(
+                        self.writer,
+                        "{timestamp:.3},{peer_id},{channel_id},{direction},{},{},,",
+                        traffic.messages, traffic.bytes
+                    )?;
+                }
+            }
+        }
+        self.writer.flush()
+    }
+
+    pub(crate) fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Appends a snapshot once the interval is up, stopping the log on write errors.
+pub(crate) fn log_snapshot<'a>(
+    log: &mut Option<StatsLog>,
+    now: Duration,
+    clients: impl Iterator<Item = (PeerId, &'a ConnectionStats)>,
+) {
+    let Some(writer) = log else {
+        return;
+    };
+    if now < writer.next_at {
+        return;
+    }
+    writer.next_at = now + writer.interval;
+    if let Err(e) = writer.write(now, clients) {
+        error!("stopping stats log: {e}");
+        *log = None;
+    }
+}
+
+#[test]
+fn test_stats_log() {
+    use crate::capture::SharedBuf;
+
+    let buf = SharedBuf::default();
+    let interval = Duration::from_secs(1);
+    let mut log = Some(StatsLog::new(Box::new(buf.clone()), interval).unwrap());
+    let peer_id = PeerId(uuid::Uuid::from_u128(42));
+    let mut stats = ConnectionStats::default();
+    stats.record_sent(1, 10);
+
+    log_snapshot(&mut log, Duration::ZERO, [(peer_id, &stats)].into_iter());
+    log_snapshot(
+        &mut log,
+        Duration::from_millis(500),
+        [(peer_id, &stats)].into_iter(),
+    );
+
+    let data = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+    assert_eq!(
+        data,
+        format!(
+            "{HEADER}\n\
+            0.000,{peer_id},,sent,1,10,0,\n\
+            0.000,{peer_id},,received,0,0,0,\n\
+            0.000,{peer_id},1,sent,1,10,,\n"
+        ),
+        "the second snapshot is within the interval"
+    );
+}
@@ -0,0 +1,220 @@
+//! Helpers for integration tests of games using this backend.
+
+use crate::client::MatchboxClient;
+use crate::loopback::{LoopbackClient, LoopbackConnection, LoopbackHost, SharedQueues};
+use crate::server::{MatchboxHost, ServerPluginSettings};
+use crate::shared::RepliconMatchboxPlugins;
+use bevy::prelude::*;
+use bevy_replicon::prelude::*;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Time [`TestPair::update_until`] waits by default.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A host and a client app connected through an embedded signaling server on a free port.
+///
+/// ```no_run
+/// # use bevy::prelude::*;
+/// # use bevy_replicon::prelude::*;
+/// # use bevy_replicon_matchbox::test_utils::TestPair;
+/// # #[derive(Component, Clone, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+/// # struct Health(u32);
+/// let mut pair = TestPair::new(|app| {
+///     app.replicate::<Health>();
+/// });
+/// pair.host.world_mut().spawn((Replicated, Health(100)));
+/// pair.update_until_replicated::<Health>(1);
+/// assert_eq!(pair.client_components::<Health>(), [Health(100)]);
+/// ```
+pub struct TestPair {
+    pub host: App,
+    pub client: App,
+}
+
+impl TestPair {
+    /// Creates both apps, calling `setup` on each to register the game's replication, and
+    /// waits until the client is connected.
+    ///
+    /// Replicon sends every frame, independent of the tick policy in the game.
+    pub fn new(setup: impl Fn(&mut App)) -> Self {
+        let mut host = test_app(&setup);
+        let mut client = test_app(&setup);
+
+        let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, 0));
+        let channels = host.world().resource::<RepliconChannels>();
+        let (matchbox_host, signaling) =
+            MatchboxHost::with_embedded_signaling(addr, "test", channels)
+                .expect("signaling server should start");
+        let room_url = signaling
+            .room_url("test")
+            .expect("server should be running");
+        host.insert_resource(matchbox_host);
+        host.insert_resource(signaling);
+
+        let channels = client.world().resource::<RepliconChannels>();
+        let matchbox_client =
+            MatchboxClient::new(room_url, channels).expect("client should be created");
+        client.insert_resource(matchbox_client);
+
+        let mut pair = Self { host, client };
+        pair.update_until(|host, client| {
+            host.world().resource::<MatchboxHost>().connected_clients() > 0
+                && client.world().resource::<RepliconClient>().is_connected()
+        });
+        pair
+    }
+
+    /// Runs a frame of the client and then of the host.
+    pub fn update(&mut self) {
+        self.client.update();
+        self.host.update();
+    }
+
+    /// Updates both apps until `condition` holds, panicking after [`DEFAULT_TIMEOUT`].
+    pub fn update_until(&mut self, condition: impl FnMut(&mut App, &mut App) -> bool) {
+        self.update_until_timeout(DEFAULT_TIMEOUT, condition);
+    }
+
+    /// Updates both apps until `condition` holds, panicking after `timeout`.
+    pub fn update_until_timeout(
+        &mut self,
+        timeout: Duration,
+        mut condition: impl FnMut(&mut App, &mut App) -> bool,
+    ) {
+        let start = Instant::now();
+        loop {
+            self.update();
+            if condition(&mut self.host, &mut self.client) {
+                return;
+            }
+            assert!(
+                start.elapsed() < timeout,
+                "condition didn't hold within {timeout:?}"
+            );
+        }
+    }
+
+    /// Updates both apps until the client has `count` entities with `C`.
+    pub fn update_until_replicated<C: Component>(&mut self, count: usize) {
+        self.update_until(|_, client| {
+            let mut query = client.world_mut().query::<&C>();
+            query.iter(client.world()).len() == count
+        });
+    }
+
+    /// Components of type `C` on the client.
+    pub fn client_components<C: Component + Clone>(&mut self) -> Vec<C> {
+        let mut query = self.client.world_mut().query::<&C>();
+        query.iter(self.client.world()).cloned().collect()
+    }
+
+    /// Entity of the client on the host.
+    pub fn client_entity(&mut self) -> Entity {
+        let mut query = self
+            .host
+            .world_mut()
+            .query_filtered::<Entity, With<ConnectedClient>>();
+        query
+            .single(self.host.world())
+            .expect("host should have a single client")
+    }
+}
+
+fn test_app(setup: &impl Fn(&mut App)) -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        RepliconPlugins.set(ServerPlugin {
+            tick_policy: TickPolicy::EveryFrame,
+            ..Default::default()
+        }),
+        RepliconMatchboxPlugins,
+    ));
+    setup(&mut app);
+    app.finish();
+    app.cleanup();
+    app
+}
+
+/// Drop-in for replicon's `ServerTestAppExt`, connecting the apps through a [`LoopbackHost`].
+///
+/// Tests written against replicon's test backend only need to import this trait instead.
+/// Like the original, [`Self::exchange_with_client`] moves messages without updating the apps.
+pub trait ServerTestAppExt {
+    fn connect_client(&mut self, client_app: &mut App);
+    fn disconnect_client(&mut self, client_app: &mut App);
+    fn exchange_with_client(&mut self, client_app: &mut App);
+}
+
+impl ServerTestAppExt for App {
+    fn connect_client(&mut self, client_app: &mut App) {
+        let queues = SharedQueues::default();
+        let max_size = self.world().resource::<ServerPluginSettings>().max_size;
+        self.world_mut().init_resource::<LoopbackHost>();
+        self.world_mut().spawn((
+            ConnectedClient { max_size },
+            LoopbackConnection(queues.clone()),
+        ));
+        self.world_mut()
+            .resource_mut::<RepliconServer>()
+            .set_running(true);
+
+        client_app.insert_resource(LoopbackClient(queues));
+        client_app
+            .world_mut()
+            .resource_mut::<RepliconClient>()
+            .set_status(RepliconClientStatus::Connected);
+    }
+
+    fn disconnect_client(&mut self, client_app: &mut App) {
+        let client_entity = loopback_entity(self, client_app);
+        self.world_mut().despawn(client_entity);
+        client_app.world_mut().remove_resource::<LoopbackClient>();
+        client_app
+            .world_mut()
+            .resource_mut::<RepliconClient>()
+            .set_status(RepliconClientStatus::Disconnected);
+    }
+
+    fn exchange_with_client(&mut self, client_app: &mut App) {
+        let client_entity = loopback_entity(self, client_app);
+        let queues = client_app.world().resource::<LoopbackClient>().0.clone();
+        let mut queues = queues.lock().unwrap();
+
+        let mut replicon_server = self.world_mut().resource_mut::<RepliconServer>();
+        let sent: Vec<_> = replicon_server.drain_sent().collect();
+        for (entity, channel_id, message) in sent {
+            if entity == client_entity {
+                queues.to_client.push((channel_id, message));
+            } else {
+                replicon_server.send(entity, channel_id, message);
+            }
+        }
+
+        let mut replicon_client = client_app.world_mut().resource_mut::<RepliconClient>();
+        queues.to_host.extend(replicon_client.drain_sent());
+        for (channel_id, message) in queues.to_client.drain(..) {
+            replicon_client.insert_received(channel_id, message);
+        }
+
+        let mut replicon_server = self.world_mut().resource_mut::<RepliconServer>();
+        for (channel_id, message) in queues.to_host.drain(..) {
+            replicon_server.insert_received(client_entity, channel_id, message);
+        }
+    }
+}
+
+/// Entity of the loopback client of `client_app` on the host.
+fn loopback_entity(host_app: &mut App, client_app: &App) -> Entity {
+    let queues = &client_app.world().resource::<LoopbackClient>().0;
+    let mut connections = host_app
+        .world_mut()
+        .query::<(Entity, &LoopbackConnection)>();
+    connections
+        .iter(host_app.world())
+        .find(|(_, connection)| Arc::ptr_eq(&connection.0, queues))
+        .map(|(client_entity, _)| client_entity)
+        .expect("client should be connected to the host")
+}
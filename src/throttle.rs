@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Cool-downs the host applies to peers that connect over and over.
+///
+/// Matchbox assigns a new `PeerId` to every socket, so repeated connections are recognized
+/// by the session token a client joins with: the one of its previous connection when it
+/// rejoins automatically or through `MatchboxClient::rejoin`. Clients joining without a
+/// session token can't be told apart and aren't throttled. Attempts during a cool-down are
+/// rejected with `DisconnectKind::Kicked` before a client entity is spawned or reattached.
+#[derive(Clone, Debug)]
+pub struct ReconnectThrottle {
+    /// Attempts allowed before the first cool-down.
+    pub free_attempts: u32,
+    /// First cool-down, doubling with every further attempt.
+    pub base_cooldown: Duration,
+    pub max_cooldown: Duration,
+    /// Attempts are forgotten after this long without a new one.
+    pub reset_after: Duration,
+}
+
+impl Default for ReconnectThrottle {
+    fn default() -> Self {
+        Self {
+            free_attempts: 5,
+            base_cooldown: Duration::from_secs(2),
+            max_cooldown: Duration::from_secs(300),
+            reset_after: Duration::from_secs(60),
+        }
+    }
+}
+
+impl ReconnectThrottle {
+    fn cooldown(&self, throttled_attempts: u32) -> Duration {
+        self.base_cooldown
+            .checked_mul(1 << throttled_attempts.min(31))
+            .unwrap_or(Duration::MAX)
+            .min(self.max_cooldown)
+    }
+}
+
+struct Attempts {
+    count: u32,
+    last: Duration,
+    blocked_until: Duration,
+}
+
+/// Connection attempts of the host, keyed by session token.
+#[derive(Default)]
+pub(crate) struct ConnectionThrottle {
+    attempts: HashMap<u64, Attempts>,
+}
+
+impl ConnectionThrottle {
+    /// Records an attempt, returns `false` if it has to be rejected.
+    pub(crate) fn attempt(
+        &mut self,
+        throttle: &ReconnectThrottle,
+        session_token: u64,
+        now: Duration,
+    ) -> bool {
+        self.attempts.retain(|_, attempts| {
+            now.saturating_sub(attempts.last) < throttle.reset_after || now < attempts.blocked_until
+        });
+        let attempts = self.attempts.entry(session_token).or_insert(Attempts {
+            count: 0,
+            last: now,
+            blocked_until: Duration::ZERO,
+        });
+        attempts.count += 1;
+        attempts.last = now;
+        if now < attempts.blocked_until {
+            return false;
+        }
+        if let Some(throttled) = attempts.count.checked_sub(throttle.free_attempts) {
+            attempts.blocked_until = now + throttle.cooldown(throttled);
+        }
+        true
+    }
+}
+
+#[test]
+fn test_throttle() {
+    let throttle = ReconnectThrottle {
+        free_attempts: 2,
+        base_cooldown: Duration::from_secs(1),
+        max_cooldown: Duration::from_secs(3),
+        reset_after: Duration::from_secs(10),
+    };
+    let session_token = 42;
+    let mut state = ConnectionThrottle::default();
+    let at = Duration::from_secs;
+
+    assert!(state.attempt(&throttle, session_token, at(0)));
+    assert!(state.attempt(&throttle, session_token, at(0)));
+    assert!(
+        !state.attempt(&throttle, session_token, at(0)),
+        "rejected during the cool-down"
+    );
+    assert!(
+        state.attempt(&throttle, 7, at(0)),
+        "other sessions aren't affected"
+    );
+    assert!(state.attempt(&throttle, session_token, at(1)));
+    assert!(
+        !state.attempt(&throttle, session_token, at(3)),
+        "rejected attempts lengthen the cool-down"
+    );
+    assert!(
+        state.attempt(&throttle, session_token, at(4)),
+        "cool-down capped"
+    );
+    assert!(
+        state.attempt(&throttle, session_token, at(30)),
+        "attempts forgotten"
+    );
+}
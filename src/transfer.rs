@@ -0,0 +1,899 @@
+use crate::shared::*;
+use bevy::prelude::*;
+use bevy_matchbox::MatchboxSocket;
+use bevy_matchbox::matchbox_socket::{Packet, PeerId};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+
+#[cfg(feature = "client")]
+use crate::client::MatchboxClient;
+#[cfg(feature = "server")]
+use crate::server::{MatchboxHost, protocol_violation};
+#[cfg(feature = "server")]
+use crate::stats::ConnectionStats;
+
+/// Payload bytes per chunk, below the 16 KiB message size that all WebRTC implementations
+/// handle.
+const CHUNK_SIZE: usize = 16_000;
+
+/// Chunks sent per transfer and frame, so transfers don't starve other channels.
+const CHUNKS_PER_FRAME: usize = 8;
+
+/// Upper bound of the serialized fields besides the chunk data.
+const PACKET_OVERHEAD: usize = 64;
+
+/// Partial downloads kept for resumption after their sender disconnected.
+const MAX_PARKED_TRANSFERS: usize = 4;
+
+/// Finished incoming transfers remembered to ignore replayed packets.
+const FINISHED_WINDOW: usize = 64;
+
+/// Identifies a transfer together with the sending peer.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TransferId(pub u64);
+
+/// Progress of a transfer, emitted on the sender and the receiver.
+#[derive(Event, Debug, Clone)]
+pub struct TransferProgress {
+    pub id: TransferId,
+    /// Receiver on the sending side, sender on the receiving side.
+    pub peer_id: PeerId,
+    pub transferred: u64,
+    pub total: u64,
+}
+
+/// A transfer was fully received.
+#[derive(Event, Debug, Clone)]
+pub struct TransferCompleted {
+    pub id: TransferId,
+    pub sender: PeerId,
+    pub data: Bytes,
+}
+
+/// A transfer was fully received, but its content doesn't match the hash announced by the
+/// sender.
+///
+/// The data is discarded. Sending it again starts from scratch.
+#[derive(Event, Debug, Clone)]
+pub struct TransferCorrupted {
+    pub id: TransferId,
+    pub sender: PeerId,
+}
+
+/// A transfer was cancelled by the sender, rejected by the receiver or because the peer
+/// disconnected.
+///
+/// Partial downloads from disconnected peers are kept for a while to resume them.
+#[derive(Event, Debug, Clone)]
+pub struct TransferCancelled {
+    pub id: TransferId,
+    pub peer_id: PeerId,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+enum TransferPacket<'a> {
+    /// Announces a transfer, the sender waits for [`TransferPacket::Accept`] before sending
+    /// chunks.
+    Start {
+        id: TransferId,
+        total: u64,
+        /// SHA-256 of the content, verified on completion and used to resume interrupted
+        /// transfers.
+        hash: ContentHash,
+    },
+    /// Accepts a transfer, listing already received chunk ranges of an interrupted one.
+    Accept {
+        id: TransferId,
+        received: Vec<(u32, u32)>,
+    },
+    Chunk {
+        id: TransferId,
+        index: u32,
+        #[serde(borrow)]
+        data: &'a [u8],
+    },
+    /// Acknowledges received chunk ranges.
+    Ack {
+        id: TransferId,
+        received: Vec<(u32, u32)>,
+    },
+    Cancel {
+        id: TransferId,
+    },
+    /// Declines a transfer, e.g. because it's too large.
+    Reject {
+        id: TransferId,
+    },
+}
+
+impl TransferPacket<'_> {
+    fn to_packet(&self) -> Packet {
+        let max_size = match self {
+            TransferPacket::Chunk { data, .. } => data.len(),
+            TransferPacket::Accept { received, .. } | TransferPacket::Ack { received, .. } => {
+                received.len() * 10
+            }
+            _ => 0,
+        };
+        to_vec_packet(self, max_size + PACKET_OVERHEAD)
+    }
+}
+
+type ContentHash = [u8; 32];
+
+fn content_hash(data: &[u8]) -> ContentHash {
+    Sha256::digest(data).into()
+}
+
+/// Collapses sorted chunk indices into `[start, end)` ranges.
+fn to_ranges(indices: impl IntoIterator<Item = u32>) -> Vec<(u32, u32)> {
+    let mut ranges: Vec<(u32, u32)> = Vec::new();
+    for index in indices {
+        match ranges.last_mut() {
+            Some((_, end)) if *end == index => *end += 1,
+            _ => ranges.push((index, index + 1)),
+        }
+    }
+    ranges
+}
+
+fn chunk_count(len: usize) -> usize {
+    len.div_ceil(CHUNK_SIZE)
+}
+
+fn chunk_len(total: usize, index: usize) -> usize {
+    (total - index * CHUNK_SIZE).min(CHUNK_SIZE)
+}
+
+struct OutgoingTransfer {
+    id: TransferId,
+    peer_id: PeerId,
+    data: Bytes,
+    hash: ContentHash,
+    started: bool,
+    accepted: bool,
+    next_chunk: usize,
+    acked: Vec<bool>,
+}
+
+impl OutgoingTransfer {
+    fn mark_acked(&mut self, ranges: &[(u32, u32)]) {
+        for &(start, end) in ranges {
+            let end = (end as usize).min(self.acked.len());
+            for acked in self.acked.get_mut(start as usize..end).unwrap_or_default() {
+                *acked = true;
+            }
+        }
+    }
+
+    fn acked_bytes(&self) -> u64 {
+        self.acked
+            .iter()
+            .enumerate()
+            .filter(|&(_, &acked)| acked)
+            .map(|(index, _)| chunk_len(self.data.len(), index) as u64)
+            .sum()
+    }
+
+    fn is_done(&self) -> bool {
+        self.started && self.acked.iter().all(|&acked| acked)
+    }
+}
+
+struct IncomingTransfer {
+    hash: ContentHash,
+    data: Vec<u8>,
+    received: Vec<bool>,
+}
+
+impl IncomingTransfer {
+    fn received_bytes(&self) -> u64 {
+        self.received
+            .iter()
+            .enumerate()
+            .filter(|&(_, &received)| received)
+            .map(|(index, _)| chunk_len(self.data.len(), index) as u64)
+            .sum()
+    }
+
+    fn received_ranges(&self) -> Vec<(u32, u32)> {
+        to_ranges(
+            self.received
+                .iter()
+                .enumerate()
+                .filter(|&(_, &received)| received)
+                .map(|(index, _)| index as u32),
+        )
+    }
+}
+
+/// Events produced while processing transfers.
+pub(crate) enum TransferEvent {
+    Progress(TransferProgress),
+    Completed(TransferCompleted),
+    Corrupted(TransferCorrupted),
+    Cancelled(TransferCancelled),
+    /// The peer announced a transfer over the limit, handled as a protocol violation.
+    Oversized,
+}
+
+/// Outgoing and incoming transfers of a host or client.
+#[derive(Default)]
+pub(crate) struct Transfers {
+    next_id: u64,
+    outgoing: Vec<OutgoingTransfer>,
+    incoming: HashMap<(PeerId, TransferId), IncomingTransfer>,
+    /// Interrupted downloads by content hash and size.
+    parked: Vec<((ContentHash, u64), IncomingTransfer)>,
+    /// Chunks received since the last update, acknowledged in batches.
+    acks: HashMap<(PeerId, TransferId), Vec<u32>>,
+    replies: Vec<(PeerId, Packet)>,
+    /// Recently completed or cancelled incoming transfers, so duplicated packets can't
+    /// start or complete them again.
+    finished: VecDeque<(PeerId, TransferId)>,
+}
+
+impl Transfers {
+    fn start(&mut self, peers: impl IntoIterator<Item = PeerId>, data: Bytes) -> TransferId {
+        let id = TransferId(self.next_id);
+        self.next_id += 1;
+        let hash = content_hash(&data);
+        for peer_id in peers {
+            self.outgoing.push(OutgoingTransfer {
+                id,
+                peer_id,
+                data: data.clone(),
+                hash,
+                started: false,
+                accepted: false,
+                next_chunk: 0,
+                acked: vec![false; chunk_count(data.len())],
+            });
+        }
+        id
+    }
+
+    fn cancel(&mut self, socket: &mut MatchboxSocket, channel: usize, id: TransferId) {
+        self.outgoing.retain(|transfer| {
+            if transfer.id != id {
+                return true;
+            }
+            let packet = TransferPacket::Cancel { id }.to_packet();
+            socket.channel_mut(channel).send(packet, transfer.peer_id);
+            false
+        });
+    }
+
+    /// Sends pending replies and acknowledgments and the next chunks of outgoing transfers.
+    pub(crate) fn update(&mut self, socket: &mut MatchboxSocket, channel: usize) {
+        for (peer_id, packet) in self.replies.drain(..) {
+            socket.channel_mut(channel).send(packet, peer_id);
+        }
+        for ((peer_id, id), mut indices) in self.acks.drain() {
+            indices.sort_unstable();
+            let received = to_ranges(indices);
+            let packet = TransferPacket::Ack { id, received }.to_packet();
+            socket.channel_mut(channel).send(packet, peer_id);
+        }
+
+        for transfer in &mut self.outgoing {
+            if !transfer.started {
+                let packet = TransferPacket::Start {
+                    id: transfer.id,
+                    total: transfer.data.len() as u64,
+                    hash: transfer.hash,
+                }
+                .to_packet();
+                socket.channel_mut(channel).send(packet, transfer.peer_id);
+                transfer.started = true;
+            }
+            if !transfer.accepted {
+                continue;
+            }
+            let mut sent = 0;
+            while sent < CHUNKS_PER_FRAME && transfer.next_chunk < transfer.acked.len() {
+                let index = transfer.next_chunk;
+                transfer.next_chunk += 1;
+                if transfer.acked[index] {
+                    continue;
+                }
+                let start = index * CHUNK_SIZE;
+                let data = &transfer.data[start..start + chunk_len(transfer.data.len(), index)];
+                let packet = TransferPacket::Chunk {
+                    id: transfer.id,
+                    index: index as u32,
+                    data,
+                }
+                .to_packet();
+                socket.channel_mut(channel).send(packet, transfer.peer_id);
+                sent += 1;
+            }
+        }
+        self.outgoing.retain(|transfer| !transfer.is_done());
+    }
+
+    /// Processes a packet from the transfer channel.
+    ///
+    /// Transfers that would exceed `max_incoming` bytes in progress from the peer are rejected.
+    pub(crate) fn receive(
+        &mut self,
+        from: PeerId,
+        packet: &[u8],
+        max_incoming: u64,
+    ) -> Option<TransferEvent> {
+        let Ok(transfer_packet) = from_packet::<TransferPacket>(packet) else {
+            error!("failed to deserialize transfer packet from {from}");
+            return None;
+        };
+        match transfer_packet {
+            TransferPacket::Start { id, total, hash } => {
+                if self.incoming.contains_key(&(from, id)) || self.finished.contains(&(from, id)) {
+                    debug!("ignoring duplicate start of transfer {id:?} from {from}");
+                    return None;
+                }
+                let pending: u64 = self
+                    .incoming
+                    .iter()
+                    .filter(|((peer_id, _), _)| *peer_id == from)
+                    .map(|(_, incoming)| incoming.data.len() as u64)
+                    .sum();
+                if pending.saturating_add(total) > max_incoming {
+                    self.replies
+                        .push((from, TransferPacket::Reject { id }.to_packet()));
+                    return Some(TransferEvent::Oversized);
+                }
+                let incoming = match self
+                    .parked
+                    .iter()
+                    .position(|&(key, _)| key == (hash, total))
+                {
+                    Some(index) => {
+                        debug!("resuming transfer {id:?} from {from}");
+                        self.parked.remove(index).1
+                    }
+                    None => IncomingTransfer {
+                        hash,
+                        data: vec![0; total as usize],
+                        received: vec![false; chunk_count(total as usize)],
+                    },
+                };
+                let received = incoming.received_ranges();
+                self.replies
+                    .push((from, TransferPacket::Accept { id, received }.to_packet()));
+                self.incoming.insert((from, id), incoming);
+                self.complete_if_received(from, id)
+            }
+            TransferPacket::Accept { id, received } | TransferPacket::Ack { id, received } => {
+                let transfer = self
+                    .outgoing
+                    .iter_mut()
+                    .find(|transfer| transfer.id == id && transfer.peer_id == from)?;
+                transfer.accepted = true;
+                let previous = transfer.acked_bytes();
+                transfer.mark_acked(&received);
+                if transfer.acked_bytes() == previous {
+                    return None;
+                }
+                Some(TransferEvent::Progress(TransferProgress {
+                    id,
+                    peer_id: from,
+                    transferred: transfer.acked_bytes(),
+                    total: transfer.data.len() as u64,
+                }))
+            }
+            TransferPacket::Chunk { id, index, data } => {
+                let incoming = self.incoming.get_mut(&(from, id))?;
+                let in_bounds = incoming
+                    .received
+                    .get(index as usize)
+                    .is_some_and(|_| data.len() == chunk_len(incoming.data.len(), index as usize));
+                if !in_bounds {
+                    error!("chunk {index} of transfer {id:?} from {from} is out of bounds");
+                    self.finish(from, id);
+                    return Some(TransferEvent::Cancelled(TransferCancelled {
+                        id,
+                        peer_id: from,
+                    }));
+                }
+                if incoming.received[index as usize] {
+                    return None;
+                }
+                // only in bounds now, a crafted index would overflow on 32-bit targets
+                let start = index as usize * CHUNK_SIZE;
+                incoming.data[start..start + data.len()].copy_from_slice(data);
+                incoming.received[index as usize] = true;
+                self.acks.entry((from, id)).or_default().push(index);
+                if let Some(event) = self.complete_if_received(from, id) {
+                    return Some(event);
+                }
+                let incoming = &self.incoming[&(from, id)];
+                Some(TransferEvent::Progress(TransferProgress {
+                    id,
+                    peer_id: from,
+                    transferred: incoming.received_bytes(),
+                    total: incoming.data.len() as u64,
+                }))
+            }
+            TransferPacket::Reject { id } => {
+                let index = self
+                    .outgoing
+                    .iter()
+                    .position(|transfer| transfer.id == id && transfer.peer_id == from)?;
+                self.outgoing.remove(index);
+                Some(TransferEvent::Cancelled(TransferCancelled {
+                    id,
+                    peer_id: from,
+                }))
+            }
+            TransferPacket::Cancel { id } => {
+                if !self.incoming.contains_key(&(from, id)) {
+                    return None;
+                }
+                self.finish(from, id);
+                self.acks.remove(&(from, id));
+                Some(TransferEvent::Cancelled(TransferCancelled {
+                    id,
+                    peer_id: from,
+                }))
+            }
+        }
+    }
+
+    /// Removes an incoming transfer and remembers it as finished.
+    fn finish(&mut self, from: PeerId, id: TransferId) -> Option<IncomingTransfer> {
+        if self.finished.len() == FINISHED_WINDOW {
+            self.finished.pop_front();
+        }
+        self.finished.push_back((from, id));
+        self.incoming.remove(&(from, id))
+    }
+
+    fn complete_if_received(&mut self, from: PeerId, id: TransferId) -> Option<TransferEvent> {
+        let incoming = self.incoming.get(&(from, id))?;
+        if !incoming.received.iter().all(|&received| received) {
+            return None;
+        }
+        let incoming = self.finish(from, id)?;
+        if content_hash(&incoming.data) != incoming.hash {
+            warn!("transfer {id:?} from {from} doesn't match its hash");
+            return Some(TransferEvent::Corrupted(TransferCorrupted {
+                id,
+                sender: from,
+            }));
+        }
+        Some(TransferEvent::Completed(TransferCompleted {
+            id,
+            sender: from,
+            data: incoming.data.into(),
+        }))
+    }
+
+    /// Drops all transfers with peers that are no longer connected.
+    ///
+    /// Partial downloads are kept, so the transfer resumes when the sender reconnects and
+    /// sends the same content again.
+    pub(crate) fn retain_peers(
+        &mut self,
+        is_connected: impl Fn(&PeerId) -> bool,
+        mut on_event: impl FnMut(TransferEvent),
+    ) {
+        self.outgoing.retain(|transfer| {
+            if is_connected(&transfer.peer_id) {
+                return true;
+            }
+            on_event(TransferEvent::Cancelled(TransferCancelled {
+                id: transfer.id,
+                peer_id: transfer.peer_id,
+            }));
+            false
+        });
+        let disconnected: Vec<_> = self
+            .incoming
+            .keys()
+            .filter(|(peer_id, _)| !is_connected(peer_id))
+            .copied()
+            .collect();
+        for (peer_id, id) in disconnected {
+            let incoming = self.incoming.remove(&(peer_id, id)).unwrap();
+            self.acks.remove(&(peer_id, id));
+            if self.parked.len() == MAX_PARKED_TRANSFERS {
+                self.parked.remove(0);
+            }
+            let total = incoming.data.len() as u64;
+            self.parked.push(((incoming.hash, total), incoming));
+            on_event(TransferEvent::Cancelled(TransferCancelled { id, peer_id }));
+        }
+    }
+}
+
+fn write_event(
+    event: TransferEvent,
+    progress: &mut EventWriter<TransferProgress>,
+    completed: &mut EventWriter<TransferCompleted>,
+    corrupted: &mut EventWriter<TransferCorrupted>,
+    cancelled: &mut EventWriter<TransferCancelled>,
+) {
+    match event {
+        TransferEvent::Progress(event) => {
+            progress.write(event);
+        }
+        TransferEvent::Completed(event) => {
+            completed.write(event);
+        }
+        TransferEvent::Corrupted(event) => {
+            corrupted.write(event);
+        }
+        TransferEvent::Cancelled(event) => {
+            cancelled.write(event);
+        }
+        TransferEvent::Oversized => (),
+    }
+}
+
+#[cfg(feature = "server")]
+impl MatchboxHost {
+    /// Starts sending a blob to a client, or to all clients if `recipient` is `None`.
+    ///
+    /// The data is shared between the recipients. Arrives as [`TransferCompleted`].
+    ///
+    /// Chunks are acknowledged by the receiver. If the connection drops, sending the same data
+    /// again after the client rejoined automatically only sends the chunks that weren't
+    /// received yet, see [`MatchboxConfig::auto_rejoin_attempts`].
+    pub fn send_transfer(
+        &mut self,
+        recipient: Option<PeerId>,
+        data: impl Into<Bytes>,
+    ) -> TransferId {
+        let peers: Vec<_> = match recipient {
+            Some(recipient) => vec![recipient],
+            None => self.client_entities.keys().copied().collect(),
+        };
+        self.transfers.start(peers, data.into())
+    }
+
+    /// Stops an outgoing transfer for all its recipients.
+    pub fn cancel_transfer(&mut self, id: TransferId) {
+        let channel = self.layout.transfer_channel();
+        self.transfers.cancel(&mut self.socket, channel, id);
+    }
+}
+
+#[cfg(feature = "server")]
+pub(crate) fn update_host_transfers(
+    mut server: ResMut<MatchboxHost>,
+    mut progress: EventWriter<TransferProgress>,
+    mut completed: EventWriter<TransferCompleted>,
+    mut corrupted: EventWriter<TransferCorrupted>,
+    mut cancelled: EventWriter<TransferCancelled>,
+    mut stats: Query<&mut ConnectionStats>,
+) {
+    let channel = server.layout.transfer_channel();
+    let max_incoming = server.config().max_inbound_transfer_bytes;
+    let mut oversized = Vec::new();
+    let MatchboxHost {
+        socket,
+        client_entities,
+        transfers,
+        ..
+    } = &mut *server;
+    for (from, packet) in socket.channel_mut(channel).receive() {
+        if !client_entities.contains_key(&from) {
+            continue;
+        }
+        if let Some(event) = transfers.receive(from, &packet, max_incoming) {
+            if let TransferEvent::Oversized = event {
+                oversized.push(from);
+                continue;
+            }
+            write_event(
+                event,
+                &mut progress,
+                &mut completed,
+                &mut corrupted,
+                &mut cancelled,
+            );
+        }
+    }
+    transfers.retain_peers(
+        |peer_id| client_entities.contains_key(peer_id),
+        |event| {
+            write_event(
+                event,
+                &mut progress,
+                &mut completed,
+                &mut corrupted,
+                &mut cancelled,
+            )
+        },
+    );
+    transfers.update(socket, channel);
+
+    for peer_id in oversized {
+        let Some(&client_entity) = server.client_entities.get(&peer_id) else {
+            continue;
+        };
+        if let Ok(mut stats) = stats.get_mut(client_entity) {
+            protocol_violation(&mut server, peer_id, &mut stats, "oversized transfer");
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+impl MatchboxClient {
+    /// Starts sending a blob to the host. Arrives as [`TransferCompleted`].
+    ///
+    /// Like on the host, sending the same data again after a rejoin resumes an interrupted
+    /// transfer.
+    pub fn send_transfer(&mut self, data: impl Into<Bytes>) -> Option<TransferId> {
+        let host_peer_id = self.host_peer_id?;
+        Some(self.transfers.start([host_peer_id], data.into()))
+    }
+
+    /// Stops an outgoing transfer.
+    pub fn cancel_transfer(&mut self, id: TransferId) {
+        let channel = self.layout.transfer_channel();
+        self.transfers.cancel(&mut self.socket, channel, id);
+    }
+}
+
+#[cfg(feature = "client")]
+pub(crate) fn update_client_transfers(
+    mut client: ResMut<MatchboxClient>,
+    mut progress: EventWriter<TransferProgress>,
+    mut completed: EventWriter<TransferCompleted>,
+    mut corrupted: EventWriter<TransferCorrupted>,
+    mut cancelled: EventWriter<TransferCancelled>,
+) {
+    let channel = client.layout.transfer_channel();
+    let max_incoming = client.config().max_inbound_transfer_bytes;
+    let mut oversized = 0;
+    let MatchboxClient {
+        socket,
+        transfers,
+        host_peer_id,
+        ..
+    } = &mut *client;
+    // a lost host parks its downloads until it sends the same content again, e.g. after
+    // an automatic rejoin
+    transfers.retain_peers(
+        |peer_id| Some(*peer_id) == *host_peer_id,
+        |event| {
+            write_event(
+                event,
+                &mut progress,
+                &mut completed,
+                &mut corrupted,
+                &mut cancelled,
+            )
+        },
+    );
+    let Ok(socket_channel) = socket.get_channel_mut(channel) else {
+        return;
+    };
+    for (from, packet) in socket_channel.receive() {
+        // other clients of a full mesh could send transfers posing as the host
+        if Some(from) != *host_peer_id {
+            debug!("ignoring transfer packet from peer {from}, which isn't the host");
+            continue;
+        }
+        if let Some(event) = transfers.receive(from, &packet, max_incoming) {
+            if let TransferEvent::Oversized = event {
+                oversized += 1;
+                continue;
+            }
+            write_event(
+                event,
+                &mut progress,
+                &mut completed,
+                &mut corrupted,
+                &mut cancelled,
+            );
+        }
+    }
+    transfers.update(socket, channel);
+
+    for _ in 0..oversized {
+        client.protocol_violation("oversized transfer");
+    }
+}
+
+#[cfg(test)]
+fn chunk_packets(id: TransferId, data: &[u8]) -> Vec<Packet> {
+    data.chunks(CHUNK_SIZE)
+        .enumerate()
+        .map(|(index, chunk)| {
+            TransferPacket::Chunk {
+                id,
+                index: index as u32,
+                data: chunk,
+            }
+            .to_packet()
+        })
+        .collect()
+}
+
+#[test]
+fn test_transfer() {
+    let peer_id = PeerId(Default::default());
+    let data: Vec<u8> = (0..CHUNK_SIZE * 2 + 10).map(|i| i as u8).collect();
+    let mut receiver = Transfers::default();
+
+    let id = TransferId(0);
+    let start = TransferPacket::Start {
+        id,
+        total: data.len() as u64,
+        hash: content_hash(&data),
+    };
+    assert!(
+        receiver
+            .receive(peer_id, &start.to_packet(), u64::MAX)
+            .is_none()
+    );
+    assert_eq!(receiver.replies.len(), 1, "transfer is accepted");
+    for (index, packet) in chunk_packets(id, &data).iter().enumerate() {
+        let event = receiver.receive(peer_id, packet, u64::MAX).unwrap();
+        if index < 2 {
+            assert!(matches!(event, TransferEvent::Progress(_)));
+        } else {
+            let TransferEvent::Completed(completed) = event else {
+                panic!("transfer should be completed");
+            };
+            assert_eq!(completed.data, data);
+        }
+    }
+    assert_eq!(receiver.acks[&(peer_id, id)], [0, 1, 2]);
+    assert!(
+        receiver
+            .receive(peer_id, &start.to_packet(), u64::MAX)
+            .is_none(),
+        "replayed start is ignored"
+    );
+    assert!(
+        receiver
+            .receive(peer_id, &chunk_packets(id, &data)[0], u64::MAX)
+            .is_none()
+    );
+}
+
+#[test]
+fn test_transfer_resume() {
+    let peer_id = PeerId(Default::default());
+    let data: Vec<u8> = (0..CHUNK_SIZE * 3).map(|i| i as u8).collect();
+    let hash = content_hash(&data);
+    let mut receiver = Transfers::default();
+
+    let start = TransferPacket::Start {
+        id: TransferId(0),
+        total: data.len() as u64,
+        hash,
+    };
+    receiver.receive(peer_id, &start.to_packet(), u64::MAX);
+    let chunks = chunk_packets(TransferId(0), &data);
+    receiver.receive(peer_id, &chunks[0], u64::MAX);
+    receiver.receive(peer_id, &chunks[1], u64::MAX);
+    receiver.retain_peers(|_| false, |_| ());
+    assert!(receiver.incoming.is_empty());
+    receiver.replies.clear();
+
+    let id = TransferId(5);
+    let start = TransferPacket::Start {
+        id,
+        total: data.len() as u64,
+        hash,
+    };
+    receiver.receive(peer_id, &start.to_packet(), u64::MAX);
+    let (_, accept) = receiver.replies.pop().unwrap();
+    let accept: TransferPacket = from_packet(&accept).unwrap();
+    assert_eq!(
+        accept,
+        TransferPacket::Accept {
+            id,
+            received: vec![(0, 2)]
+        },
+        "received chunks are skipped"
+    );
+
+    let chunks = chunk_packets(id, &data);
+    let event = receiver.receive(peer_id, &chunks[2], u64::MAX).unwrap();
+    let TransferEvent::Completed(completed) = event else {
+        panic!("transfer should be completed");
+    };
+    assert_eq!(completed.data, data);
+}
+
+#[test]
+fn test_download_resume_after_rejoin() {
+    let host = PeerId(uuid::Uuid::from_u128(1));
+    let data: Vec<u8> = (0..CHUNK_SIZE * 2).map(|i| i as u8).collect();
+    let max_incoming = data.len() as u64;
+    let start = |id| {
+        TransferPacket::Start {
+            id: TransferId(id),
+            total: data.len() as u64,
+            hash: content_hash(&data),
+        }
+        .to_packet()
+    };
+    let mut client = Transfers::default();
+
+    client.receive(host, &start(0), max_incoming);
+    client.receive(host, &chunk_packets(TransferId(0), &data)[0], max_incoming);
+    // the host is lost, as the client only downloads from its host
+    let mut cancelled = 0;
+    client.retain_peers(|_| false, |_| cancelled += 1);
+    assert_eq!(cancelled, 1);
+
+    assert!(
+        client.receive(host, &start(1), max_incoming).is_none(),
+        "the parked download doesn't count toward the limit"
+    );
+    let event = client.receive(host, &chunk_packets(TransferId(1), &data)[1], max_incoming);
+    let Some(TransferEvent::Completed(completed)) = event else {
+        panic!("download should resume with the missing chunk");
+    };
+    assert_eq!(completed.data, data);
+}
+
+#[test]
+fn test_transfer_corrupted() {
+    let peer_id = PeerId(Default::default());
+    let data = vec![1; 10];
+    let mut receiver = Transfers::default();
+
+    let id = TransferId(0);
+    let start = TransferPacket::Start {
+        id,
+        total: data.len() as u64,
+        hash: content_hash(&[2; 10]),
+    };
+    receiver.receive(peer_id, &start.to_packet(), u64::MAX);
+    let event = receiver.receive(peer_id, &chunk_packets(id, &data)[0], u64::MAX);
+    assert!(matches!(event, Some(TransferEvent::Corrupted(_))));
+}
+
+#[test]
+fn test_transfer_limit() {
+    let peer_id = PeerId(Default::default());
+    let mut receiver = Transfers::default();
+    let start = |id, total| {
+        TransferPacket::Start {
+            id: TransferId(id),
+            total,
+            hash: Default::default(),
+        }
+        .to_packet()
+    };
+
+    assert!(receiver.receive(peer_id, &start(0, 60), 100).is_none());
+    assert!(matches!(
+        receiver.receive(peer_id, &start(1, 60), 100),
+        Some(TransferEvent::Oversized)
+    ));
+    assert!(
+        receiver.receive(peer_id, &start(2, 40), 100).is_none(),
+        "fits next to the first transfer"
+    );
+}
+
+#[test]
+fn test_duplicate_ack() {
+    let peer_id = PeerId(Default::default());
+    let mut sender = Transfers::default();
+    let id = sender.start([peer_id], vec![0; CHUNK_SIZE * 2].into());
+
+    let ack = TransferPacket::Ack {
+        id,
+        received: vec![(0, 1)],
+    }
+    .to_packet();
+    assert!(matches!(
+        sender.receive(peer_id, &ack, u64::MAX),
+        Some(TransferEvent::Progress(_))
+    ));
+    assert!(
+        sender.receive(peer_id, &ack, u64::MAX).is_none(),
+        "no progress for a duplicated ack"
+    );
+}
@@ -0,0 +1,546 @@
+use crate::delivery::{DeliveryId, read_user_packet};
+use crate::relay::RelayEnvelope;
+use crate::shared::*;
+use bevy::prelude::*;
+use bevy_matchbox::matchbox_socket::{Packet, PeerId, WebRtcChannel};
+use bevy_replicon::prelude::Channel;
+use bytes::Bytes;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+#[cfg(feature = "client")]
+use crate::client::MatchboxClient;
+#[cfg(feature = "server")]
+use crate::server::MatchboxHost;
+
+/// Message received on one of the [`MatchboxConfig::user_channels`].
+///
+/// On the host it comes from a client, on a client from the host or, on channels with a
+/// [`Fanout`], from another client. User channels bypass replicon and work as soon as a
+/// client is connected, before replicon authorized it.
+#[derive(Event, Debug, Clone)]
+pub struct UserMessage {
+    /// Index of the channel in [`MatchboxConfig::user_channels`].
+    pub channel: usize,
+    pub sender: PeerId,
+    pub payload: Bytes,
+}
+
+/// Received messages a [`UserChannelHandle`] keeps until they are taken, older ones are
+/// dropped.
+const HANDLE_BACKLOG: usize = 1024;
+
+/// Cloneable access to a single user channel for other plugins, e.g. voice or telemetry
+/// crates that integrate without owning the socket.
+///
+/// Obtained from `MatchboxHost::user_channel_handle` or
+/// `MatchboxClient::user_channel_handle`. Usable from any thread: sent messages are queued
+/// until the next update of the host or client, and received messages are kept until
+/// [`Self::receive`] takes them, in addition to the [`UserMessage`] events.
+#[derive(Clone)]
+pub struct UserChannelHandle {
+    channel: usize,
+    state: Arc<Mutex<HandleState>>,
+}
+
+#[derive(Default)]
+struct HandleState {
+    peers: Vec<PeerId>,
+    incoming: VecDeque<(PeerId, Bytes)>,
+    /// Payloads with their recipient, `None` for all peers.
+    outgoing: Vec<(Option<PeerId>, Bytes)>,
+}
+
+impl UserChannelHandle {
+    /// Index of the channel in [`MatchboxConfig::user_channels`].
+    pub fn channel(&self) -> usize {
+        self.channel
+    }
+
+    /// Peers reachable on the channel as of the last update: the clients on the host, the
+    /// host on a client.
+    pub fn peers(&self) -> Vec<PeerId> {
+        self.state.lock().unwrap().peers.clone()
+    }
+
+    pub fn send(&self, peer_id: PeerId, payload: impl Into<Bytes>) {
+        let mut state = self.state.lock().unwrap();
+        state.outgoing.push((Some(peer_id), payload.into()));
+    }
+
+    /// Sends `payload` to all [`Self::peers`], fanned out on clients if the channel has a
+    /// [`Fanout`].
+    pub fn broadcast(&self, payload: impl Into<Bytes>) {
+        let mut state = self.state.lock().unwrap();
+        state.outgoing.push((None, payload.into()));
+    }
+
+    /// Takes the messages received since the last call with their senders.
+    pub fn receive(&self) -> Vec<(PeerId, Bytes)> {
+        self.state.lock().unwrap().incoming.drain(..).collect()
+    }
+}
+
+/// Handles given out by a host or client, keyed by user channel.
+#[derive(Default)]
+pub(crate) struct UserChannelHandles(HashMap<usize, UserChannelHandle>);
+
+impl UserChannelHandles {
+    fn get_or_create(&mut self, channel: usize) -> UserChannelHandle {
+        self.0
+            .entry(channel)
+            .or_insert_with(|| UserChannelHandle {
+                channel,
+                state: Default::default(),
+            })
+            .clone()
+    }
+
+    fn deliver(&self, channel: usize, sender: PeerId, payload: &Bytes) {
+        let Some(handle) = self.0.get(&channel) else {
+            return;
+        };
+        let mut state = handle.state.lock().unwrap();
+        if state.incoming.len() >= HANDLE_BACKLOG {
+            state.incoming.pop_front();
+        }
+        state.incoming.push_back((sender, payload.clone()));
+    }
+
+    /// Publishes `peers` to all handles and takes their queued messages.
+    fn exchange(&self, peers: &[PeerId]) -> Vec<(usize, Option<PeerId>, Bytes)> {
+        let mut outgoing = Vec::new();
+        for (&channel, handle) in &self.0 {
+            let mut state = handle.state.lock().unwrap();
+            state.peers.clear();
+            state.peers.extend_from_slice(peers);
+            outgoing.extend(
+                state
+                    .outgoing
+                    .drain(..)
+                    .map(|(peer_id, payload)| (channel, peer_id, payload)),
+            );
+        }
+        outgoing
+    }
+}
+
+/// How messages of clients on a user channel reach the other clients, see
+/// [`MatchboxConfig::fanout_channels`].
+///
+/// For client-authoritative data like cosmetic animation states, which doesn't need the
+/// host's game logic. Messages still arrive on the host as well.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fanout {
+    /// The host forwards everything clients send on the channel to all other clients,
+    /// naming the original sender.
+    Relayed,
+    /// Clients send to every peer they are connected to, skipping the extra hop through the
+    /// host.
+    ///
+    /// Clients only have connections to each other when the signaling server uses the full
+    /// mesh topology, e.g. `SignalingServer::full_mesh_builder`. Otherwise only the host
+    /// receives the messages.
+    Direct,
+}
+
+#[cfg(feature = "server")]
+impl MatchboxHost {
+    /// Sends `payload` to a client on a user channel.
+    ///
+    /// Ignored if `channel` isn't configured in [`MatchboxConfig::user_channels`] or the
+    /// client isn't connected.
+    pub fn send_to(&mut self, peer_id: PeerId, channel: usize, payload: impl Into<Bytes>) {
+        if !self.client_entities.contains_key(&peer_id) {
+            debug!("ignoring user message to unknown client {peer_id}");
+            return;
+        }
+        let Some((socket_channel, packet)) = self.user_packet(channel, &payload.into()) else {
+            return;
+        };
+        let Ok(socket_channel) = self.socket.get_channel_mut(socket_channel) else {
+            warn!("user channel {channel} was taken");
+            return;
+        };
+        socket_channel.send(packet, peer_id);
+    }
+
+    /// Sends `payload` to a client on a reliable user channel and triggers
+    /// [`MessageDelivered`](crate::MessageDelivered) once the client received it, e.g. to
+    /// kick a player only after a final message arrived.
+    ///
+    /// Returns `None` without sending if `channel` isn't configured, is unreliable or has
+    /// [`Fanout::Relayed`], or the client isn't connected.
+    pub fn send_to_acked(
+        &mut self,
+        peer_id: PeerId,
+        channel: usize,
+        payload: impl Into<Bytes>,
+    ) -> Option<DeliveryId> {
+        if !self.client_entities.contains_key(&peer_id) {
+            debug!("ignoring user message to unknown client {peer_id}");
+            return None;
+        }
+        let socket_channel = acked_channel(&self.layout, self.config(), channel)?;
+        if self.config().fanout_channels.get(&channel) == Some(&Fanout::Relayed) {
+            warn!("user channel {channel} is relayed, its messages can't be acknowledged");
+            return None;
+        }
+        let Ok(socket_channel) = self.socket.get_channel_mut(socket_channel) else {
+            warn!("user channel {channel} was taken");
+            return None;
+        };
+        let (id, packet) = self.deliveries.packet(peer_id, &payload.into());
+        socket_channel.send(packet, peer_id);
+        Some(id)
+    }
+
+    /// Messages sent with [`Self::send_to_acked`] that weren't acknowledged yet.
+    pub fn pending_deliveries(&self) -> usize {
+        self.deliveries.len()
+    }
+
+    /// Sends `payload` to all connected clients on a user channel, e.g. for announcements
+    /// or a voice mix.
+    ///
+    /// The packet is built once and shared by all recipients. Clients are included as soon
+    /// as they are connected, before replicon authorized them. Ignored if `channel` isn't
+    /// configured in [`MatchboxConfig::user_channels`].
+    pub fn broadcast(&mut self, channel: usize, payload: impl Into<Bytes>) {
+        let Some((socket_channel, packet)) = self.user_packet(channel, &payload.into()) else {
+            return;
+        };
+        let Ok(socket_channel) = self.socket.get_channel_mut(socket_channel) else {
+            warn!("user channel {channel} was taken");
+            return;
+        };
+        for &peer_id in self.client_entities.keys() {
+            socket_channel.send(packet.clone(), peer_id);
+        }
+    }
+
+    /// Socket channel and packet for `payload`, wrapped to name the host as sender on
+    /// [`Fanout::Relayed`] channels, where clients receive forwarded messages as well.
+    fn user_packet(&mut self, channel: usize, payload: &[u8]) -> Option<(usize, Packet)> {
+        let Some(socket_channel) = self.layout.user_channel(channel) else {
+            warn!("user channel {channel} isn't configured");
+            return None;
+        };
+        let packet = if self.config().fanout_channels.get(&channel) == Some(&Fanout::Relayed) {
+            let peer_id = self.socket.id()?;
+            RelayEnvelope { peer_id, payload }.to_packet()
+        } else {
+            add_marker(payload)
+        };
+        Some((socket_channel, packet))
+    }
+
+    /// Takes a user channel out of the socket to drive it elsewhere, e.g. as the
+    /// `NonBlockingSocket` of a GGRS session next to replicon.
+    ///
+    /// The channel then no longer produces [`UserMessage`]s and sending on it is ignored.
+    /// `None` if `channel` isn't configured in [`MatchboxConfig::user_channels`] or was
+    /// already taken.
+    pub fn take_user_channel(&mut self, channel: usize) -> Option<WebRtcChannel> {
+        let socket_channel = self.layout.user_channel(channel)?;
+        self.socket.take_channel(socket_channel).ok()
+    }
+}
+
+#[cfg(feature = "server")]
+impl MatchboxHost {
+    /// Handle to a user channel for other plugins, see [`UserChannelHandle`].
+    ///
+    /// All handles of a channel share their state. `None` if `channel` isn't configured in
+    /// [`MatchboxConfig::user_channels`].
+    pub fn user_channel_handle(&mut self, channel: usize) -> Option<UserChannelHandle> {
+        self.layout.user_channel(channel)?;
+        Some(self.user_handles.get_or_create(channel))
+    }
+}
+
+#[cfg(feature = "server")]
+pub(crate) fn update_user_handles_on_host(mut server: ResMut<MatchboxHost>) {
+    let peers: Vec<_> = server.client_entities.keys().copied().collect();
+    for (channel, peer_id, payload) in server.user_handles.exchange(&peers) {
+        match peer_id {
+            Some(peer_id) => server.send_to(peer_id, channel, payload),
+            None => server.broadcast(channel, payload),
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+pub(crate) fn receive_user_on_host(
+    mut server: ResMut<MatchboxHost>,
+    mut messages: EventWriter<UserMessage>,
+) {
+    for channel in 0..server.layout.user_channel_count() {
+        let Some(socket_channel) = server.layout.user_channel(channel) else {
+            continue;
+        };
+        let Ok(user_channel) = server.socket.get_channel_mut(socket_channel) else {
+            continue;
+        };
+        let packets = user_channel.receive();
+        let fanout = server.config().fanout_channels.get(&channel).copied();
+        for (sender, packet) in packets {
+            if !server.client_entities.contains_key(&sender) {
+                continue;
+            }
+            if packet.len() > server.config().max_inbound_message_size {
+                debug!("dropping oversized user message from {sender}");
+                continue;
+            }
+            let (payload, delivery) = read_user_packet(&packet);
+            if let Some(id) = delivery {
+                let packet = system_packet(&SystemChannelMessage::Delivered { id });
+                server
+                    .socket
+                    .channel_mut(SYSTEM_CHANNEL_ID)
+                    .send(packet, sender);
+            }
+            if fanout == Some(Fanout::Relayed) {
+                let forwarded = RelayEnvelope {
+                    peer_id: sender,
+                    payload: &payload,
+                }
+                .to_packet();
+                let recipients: Vec<_> = server
+                    .client_entities
+                    .keys()
+                    .copied()
+                    .filter(|&peer_id| peer_id != sender)
+                    .collect();
+                let socket_channel = server.socket.channel_mut(socket_channel);
+                for peer_id in recipients {
+                    socket_channel.send(forwarded.clone(), peer_id);
+                }
+            }
+            server.user_handles.deliver(channel, sender, &payload);
+            messages.write(UserMessage {
+                channel,
+                sender,
+                payload,
+            });
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+impl MatchboxClient {
+    /// Sends `payload` to the host on a user channel.
+    ///
+    /// Ignored if `channel` isn't configured in [`MatchboxConfig::user_channels`] or the
+    /// client isn't connected.
+    pub fn send_to_host(&mut self, channel: usize, payload: impl Into<Bytes>) {
+        let Some(socket_channel) = self.layout.user_channel(channel) else {
+            warn!("user channel {channel} isn't configured");
+            return;
+        };
+        let Some(host_peer_id) = self.host_peer_id else {
+            warn!("can't send user messages before connecting to the host");
+            return;
+        };
+        let Ok(socket_channel) = self.socket.get_channel_mut(socket_channel) else {
+            warn!("user channel {channel} was taken");
+            return;
+        };
+        socket_channel.send(add_marker(&payload.into()), host_peer_id);
+    }
+
+    /// Sends `payload` to the host on a reliable user channel and triggers
+    /// [`MessageDelivered`](crate::MessageDelivered) once the host received it.
+    ///
+    /// Returns `None` without sending if `channel` isn't configured or is unreliable, or the
+    /// client isn't connected.
+    pub fn send_to_host_acked(
+        &mut self,
+        channel: usize,
+        payload: impl Into<Bytes>,
+    ) -> Option<DeliveryId> {
+        let socket_channel = acked_channel(&self.layout, self.config(), channel)?;
+        let Some(host_peer_id) = self.host_peer_id else {
+            warn!("can't send user messages before connecting to the host");
+            return None;
+        };
+        let Ok(socket_channel) = self.socket.get_channel_mut(socket_channel) else {
+            warn!("user channel {channel} was taken");
+            return None;
+        };
+        let (id, packet) = self.deliveries.packet(host_peer_id, &payload.into());
+        socket_channel.send(packet, host_peer_id);
+        Some(id)
+    }
+
+    /// Messages sent with [`Self::send_to_host_acked`] that weren't acknowledged yet.
+    ///
+    /// Automatic rejoins and failovers drop them, as messages in flight are lost.
+    pub fn pending_deliveries(&self) -> usize {
+        self.deliveries.len()
+    }
+
+    /// Sends `payload` to the host and all other clients on a user channel with a
+    /// [`Fanout`].
+    ///
+    /// Ignored if the channel isn't listed in [`MatchboxConfig::fanout_channels`] or the
+    /// client isn't connected.
+    pub fn broadcast(&mut self, channel: usize, payload: impl Into<Bytes>) {
+        let Some(socket_channel) = self.layout.user_channel(channel) else {
+            warn!("user channel {channel} isn't configured");
+            return;
+        };
+        let Some(&fanout) = self.config().fanout_channels.get(&channel) else {
+            warn!("user channel {channel} has no fanout");
+            return;
+        };
+        let Some(host_peer_id) = self.host_peer_id else {
+            warn!("can't send user messages before connecting to the host");
+            return;
+        };
+        let mut recipients = vec![host_peer_id];
+        if fanout == Fanout::Direct {
+            recipients.extend(self.direct_peers());
+        }
+        let packet = add_marker(&payload.into());
+        let Ok(socket_channel) = self.socket.get_channel_mut(socket_channel) else {
+            warn!("user channel {channel} was taken");
+            return;
+        };
+        for peer_id in recipients {
+            socket_channel.send(packet.clone(), peer_id);
+        }
+    }
+
+    /// Takes a user channel out of the socket, like `MatchboxHost::take_user_channel`.
+    ///
+    /// Automatic rejoins replace the socket, so take the channel again after
+    /// [`ClientConnectedToHost`](crate::ClientConnectedToHost).
+    pub fn take_user_channel(&mut self, channel: usize) -> Option<WebRtcChannel> {
+        let socket_channel = self.layout.user_channel(channel)?;
+        self.socket.take_channel(socket_channel).ok()
+    }
+}
+
+#[cfg(feature = "client")]
+impl MatchboxClient {
+    /// Handle to a user channel for other plugins, like
+    /// `MatchboxHost::user_channel_handle`.
+    ///
+    /// Messages sent through it only reach the host, or other clients when broadcast on a
+    /// channel with a [`Fanout`].
+    pub fn user_channel_handle(&mut self, channel: usize) -> Option<UserChannelHandle> {
+        self.layout.user_channel(channel)?;
+        Some(self.user_handles.get_or_create(channel))
+    }
+}
+
+#[cfg(feature = "client")]
+pub(crate) fn update_user_handles_on_client(mut client: ResMut<MatchboxClient>) {
+    let peers: Vec<_> = client.host_peer_id.into_iter().collect();
+    for (channel, peer_id, payload) in client.user_handles.exchange(&peers) {
+        match peer_id {
+            Some(peer_id) if Some(peer_id) != client.host_peer_id => {
+                debug!("ignoring user message to {peer_id}, clients only reach the host");
+            }
+            Some(_) => client.send_to_host(channel, payload),
+            None if client.config().fanout_channels.contains_key(&channel) => {
+                client.broadcast(channel, payload)
+            }
+            None => client.send_to_host(channel, payload),
+        }
+    }
+}
+
+#[cfg(feature = "client")]
+pub(crate) fn receive_user_on_client(
+    mut client: ResMut<MatchboxClient>,
+    mut messages: EventWriter<UserMessage>,
+) {
+    for channel in 0..client.layout.user_channel_count() {
+        let Some(socket_channel) = client.layout.user_channel(channel) else {
+            continue;
+        };
+        let Ok(socket_channel) = client.socket.get_channel_mut(socket_channel) else {
+            continue;
+        };
+        let packets = socket_channel.receive();
+        let fanout = client.config().fanout_channels.get(&channel).copied();
+        for (sender, packet) in packets {
+            let from_host = Some(sender) == client.host_peer_id;
+            if !from_host && fanout != Some(Fanout::Direct) {
+                continue;
+            }
+            if packet.len() > client.config().max_inbound_message_size {
+                debug!("dropping oversized user message from {sender}");
+                continue;
+            }
+            let message = if from_host && fanout == Some(Fanout::Relayed) {
+                let Ok(envelope) = from_packet::<RelayEnvelope>(&packet) else {
+                    client.protocol_violation("malformed forwarded user message");
+                    continue;
+                };
+                UserMessage {
+                    channel,
+                    sender: envelope.peer_id,
+                    payload: Bytes::copy_from_slice(envelope.payload),
+                }
+            } else {
+                let (payload, delivery) = read_user_packet(&packet);
+                if let Some(id) = delivery {
+                    let packet = system_packet(&SystemChannelMessage::Delivered { id });
+                    client
+                        .socket
+                        .channel_mut(SYSTEM_CHANNEL_ID)
+                        .send(packet, sender);
+                }
+                UserMessage {
+                    channel,
+                    sender,
+                    payload,
+                }
+            };
+            client
+                .user_handles
+                .deliver(channel, message.sender, &message.payload);
+            messages.write(message);
+        }
+    }
+}
+
+/// Socket channel of a user channel whose messages can be acknowledged, which requires a
+/// reliable channel.
+fn acked_channel(layout: &ChannelLayout, config: &MatchboxConfig, channel: usize) -> Option<usize> {
+    let Some(socket_channel) = layout.user_channel(channel) else {
+        warn!("user channel {channel} isn't configured");
+        return None;
+    };
+    if matches!(config.user_channels[channel], Channel::Unreliable) {
+        warn!("user channel {channel} is unreliable, its messages can't be acknowledged");
+        return None;
+    }
+    Some(socket_channel)
+}
+
+#[test]
+fn test_user_channel_handles() {
+    let mut handles = UserChannelHandles::default();
+    let handle = handles.get_or_create(1);
+    let other = handles.get_or_create(1);
+    let peer_id = PeerId(Default::default());
+
+    handle.send(peer_id, "to peer");
+    other.broadcast("to all");
+    let outgoing = handles.exchange(&[peer_id]);
+    assert_eq!(outgoing.len(), 2);
+    assert_eq!(outgoing[0], (1, Some(peer_id), Bytes::from("to peer")));
+    assert_eq!(outgoing[1], (1, None, Bytes::from("to all")));
+    assert_eq!(other.peers(), [peer_id]);
+
+    for _ in 0..=HANDLE_BACKLOG {
+        handles.deliver(1, peer_id, &Bytes::from("message"));
+    }
+    handles.deliver(0, peer_id, &Bytes::from("no handle"));
+    assert_eq!(handle.receive().len(), HANDLE_BACKLOG);
+    assert!(other.receive().is_empty(), "messages are taken once");
+}
@@ -0,0 +1,334 @@
+use crate::shared::*;
+use bevy::prelude::*;
+use bevy_matchbox::matchbox_socket::{Packet, PeerId};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::time::Duration;
+
+#[cfg(feature = "client")]
+use crate::client::MatchboxClient;
+#[cfg(feature = "server")]
+use crate::server::{MatchboxHost, client_violation};
+#[cfg(feature = "server")]
+use crate::stats::ConnectionStats;
+
+/// Upper bound of the serialized header.
+const HEADER_MAX_SIZE: usize = 32;
+
+/// Framing in front of each voice payload.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VoiceFrameHeader {
+    /// Peer that recorded the frame, filled in by the host.
+    pub sender: PeerId,
+    /// Increases by one per frame of the sender.
+    pub sequence: u32,
+    /// Capture time chosen by the sender, e.g. in samples.
+    pub timestamp: u32,
+}
+
+/// Voice frame received from another peer.
+///
+/// Frames travel on an unreliable, unordered channel, so they can arrive out of order or not
+/// at all. Feed them into a [`JitterBuffer`] per sender before decoding.
+#[derive(Event, Debug, Clone)]
+pub struct VoiceFrame {
+    pub header: VoiceFrameHeader,
+    /// Encoded audio, e.g. an Opus frame.
+    pub payload: Bytes,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct VoicePacket<'a> {
+    pub(crate) header: VoiceFrameHeader,
+    #[serde(borrow)]
+    payload: &'a [u8],
+}
+
+impl VoicePacket<'_> {
+    pub(crate) fn to_packet(&self) -> Packet {
+        to_vec_packet(self, self.payload.len() + HEADER_MAX_SIZE)
+    }
+}
+
+/// Voice state of a host or client.
+#[derive(Default)]
+pub(crate) struct VoiceState {
+    sequence: u32,
+    muted: HashSet<PeerId>,
+}
+
+impl VoiceState {
+    fn next_sequence(&mut self) -> u32 {
+        let sequence = self.sequence;
+        self.sequence = self.sequence.wrapping_add(1);
+        sequence
+    }
+}
+
+/// Reorders the voice frames of a single sender.
+///
+/// Frames are held until [`Self::depth`] of them are buffered, or until one of them waited
+/// for [`Self::max_delay`], and are then released in sequence order. The delay also drains
+/// the buffer when the sender stops talking. Frames arriving after a later frame was
+/// released are dropped. Sequence numbers are compared wrapping, so streams can outlast
+/// [`u32::MAX`] frames.
+///
+/// Times are up to the caller, e.g. [`Time<Real>`] elapsed time.
+pub struct JitterBuffer {
+    /// Buffered frames in sequence order, with the time they arrived at.
+    frames: VecDeque<(VoiceFrame, Duration)>,
+    next_sequence: Option<u32>,
+    depth: usize,
+    max_delay: Duration,
+}
+
+impl JitterBuffer {
+    pub fn new(depth: usize, max_delay: Duration) -> Self {
+        Self {
+            frames: VecDeque::new(),
+            next_sequence: None,
+            depth,
+            max_delay,
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn max_delay(&self) -> Duration {
+        self.max_delay
+    }
+
+    pub fn push(&mut self, frame: VoiceFrame, now: Duration) {
+        let sequence = frame.header.sequence;
+        if self
+            .next_sequence
+            .is_some_and(|next| sequence_before(sequence, next))
+        {
+            return;
+        }
+        let index = self
+            .frames
+            .partition_point(|(buffered, _)| sequence_before(buffered.header.sequence, sequence));
+        if self
+            .frames
+            .get(index)
+            .is_some_and(|(buffered, _)| buffered.header.sequence == sequence)
+        {
+            return;
+        }
+        self.frames.insert(index, (frame, now));
+    }
+
+    /// Returns the next frame in sequence order once enough frames are buffered or one of
+    /// them waited long enough.
+    ///
+    /// Missing frames are skipped, decoders usually conceal them.
+    pub fn pop(&mut self, now: Duration) -> Option<VoiceFrame> {
+        let arrived_at = self
+            .frames
+            .iter()
+            .map(|&(_, arrived_at)| arrived_at)
+            .min()?;
+        if self.frames.len() < self.depth && now.saturating_sub(arrived_at) < self.max_delay {
+            return None;
+        }
+        let (frame, _) = self.frames.pop_front()?;
+        self.next_sequence = Some(frame.header.sequence.wrapping_add(1));
+        Some(frame)
+    }
+}
+
+/// Returns `true` if sequence number `a` comes before `b`, treating them as wrapping.
+fn sequence_before(a: u32, b: u32) -> bool {
+    (b.wrapping_sub(a) as i32) > 0
+}
+
+#[cfg(feature = "server")]
+impl MatchboxHost {
+    /// Sends a voice frame of the host player to all clients.
+    pub fn send_voice(&mut self, timestamp: u32, payload: &[u8]) {
+        let Some(sender) = self.socket.id() else {
+            return;
+        };
+        let packet = VoicePacket {
+            header: VoiceFrameHeader {
+                sender,
+                sequence: self.voice.next_sequence(),
+                timestamp,
+            },
+            payload,
+        }
+        .to_packet();
+        let voice_channel = self.layout.voice_channel();
+        let peers: Vec<_> = self.client_entities.keys().copied().collect();
+        for peer_id in peers {
+            self.socket
+                .channel_mut(voice_channel)
+                .send(packet.clone(), peer_id);
+        }
+    }
+
+    /// Stops forwarding voice frames from a client to the others.
+    pub fn mute_voice(&mut self, peer_id: PeerId) {
+        self.voice.muted.insert(peer_id);
+    }
+
+    pub fn unmute_voice(&mut self, peer_id: PeerId) {
+        self.voice.muted.remove(&peer_id);
+    }
+}
+
+/// Forwards voice frames between clients and emits them for the host player.
+#[cfg(feature = "server")]
+pub(crate) fn forward_voice(
+    mut server: ResMut<MatchboxHost>,
+    mut stats: Query<&mut ConnectionStats>,
+    mut voice_frames: EventWriter<VoiceFrame>,
+) {
+    let voice_channel = server.layout.voice_channel();
+    for (from, packet) in server.socket.channel_mut(voice_channel).receive() {
+        if !server.client_entities.contains_key(&from) || server.voice.muted.contains(&from) {
+            continue;
+        }
+        if packet.len() > server.config().max_inbound_message_size {
+            client_violation(&mut server, &mut stats, from, "oversized voice frame");
+            continue;
+        }
+        let Ok(mut voice_packet) = from_packet::<VoicePacket>(&packet) else {
+            client_violation(&mut server, &mut stats, from, "malformed voice frame");
+            continue;
+        };
+        voice_packet.header.sender = from;
+        let packet = voice_packet.to_packet();
+        let peers: Vec<_> = server.client_entities.keys().copied().collect();
+        for peer_id in peers.into_iter().filter(|&peer_id| peer_id != from) {
+            server.forward(peer_id, voice_channel, packet.clone());
+        }
+        voice_frames.write(VoiceFrame {
+            header: voice_packet.header,
+            payload: Bytes::copy_from_slice(voice_packet.payload),
+        });
+    }
+}
+
+#[cfg(feature = "client")]
+impl MatchboxClient {
+    /// Sends a voice frame to the host, which forwards it to the other clients.
+    pub fn send_voice(&mut self, timestamp: u32, payload: &[u8]) {
+        let Some(host_peer_id) = self.host_peer_id else {
+            return;
+        };
+        let packet = VoicePacket {
+            header: VoiceFrameHeader {
+                // replaced by the host
+                sender: host_peer_id,
+                sequence: self.voice.next_sequence(),
+                timestamp,
+            },
+            payload,
+        }
+        .to_packet();
+        let voice_channel = self.layout.voice_channel();
+        self.socket
+            .channel_mut(voice_channel)
+            .send(packet, host_peer_id);
+    }
+
+    /// Drops received voice frames of a peer.
+    pub fn mute_voice(&mut self, peer_id: PeerId) {
+        self.voice.muted.insert(peer_id);
+    }
+
+    pub fn unmute_voice(&mut self, peer_id: PeerId) {
+        self.voice.muted.remove(&peer_id);
+    }
+}
+
+#[cfg(feature = "client")]
+pub(crate) fn receive_voice(
+    mut client: ResMut<MatchboxClient>,
+    mut voice_frames: EventWriter<VoiceFrame>,
+) {
+    let voice_channel = client.layout.voice_channel();
+    let Ok(channel) = client.socket.get_channel_mut(voice_channel) else {
+        return;
+    };
+    for (from, packet) in channel.receive() {
+        if !client.is_host(from) {
+            debug!("ignoring voice frame from peer {from}, which isn't the host");
+            continue;
+        }
+        let Ok(voice_packet) = from_packet::<VoicePacket>(&packet) else {
+            error!("failed to deserialize voice frame {}", packet.len());
+            continue;
+        };
+        if client.voice.muted.contains(&voice_packet.header.sender) {
+            continue;
+        }
+        voice_frames.write(VoiceFrame {
+            header: voice_packet.header,
+            payload: Bytes::copy_from_slice(voice_packet.payload),
+        });
+    }
+}
+
+#[test]
+fn test_jitter_buffer() {
+    let frame = |sequence| VoiceFrame {
+        header: VoiceFrameHeader {
+            sender: PeerId(Default::default()),
+            sequence,
+            timestamp: 0,
+        },
+        payload: Bytes::new(),
+    };
+    let now = Duration::ZERO;
+    let mut buffer = JitterBuffer::new(2, Duration::from_secs(1));
+    buffer.push(frame(1), now);
+    assert!(buffer.pop(now).is_none(), "not enough frames buffered");
+
+    buffer.push(frame(0), now);
+    assert_eq!(buffer.pop(now).unwrap().header.sequence, 0);
+    buffer.push(frame(3), now);
+    assert_eq!(buffer.pop(now).unwrap().header.sequence, 1);
+
+    buffer.push(frame(0), now);
+    assert!(buffer.pop(now).is_none(), "late frame is dropped");
+}
+
+#[test]
+fn test_jitter_buffer_short_spurt() {
+    let frame = |sequence| VoiceFrame {
+        header: VoiceFrameHeader {
+            sender: PeerId(Default::default()),
+            sequence,
+            timestamp: 0,
+        },
+        payload: Bytes::new(),
+    };
+    let max_delay = Duration::from_millis(100);
+    let mut buffer = JitterBuffer::new(4, max_delay);
+    buffer.push(frame(u32::MAX), Duration::ZERO);
+    buffer.push(frame(0), Duration::ZERO);
+    assert!(
+        buffer.pop(max_delay / 2).is_none(),
+        "frames wait for the depth"
+    );
+
+    assert_eq!(buffer.pop(max_delay).unwrap().header.sequence, u32::MAX);
+    assert_eq!(
+        buffer.pop(max_delay).unwrap().header.sequence,
+        0,
+        "sequence numbers wrap"
+    );
+    assert!(buffer.pop(max_delay).is_none());
+
+    buffer.push(frame(u32::MAX - 1), max_delay);
+    assert!(
+        buffer.pop(max_delay * 2).is_none(),
+        "late frame before the wrap is dropped"
+    );
+}
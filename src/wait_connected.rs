@@ -0,0 +1,82 @@
+use crate::shared::DisconnectKind;
+use bevy_matchbox::prelude::PeerId;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+#[derive(Default)]
+struct ConnectState {
+    outcome: Option<Result<PeerId, DisconnectKind>>,
+    wakers: Vec<Waker>,
+}
+
+/// Outcome of a client's connection attempt, shared with its [`WaitConnected`] handles.
+#[derive(Default)]
+pub(crate) struct ConnectSignal(Arc<Mutex<ConnectState>>);
+
+impl ConnectSignal {
+    /// Resolves the handles, returns `false` if they were already resolved, since only the
+    /// first outcome counts.
+    pub(crate) fn resolve(&self, outcome: Result<PeerId, DisconnectKind>) -> bool {
+        let mut state = self.0.lock().unwrap();
+        if state.outcome.is_some() {
+            return false;
+        }
+        state.outcome = Some(outcome);
+        state.wakers.drain(..).for_each(Waker::wake);
+        true
+    }
+
+    pub(crate) fn handle(&self) -> WaitConnected {
+        WaitConnected(self.0.clone())
+    }
+}
+
+/// Handle resolving once a client connected to the host, returned by
+/// `MatchboxClient::wait_connected`.
+///
+/// For code outside of systems, like a matchmaking state machine in an async task: await it
+/// or check [`Self::outcome`]. Resolves to the host's peer ID, or to the reason the client
+/// disconnected first. Stays resolved through automatic rejoins.
+#[derive(Clone)]
+pub struct WaitConnected(Arc<Mutex<ConnectState>>);
+
+impl WaitConnected {
+    /// The outcome without waiting, `None` while the client is still connecting.
+    pub fn outcome(&self) -> Option<Result<PeerId, DisconnectKind>> {
+        self.0.lock().unwrap().outcome.clone()
+    }
+}
+
+impl Future for WaitConnected {
+    type Output = Result<PeerId, DisconnectKind>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.0.lock().unwrap();
+        match &state.outcome {
+            Some(outcome) => Poll::Ready(outcome.clone()),
+            None => {
+                if !state.wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                    state.wakers.push(cx.waker().clone());
+                }
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[test]
+fn test_wait_connected() {
+    use bevy::tasks::block_on;
+
+    let signal = ConnectSignal::default();
+    let handle = signal.handle();
+    assert_eq!(handle.outcome(), None);
+
+    let host = PeerId(Default::default());
+    assert!(signal.resolve(Ok(host)));
+    assert!(!signal.resolve(Err(DisconnectKind::LocalClosed)));
+    assert_eq!(block_on(handle.clone()), Ok(host), "first outcome counts");
+    assert_eq!(signal.handle().outcome(), Some(Ok(host)));
+}
@@ -0,0 +1,112 @@
+#[cfg(feature = "server")]
+use bevy::prelude::*;
+use std::time::Duration;
+
+/// Timeouts of the host's watchdog for clients whose connection looks open but that stopped
+/// answering pings, e.g. because their process hangs or the route silently died.
+///
+/// WebRTC only reports a connection as failed once ICE consent checks fail, which can take
+/// much longer and never happens when just the game stopped responding.
+#[derive(Clone, Debug)]
+pub struct UnresponsiveTimeout {
+    /// Time without a pong after which `PeerUnresponsive` is sent.
+    pub after: Duration,
+    /// Further time without a pong before the client is disconnected with
+    /// `DisconnectKind::Timeout`.
+    pub grace_period: Duration,
+}
+
+impl Default for UnresponsiveTimeout {
+    fn default() -> Self {
+        Self {
+            after: Duration::from_secs(5),
+            grace_period: Duration::from_secs(5),
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum WatchdogAlarm {
+    /// The client stopped answering pings.
+    Unresponsive,
+    /// The client stayed unresponsive through the grace period.
+    Expired,
+}
+
+/// Pongs received from a client.
+#[cfg(feature = "server")]
+#[derive(Component)]
+pub(crate) struct Watchdog {
+    last_pong: Duration,
+    unresponsive_since: Option<Duration>,
+    expired: bool,
+}
+
+#[cfg(feature = "server")]
+impl Watchdog {
+    pub(crate) fn new(now: Duration) -> Self {
+        Self {
+            last_pong: now,
+            unresponsive_since: None,
+            expired: false,
+        }
+    }
+
+    pub(crate) fn record_pong(&mut self, now: Duration) {
+        self.last_pong = now;
+        self.unresponsive_since = None;
+        self.expired = false;
+    }
+
+    /// Raises each alarm once, until a pong arrives.
+    pub(crate) fn check(
+        &mut self,
+        timeout: &UnresponsiveTimeout,
+        now: Duration,
+    ) -> Option<WatchdogAlarm> {
+        match self.unresponsive_since {
+            None if now.saturating_sub(self.last_pong) >= timeout.after => {
+                self.unresponsive_since = Some(now);
+                Some(WatchdogAlarm::Unresponsive)
+            }
+            Some(since) if !self.expired && now.saturating_sub(since) >= timeout.grace_period => {
+                self.expired = true;
+                Some(WatchdogAlarm::Expired)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+#[test]
+fn test_watchdog() {
+    let timeout = UnresponsiveTimeout {
+        after: Duration::from_secs(2),
+        grace_period: Duration::from_secs(3),
+    };
+    let at = Duration::from_secs;
+    let mut watchdog = Watchdog::new(at(0));
+
+    assert_eq!(watchdog.check(&timeout, at(1)), None);
+    watchdog.record_pong(at(1));
+    assert_eq!(
+        watchdog.check(&timeout, at(2)),
+        None,
+        "pong resets the timer"
+    );
+    assert_eq!(
+        watchdog.check(&timeout, at(3)),
+        Some(WatchdogAlarm::Unresponsive)
+    );
+    assert_eq!(watchdog.check(&timeout, at(4)), None, "raised only once");
+    assert_eq!(
+        watchdog.check(&timeout, at(6)),
+        Some(WatchdogAlarm::Expired)
+    );
+    assert_eq!(watchdog.check(&timeout, at(7)), None);
+
+    watchdog.record_pong(at(8));
+    assert_eq!(watchdog.check(&timeout, at(9)), None, "responsive again");
+}
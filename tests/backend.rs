@@ -1,10 +1,12 @@
 use bevy::log::{Level, LogPlugin};
 use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
 use bevy_replicon::prelude::*;
 use bevy_replicon_matchbox::*;
 use serde::{Deserialize, Serialize};
 use std::net::{Ipv4Addr, SocketAddrV4};
 use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
 use test_log::test;
 
 //run the tests with cargo test -- --test-threads=1
@@ -30,6 +32,14 @@ fn connect_disconnect() {
         ))
         .finish();
     }
+    client_app.add_observer(|trigger: Trigger<HostAssigned>, mut commands: Commands| {
+        commands.insert_resource(AssignedHost(trigger.host_peer_id));
+    });
+    client_app.add_observer(
+        |trigger: Trigger<ClientConnectedToHost>, mut commands: Commands| {
+            commands.insert_resource(ConnectedHost(trigger.host_peer));
+        },
+    );
 
     setup(&mut server_app, &mut client_app, port);
     assert!(server_app.world().resource::<RepliconServer>().is_running());
@@ -50,6 +60,23 @@ fn connect_disconnect() {
     let replicon_client = client_app.world().resource::<RepliconClient>();
     assert!(replicon_client.is_connected());
 
+    let mut host = server_app.world_mut().resource_mut::<MatchboxHost>();
+    let host_peer_id = host.socket.id();
+    let client = client_app.world().resource::<MatchboxClient>();
+    let client_peer_id = client.peer_id().expect("client should have an ID");
+    assert!(host.client_entities.contains_key(&client_peer_id));
+    assert_eq!(client.host_peer_id(), host_peer_id);
+    let assigned_host = client_app.world().resource::<AssignedHost>();
+    assert_eq!(Some(assigned_host.0), host_peer_id);
+    let connected_host = client_app.world().resource::<ConnectedHost>();
+    assert_eq!(Some(connected_host.0), host_peer_id);
+    assert_eq!(client.wait_connected().outcome(), host_peer_id.map(Ok));
+    let connected = client_app
+        .world_mut()
+        .run_system_cached(matchbox_client_connected)
+        .unwrap();
+    assert!(connected);
+
     let mut matchbox_client = client_app.world_mut().resource_mut::<MatchboxClient>();
     assert!(matchbox_client.is_connected());
 
@@ -77,6 +104,80 @@ fn connect_disconnect() {
     assert!(replicon_client.is_disconnected());
 }
 
+#[test]
+fn quick_plugin() {
+    let port = next_test_port();
+    let room_url = format!("ws://localhost:{port}/TestRoom");
+
+    let mut server_app = App::new();
+    let mut client_app = App::new();
+    for (app, plugin) in [
+        (
+            &mut server_app,
+            RepliconMatchboxQuickPlugin::host(&room_url),
+        ),
+        (
+            &mut client_app,
+            RepliconMatchboxQuickPlugin::client(&room_url),
+        ),
+    ] {
+        app.add_plugins((
+            MinimalPlugins,
+            plugin,
+            RepliconPlugins,
+            RepliconMatchboxPlugins,
+        ))
+        .finish();
+    }
+    start_signaling_server(&mut server_app, port);
+
+    wait_for_connection(&mut server_app, &mut client_app);
+}
+
+#[test]
+fn deferred_socket() {
+    let port = next_test_port();
+    let room_url = format!("ws://localhost:{port}/TestRoom");
+
+    let mut server_app = App::new();
+    let mut client_app = App::new();
+    server_app.insert_resource(DeferredMatchboxHost::new(&room_url));
+    client_app.insert_resource(DeferredMatchboxClient::new(&room_url));
+    for app in [&mut server_app, &mut client_app] {
+        app.add_plugins((
+            MinimalPlugins,
+            RepliconPlugins.set(ServerPlugin {
+                tick_policy: TickPolicy::EveryFrame,
+                ..Default::default()
+            }),
+            RepliconMatchboxPlugins,
+        ))
+        // registered after the sockets were requested
+        .add_client_event::<TestEvent>(Channel::Ordered)
+        .finish();
+    }
+    start_signaling_server(&mut server_app, port);
+
+    wait_for_connection(&mut server_app, &mut client_app);
+    assert!(
+        !server_app
+            .world()
+            .contains_resource::<DeferredMatchboxHost>()
+    );
+
+    client_app.world_mut().send_event(TestEvent);
+    loop {
+        client_app.update();
+        server_app.update();
+        let client_events = server_app
+            .world()
+            .resource::<Events<FromClient<TestEvent>>>();
+        if !client_events.is_empty() {
+            break;
+        }
+    }
+}
+
 #[test]
 fn disconnect_request() {
     let port = next_test_port();
@@ -125,6 +226,14 @@ fn disconnect_request() {
     server_app.update();
 
     assert_eq!(clients.iter(server_app.world()).len(), 0);
+    assert_eq!(
+        server_app
+            .world()
+            .resource::<MatchboxHost>()
+            .pending_disconnects(),
+        1,
+        "host awaits the acknowledgement"
+    );
 
     client_app.update();
 
@@ -142,6 +251,14 @@ fn disconnect_request() {
 
     let client = client_app.world().resource::<RepliconClient>();
     assert!(client.is_disconnected());
+    let disconnected = client_app
+        .world()
+        .resource::<Events<DisconnectedFromHost>>();
+    assert!(
+        disconnected
+            .iter_current_update_events()
+            .any(|event| event.kind == DisconnectKind::Kicked { reason: None })
+    );
 
     let events = client_app.world().resource::<Events<TestEvent>>();
     info!("events: {:?}", events.len());
@@ -158,6 +275,58 @@ fn disconnect_request() {
         1,
         "last replication should be received"
     );
+
+    let disconnected = wait_for_disconnect_acks(&mut server_app);
+    assert!(
+        disconnected
+            .iter()
+            .any(|event| event.kind == DisconnectKind::Kicked { reason: None })
+    );
+}
+
+#[test]
+fn kick() {
+    let port = next_test_port();
+
+    let mut server_app = App::new();
+    let mut client_app = App::new();
+    for app in [&mut server_app, &mut client_app] {
+        app.add_plugins((MinimalPlugins, RepliconPlugins, RepliconMatchboxPlugins))
+            .finish();
+    }
+
+    setup(&mut server_app, &mut client_app, port);
+
+    let mut clients = server_app
+        .world_mut()
+        .query_filtered::<Entity, With<ConnectedClient>>();
+    let client_entity = clients.single(server_app.world()).unwrap();
+    server_app
+        .world_mut()
+        .resource_mut::<MatchboxHost>()
+        .kick(client_entity, "cheating");
+
+    let kicked = DisconnectKind::Kicked {
+        reason: Some("cheating".into()),
+    };
+    loop {
+        server_app.update();
+        client_app.update();
+        let disconnected = client_app
+            .world()
+            .resource::<Events<DisconnectedFromHost>>();
+        if let Some(event) = disconnected.iter_current_update_events().next() {
+            assert_eq!(event.kind, kicked);
+            break;
+        }
+    }
+
+    let disconnected = wait_for_disconnect_acks(&mut server_app);
+    let [event] = &disconnected[..] else {
+        panic!("expected a single disconnect, got {disconnected:?}");
+    };
+    assert_eq!(event.client_entity, client_entity);
+    assert_eq!(event.kind, kicked);
 }
 
 #[test]
@@ -309,6 +478,100 @@ fn replication() {
     assert_eq!(replicated.iter(client_app.world()).len(), 1);
 }
 
+#[test]
+fn loopback() {
+    let mut server_app = App::new();
+    let mut client_app = App::new();
+    for app in [&mut server_app, &mut client_app] {
+        app.add_plugins((
+            MinimalPlugins,
+            RepliconPlugins.set(ServerPlugin {
+                tick_policy: TickPolicy::EveryFrame,
+                ..Default::default()
+            }),
+            RepliconMatchboxPlugins,
+        ))
+        .finish();
+    }
+
+    let mut host = LoopbackHost::default();
+    client_app.insert_resource(host.connect());
+    server_app.insert_resource(host);
+    server_app.world_mut().spawn(Replicated);
+
+    let mut replicated = client_app.world_mut().query::<&Replicated>();
+    for _ in 0..10 {
+        server_app.update();
+        client_app.update();
+        if replicated.iter(client_app.world()).len() == 1 {
+            break;
+        }
+    }
+    assert_eq!(replicated.iter(client_app.world()).len(), 1);
+    assert!(
+        client_app
+            .world()
+            .resource::<RepliconClient>()
+            .is_connected()
+    );
+
+    client_app.world_mut().remove_resource::<LoopbackClient>();
+    client_app.update();
+    server_app.update();
+    server_app.update();
+
+    assert!(
+        client_app
+            .world()
+            .resource::<RepliconClient>()
+            .is_disconnected()
+    );
+    let mut clients = server_app.world_mut().query::<&ConnectedClient>();
+    assert_eq!(clients.iter(server_app.world()).len(), 0);
+}
+
+#[test]
+fn renet_compat_events() {
+    use bevy_replicon_matchbox::renet_compat::{RenetCompatPlugin, ServerEvent};
+
+    let mut server_app = App::new();
+    let mut client_app = App::new();
+    for app in [&mut server_app, &mut client_app] {
+        app.add_plugins((
+            MinimalPlugins,
+            RepliconPlugins.set(ServerPlugin {
+                tick_policy: TickPolicy::EveryFrame,
+                ..Default::default()
+            }),
+            RepliconMatchboxPlugins,
+        ))
+        .finish();
+    }
+    server_app.add_plugins(RenetCompatPlugin);
+
+    let mut host = LoopbackHost::default();
+    client_app.insert_resource(host.connect());
+    server_app.insert_resource(host);
+    server_app.update();
+
+    let mut clients = server_app
+        .world_mut()
+        .query_filtered::<Entity, With<ConnectedClient>>();
+    let client_entity = clients.single(server_app.world()).unwrap();
+    let events = server_app.world().resource::<Events<ServerEvent>>();
+    let events: Vec<_> = events.get_cursor().read(events).copied().collect();
+    assert_eq!(events, [ServerEvent::ClientConnected { client_entity }]);
+
+    client_app.world_mut().remove_resource::<LoopbackClient>();
+    server_app.update();
+    let events = server_app.world().resource::<Events<ServerEvent>>();
+    let events: Vec<_> = events.get_cursor().read(events).copied().collect();
+    assert_eq!(
+        events.last(),
+        Some(&ServerEvent::ClientDisconnected { client_entity })
+    );
+}
+
 #[test]
 fn server_event() {
     let port = next_test_port();
@@ -344,9 +607,8 @@ fn server_event() {
 }
 
 #[test]
-fn client_event() {
+fn late_channels() {
     let port = next_test_port();
-
     let mut server_app = App::new();
     let mut client_app = App::new();
     for app in [&mut server_app, &mut client_app] {
@@ -358,70 +620,970 @@ fn client_event() {
             }),
             RepliconMatchboxPlugins,
         ))
-        .add_client_event::<TestEvent>(Channel::Ordered)
+        .add_server_event::<TestEvent>(Channel::Ordered)
         .finish();
     }
 
     setup(&mut server_app, &mut client_app, port);
+    for app in [&mut server_app, &mut client_app] {
+        app.add_server_event::<LateEvent>(Channel::Ordered)
+            .add_client_event::<LateClientEvent>(Channel::Ordered);
+    }
 
-    client_app.world_mut().send_event(TestEvent);
-
-    client_app.update();
-    server_app.update();
-    client_app.update();
-    server_app.update();
+    server_app.world_mut().send_event(ToClients {
+        mode: SendMode::Broadcast,
+        event: LateEvent,
+    });
+    client_app.world_mut().send_event(LateClientEvent);
+    server_app.world_mut().send_event(ToClients {
+        mode: SendMode::Broadcast,
+        event: TestEvent,
+    });
+    for _ in 0..3 {
+        server_app.update();
+        client_app.update();
+    }
 
+    let events = client_app.world().resource::<Events<TestEvent>>();
+    assert_eq!(events.len(), 1, "channels of the socket still work");
     let client_events = server_app
         .world()
-        .resource::<Events<FromClient<TestEvent>>>();
-    assert_eq!(client_events.len(), 1);
+        .resource::<Events<FromClient<LateClientEvent>>>();
+    assert!(client_events.is_empty());
+    let host = server_app.world().resource::<MatchboxHost>();
+    assert_eq!(host.stats().protocol_violations, 0);
+    assert!(host.stats().dropped_messages > 0);
+    let client = client_app.world().resource::<MatchboxClient>();
+    assert_eq!(client.stats().protocol_violations, 0);
+    assert!(client.is_connected());
 }
 
-fn setup(server_app: &mut App, client_app: &mut App, port: u16) {
-    start_signaling_server(server_app, port);
-    setup_server(server_app, port);
-    setup_client(client_app, port);
-    wait_for_connection(server_app, client_app);
-}
+#[test]
+fn muted_channels() {
+    let port = next_test_port();
+    let mut server_app = App::new();
+    let mut client_app = App::new();
+    for app in [&mut server_app, &mut client_app] {
+        app.add_plugins((
+            MinimalPlugins,
+            RepliconPlugins.set(ServerPlugin {
+                tick_policy: TickPolicy::EveryFrame,
+                ..Default::default()
+            }),
+            RepliconMatchboxPlugins,
+        ))
+        .add_server_event::<TestEvent>(Channel::Ordered)
+        .finish();
+    }
 
-use bevy_matchbox::matchbox_signaling::SignalingServer;
+    setup(&mut server_app, &mut client_app, port);
 
-fn start_signaling_server(server_app: &mut App, port: u16) {
-    info!("Starting signaling server");
-    let addr = SocketAddrV4::new(Ipv4Addr::LOCALHOST, port);
-    let signaling_server = bevy_matchbox::MatchboxServer::from(
-        SignalingServer::client_server_builder(addr)
-            .on_connection_request(|connection| {
-                info!("Connecting: {connection:?}");
-                Ok(true) // Allow all connections
-            })
-            .on_id_assignment(|(socket, id)| info!("{socket} received {id}"))
-            .on_host_connected(|id| info!("Host joined: {id}"))
-            .on_host_disconnected(|id| info!("Host left: {id}"))
-            .on_client_connected(|id| info!("Client joined: {id}"))
-            .on_client_disconnected(|id| info!("Client left: {id}"))
-            .cors()
-            // .trace()
-            .build(),
-    );
-    server_app.insert_resource(signaling_server);
-}
+    let channels = server_app.world().resource::<RepliconChannels>();
+    let server_channels = channels.server_channels().len();
+    let mut server = server_app.world_mut().resource_mut::<MatchboxHost>();
+    for channel_id in 0..server_channels {
+        server.mute(MutedChannel::Outbound(channel_id));
+    }
+    server_app.world_mut().send_event(ToClients {
+        mode: SendMode::Broadcast,
+        event: TestEvent,
+    });
 
-fn setup_server(app: &mut App, port: u16) {
-    let room_url = format!("ws://localhost:{port}/TestRoom");
-    let channels = app.world().resource::<RepliconChannels>();
+    server_app.update();
+    client_app.update();
+    client_app.update();
 
-    let server = MatchboxHost::new(room_url, channels).unwrap();
+    let events = client_app.world().resource::<Events<TestEvent>>();
+    assert!(events.is_empty(), "muted channels should hold the event");
+    let server = server_app.world().resource::<MatchboxHost>();
+    assert!(server.muted_messages() > 0);
 
-    app.insert_resource(server);
-}
+    let mut server = server_app.world_mut().resource_mut::<MatchboxHost>();
+    for channel_id in 0..server_channels {
+        server.unmute(MutedChannel::Outbound(channel_id));
+    }
 
-fn setup_client(app: &mut App, port: u16) {
-    let room_url = format!("ws://localhost:{port}/TestRoom");
-    let channels = app.world().resource::<RepliconChannels>();
-    let client = MatchboxClient::new(room_url, channels).unwrap();
-    app.insert_resource(client);
-}
+    server_app.update();
+    client_app.update();
+    client_app.update();
+
+    let events = client_app.world().resource::<Events<TestEvent>>();
+    assert_eq!(events.len(), 1);
+    let server = server_app.world().resource::<MatchboxHost>();
+    assert_eq!(server.muted_messages(), 0);
+}
+
+#[test]
+fn tick_barrier() {
+    let port = next_test_port();
+    let mut server_app = App::new();
+    let mut client_app = App::new();
+    for app in [&mut server_app, &mut client_app] {
+        app.add_plugins((
+            MinimalPlugins,
+            RepliconPlugins.set(ServerPlugin {
+                tick_policy: TickPolicy::EveryFrame,
+                ..Default::default()
+            }),
+            RepliconMatchboxPlugins,
+        ))
+        .replicate::<TestComponent>()
+        .add_server_event::<TestEvent>(Channel::Ordered)
+        .make_event_independent::<TestEvent>()
+        .finish();
+    }
+
+    start_signaling_server(&mut server_app, port);
+    let room_url = format!("ws://localhost:{port}/TestRoom");
+    let config = MatchboxConfig {
+        tick_barrier: Some(Duration::from_secs(1)),
+        ..Default::default()
+    };
+    let channels = server_app.world().resource::<RepliconChannels>();
+    let server = MatchboxHost::with_config(&room_url, channels, config.clone()).unwrap();
+    server_app.insert_resource(server);
+    let channels = client_app.world().resource::<RepliconChannels>();
+    let client = MatchboxClient::with_config(&room_url, channels, config).unwrap();
+    client_app.insert_resource(client);
+    wait_for_connection(&mut server_app, &mut client_app);
+
+    server_app.world_mut().spawn((Replicated, TestComponent));
+    server_app.world_mut().send_event(ToClients {
+        mode: SendMode::Broadcast,
+        event: TestEvent,
+    });
+
+    loop {
+        server_app.update();
+        client_app.update();
+        let events = client_app.world().resource::<Events<TestEvent>>();
+        if !events.is_empty() {
+            break;
+        }
+    }
+    let mut components = client_app.world_mut().query::<&TestComponent>();
+    assert_eq!(
+        components.iter(client_app.world()).len(),
+        1,
+        "replication of the same tick should be received with the event"
+    );
+}
+
+#[test]
+fn client_event() {
+    let port = next_test_port();
+
+    let mut server_app = App::new();
+    let mut client_app = App::new();
+    for app in [&mut server_app, &mut client_app] {
+        app.add_plugins((
+            MinimalPlugins,
+            RepliconPlugins.set(ServerPlugin {
+                tick_policy: TickPolicy::EveryFrame,
+                ..Default::default()
+            }),
+            RepliconMatchboxPlugins,
+        ))
+        .add_client_event::<TestEvent>(Channel::Ordered)
+        .finish();
+    }
+
+    setup(&mut server_app, &mut client_app, port);
+
+    client_app.world_mut().send_event(TestEvent);
+
+    client_app.update();
+    server_app.update();
+    client_app.update();
+    server_app.update();
+
+    let client_events = server_app
+        .world()
+        .resource::<Events<FromClient<TestEvent>>>();
+    assert_eq!(client_events.len(), 1);
+}
+
+#[test]
+fn user_channel() {
+    let port = next_test_port();
+    let mut server_app = App::new();
+    let mut client_app = App::new();
+    for app in [&mut server_app, &mut client_app] {
+        app.add_plugins((
+            MinimalPlugins,
+            RepliconPlugins.set(ServerPlugin {
+                tick_policy: TickPolicy::EveryFrame,
+                ..Default::default()
+            }),
+            RepliconMatchboxPlugins,
+        ))
+        .finish();
+    }
+
+    start_signaling_server(&mut server_app, port);
+    let room_url = format!("ws://localhost:{port}/TestRoom");
+    let config = MatchboxConfig {
+        user_channels: vec![Channel::Unreliable, Channel::Ordered],
+        ..Default::default()
+    };
+    let channels = server_app.world().resource::<RepliconChannels>();
+    let server = MatchboxHost::with_config(&room_url, channels, config.clone()).unwrap();
+    server_app.insert_resource(server);
+    let channels = client_app.world().resource::<RepliconChannels>();
+    let client = MatchboxClient::with_config(&room_url, channels, config).unwrap();
+    client_app.insert_resource(client);
+    wait_for_connection(&mut server_app, &mut client_app);
+
+    let mut client = client_app.world_mut().resource_mut::<MatchboxClient>();
+    let client_peer_id = client.peer_id().unwrap();
+    client.send_to_host(1, "hello");
+    let message = wait_for_user_message(&mut client_app, &mut server_app);
+    assert_eq!(message.channel, 1);
+    assert_eq!(message.sender, client_peer_id);
+    assert_eq!(&message.payload[..], b"hello");
+
+    let mut host = server_app.world_mut().resource_mut::<MatchboxHost>();
+    let host_peer_id = host.socket.id().unwrap();
+    host.send_to(client_peer_id, 1, "welcome");
+    let message = wait_for_user_message(&mut server_app, &mut client_app);
+    assert_eq!(message.sender, host_peer_id);
+    assert_eq!(&message.payload[..], b"welcome");
+
+    client_app
+        .world_mut()
+        .resource_mut::<Events<UserMessage>>()
+        .clear();
+    let mut host = server_app.world_mut().resource_mut::<MatchboxHost>();
+    host.broadcast(1, "announcement");
+    let message = wait_for_user_message(&mut server_app, &mut client_app);
+    assert_eq!(message.channel, 1);
+    assert_eq!(&message.payload[..], b"announcement");
+}
+
+#[test]
+fn delivery_acks() {
+    let port = next_test_port();
+    let mut server_app = App::new();
+    let mut client_app = App::new();
+    for app in [&mut server_app, &mut client_app] {
+        app.add_plugins((
+            MinimalPlugins,
+            RepliconPlugins.set(ServerPlugin {
+                tick_policy: TickPolicy::EveryFrame,
+                ..Default::default()
+            }),
+            RepliconMatchboxPlugins,
+        ))
+        .finish();
+        app.add_observer(
+            |trigger: Trigger<MessageDelivered>, mut commands: Commands| {
+                commands.insert_resource(Delivered(trigger.id));
+            },
+        );
+    }
+
+    start_signaling_server(&mut server_app, port);
+    let room_url = format!("ws://localhost:{port}/TestRoom");
+    let config = MatchboxConfig {
+        user_channels: vec![Channel::Unreliable, Channel::Ordered],
+        ..Default::default()
+    };
+    let channels = server_app.world().resource::<RepliconChannels>();
+    let server = MatchboxHost::with_config(&room_url, channels, config.clone()).unwrap();
+    server_app.insert_resource(server);
+    let channels = client_app.world().resource::<RepliconChannels>();
+    let client = MatchboxClient::with_config(&room_url, channels, config).unwrap();
+    client_app.insert_resource(client);
+    wait_for_connection(&mut server_app, &mut client_app);
+
+    let client_peer_id = client_app
+        .world()
+        .resource::<MatchboxClient>()
+        .peer_id()
+        .unwrap();
+    let mut host = server_app.world_mut().resource_mut::<MatchboxHost>();
+    assert!(
+        host.send_to_acked(client_peer_id, 0, "lost").is_none(),
+        "unreliable channels can't be acknowledged"
+    );
+    let id = host.send_to_acked(client_peer_id, 1, "goodbye").unwrap();
+    assert_eq!(host.pending_deliveries(), 1);
+    let message = wait_for_user_message(&mut server_app, &mut client_app);
+    assert_eq!(&message.payload[..], b"goodbye");
+    while !server_app.world().contains_resource::<Delivered>() {
+        client_app.update();
+        server_app.update();
+    }
+    assert_eq!(server_app.world().resource::<Delivered>().0, id);
+    let host = server_app.world().resource::<MatchboxHost>();
+    assert_eq!(host.pending_deliveries(), 0);
+
+    let mut client = client_app.world_mut().resource_mut::<MatchboxClient>();
+    let id = client.send_to_host_acked(1, "hello").unwrap();
+    let message = wait_for_user_message(&mut client_app, &mut server_app);
+    assert_eq!(&message.payload[..], b"hello");
+    while !client_app.world().contains_resource::<Delivered>() {
+        server_app.update();
+        client_app.update();
+    }
+    assert_eq!(client_app.world().resource::<Delivered>().0, id);
+}
+
+#[test]
+fn protocol_mismatch() {
+    let port = next_test_port();
+    let mut server_app = App::new();
+    let mut client_app = App::new();
+    for app in [&mut server_app, &mut client_app] {
+        app.add_plugins((MinimalPlugins, RepliconPlugins, RepliconMatchboxPlugins));
+    }
+    client_app.replicate::<TestComponent>();
+    for app in [&mut server_app, &mut client_app] {
+        app.finish();
+    }
+
+    start_signaling_server(&mut server_app, port);
+    let room_url = format!("ws://localhost:{port}/TestRoom");
+    let config = MatchboxConfig {
+        check_protocol_hash: true,
+        ..Default::default()
+    };
+    let channels = server_app.world().resource::<RepliconChannels>();
+    let server = MatchboxHost::with_config(&room_url, channels, config).unwrap();
+    server_app.insert_resource(server);
+    setup_client(&mut client_app, port);
+
+    loop {
+        server_app.update();
+        client_app.update();
+        let disconnected = client_app
+            .world()
+            .resource::<Events<DisconnectedFromHost>>();
+        if let Some(event) = disconnected.iter_current_update_events().next() {
+            assert_eq!(event.kind, DisconnectKind::ProtocolMismatch);
+            break;
+        }
+    }
+    let replicon_client = client_app.world().resource::<RepliconClient>();
+    assert!(replicon_client.is_disconnected());
+
+    loop {
+        server_app.update();
+        let events = server_app.world().resource::<Events<ClientDisconnected>>();
+        if let Some(event) = events.get_cursor().read(events).next() {
+            assert_eq!(event.kind, DisconnectKind::ProtocolMismatch);
+            break;
+        }
+    }
+}
+
+#[test]
+fn rejoin() {
+    let port = next_test_port();
+    let mut server_app = App::new();
+    let mut client_app = App::new();
+    for app in [&mut server_app, &mut client_app] {
+        app.add_plugins((
+            MinimalPlugins,
+            RepliconPlugins.set(ServerPlugin {
+                tick_policy: TickPolicy::EveryFrame,
+                ..Default::default()
+            }),
+            RepliconMatchboxPlugins,
+        ))
+        .replicate::<TestComponent>()
+        .finish();
+    }
+
+    start_signaling_server(&mut server_app, port);
+    let room_url = format!("ws://localhost:{port}/TestRoom");
+    let config = MatchboxConfig {
+        rejoin_grace_period: Duration::from_secs(60),
+        ..Default::default()
+    };
+    let channels = server_app.world().resource::<RepliconChannels>();
+    let server = MatchboxHost::with_config(&room_url, channels, config.clone()).unwrap();
+    server_app.insert_resource(server);
+    setup_client(&mut client_app, port);
+    wait_for_connection(&mut server_app, &mut client_app);
+
+    let mut clients = server_app
+        .world_mut()
+        .query_filtered::<Entity, With<ConnectedClient>>();
+    let client_entity = clients.single(server_app.world()).unwrap();
+    let session_token = client_app
+        .world()
+        .resource::<MatchboxClient>()
+        .session_token()
+        .unwrap();
+
+    client_app.world_mut().remove_resource::<MatchboxClient>();
+    let mut dropped = server_app
+        .world_mut()
+        .query_filtered::<Entity, With<PeerDisconnected>>();
+    while dropped.iter(server_app.world()).len() == 0 {
+        client_app.update();
+        server_app.update();
+    }
+    assert_eq!(
+        server_app
+            .world()
+            .resource::<MatchboxHost>()
+            .connected_clients(),
+        0
+    );
+
+    // replicated while the client is away
+    server_app.world_mut().spawn((Replicated, TestComponent));
+    server_app.update();
+
+    let channels = client_app.world().resource::<RepliconChannels>();
+    let client = MatchboxClient::rejoin(&room_url, channels, config, session_token).unwrap();
+    client_app.insert_resource(client);
+    wait_for_connection(&mut server_app, &mut client_app);
+    server_app.update();
+
+    assert_eq!(clients.single(server_app.world()).unwrap(), client_entity);
+    assert_eq!(dropped.iter(server_app.world()).len(), 0);
+
+    let mut components = client_app.world_mut().query::<&TestComponent>();
+    while components.iter(client_app.world()).len() == 0 {
+        server_app.update();
+        client_app.update();
+    }
+}
+
+#[test]
+fn rejoin_full_host() {
+    let port = next_test_port();
+    let mut server_app = App::new();
+    let mut client_app = App::new();
+    let mut other_app = App::new();
+    for app in [&mut server_app, &mut client_app, &mut other_app] {
+        app.add_plugins((MinimalPlugins, RepliconPlugins, RepliconMatchboxPlugins))
+            .finish();
+    }
+
+    start_signaling_server(&mut server_app, port);
+    let room_url = format!("ws://localhost:{port}/TestRoom");
+    let config = MatchboxConfig {
+        max_clients: Some(1),
+        rejoin_grace_period: Duration::from_secs(60),
+        ..Default::default()
+    };
+    let channels = server_app.world().resource::<RepliconChannels>();
+    let server = MatchboxHost::with_config(&room_url, channels, config.clone()).unwrap();
+    server_app.insert_resource(server);
+    setup_client(&mut client_app, port);
+    wait_for_connection(&mut server_app, &mut client_app);
+    let session_token = client_app
+        .world()
+        .resource::<MatchboxClient>()
+        .session_token()
+        .unwrap();
+
+    client_app.world_mut().remove_resource::<MatchboxClient>();
+    let mut dropped = server_app
+        .world_mut()
+        .query_filtered::<Entity, With<PeerDisconnected>>();
+    while dropped.iter(server_app.world()).len() == 0 {
+        client_app.update();
+        server_app.update();
+    }
+    let client_entity = dropped.single(server_app.world()).unwrap();
+
+    // the slot of the dropped client stays reserved
+    setup_client(&mut other_app, port);
+    let mut disconnects = Vec::new();
+    while disconnects.is_empty() {
+        server_app.update();
+        other_app.update();
+        let events = other_app.world().resource::<Events<DisconnectedFromHost>>();
+        disconnects.extend(events.get_cursor().read(events).cloned());
+    }
+    assert_eq!(
+        disconnects[0].kind,
+        DisconnectKind::Kicked {
+            reason: Some("host is full".into())
+        }
+    );
+
+    let channels = client_app.world().resource::<RepliconChannels>();
+    let client = MatchboxClient::rejoin(&room_url, channels, config, session_token).unwrap();
+    client_app.insert_resource(client);
+    wait_for_connection(&mut server_app, &mut client_app);
+    server_app.update();
+
+    let mut clients = server_app
+        .world_mut()
+        .query_filtered::<Entity, With<ConnectedClient>>();
+    assert_eq!(clients.single(server_app.world()).unwrap(), client_entity);
+    assert_eq!(dropped.iter(server_app.world()).len(), 0);
+}
+
+#[test]
+fn rejoin_throttle() {
+    let port = next_test_port();
+    let mut server_app = App::new();
+    let mut client_app = App::new();
+    for app in [&mut server_app, &mut client_app] {
+        app.add_plugins((MinimalPlugins, RepliconPlugins, RepliconMatchboxPlugins))
+            .finish();
+    }
+
+    start_signaling_server(&mut server_app, port);
+    let room_url = format!("ws://localhost:{port}/TestRoom");
+    let config = MatchboxConfig {
+        rejoin_grace_period: Duration::from_secs(60),
+        reconnect_throttle: Some(ReconnectThrottle {
+            free_attempts: 1,
+            base_cooldown: Duration::from_secs(60),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let channels = server_app.world().resource::<RepliconChannels>();
+    let server = MatchboxHost::with_config(&room_url, channels, config.clone()).unwrap();
+    server_app.insert_resource(server);
+    setup_client(&mut client_app, port);
+    wait_for_connection(&mut server_app, &mut client_app);
+    let session_token = client_app
+        .world()
+        .resource::<MatchboxClient>()
+        .session_token()
+        .unwrap();
+
+    let mut dropped = server_app
+        .world_mut()
+        .query_filtered::<Entity, With<PeerDisconnected>>();
+    let mut drop_client = |server_app: &mut App, client_app: &mut App| {
+        client_app.world_mut().remove_resource::<MatchboxClient>();
+        while dropped.iter(server_app.world()).len() == 0 {
+            client_app.update();
+            server_app.update();
+        }
+    };
+    drop_client(&mut server_app, &mut client_app);
+    let channels = client_app.world().resource::<RepliconChannels>();
+    let client =
+        MatchboxClient::rejoin(&room_url, channels, config.clone(), session_token).unwrap();
+    client_app.insert_resource(client);
+    wait_for_connection(&mut server_app, &mut client_app);
+
+    drop_client(&mut server_app, &mut client_app);
+    let channels = client_app.world().resource::<RepliconChannels>();
+    let client = MatchboxClient::rejoin(&room_url, channels, config, session_token).unwrap();
+    client_app.insert_resource(client);
+    let mut disconnects = Vec::new();
+    while disconnects.is_empty() {
+        server_app.update();
+        client_app.update();
+        let events = client_app
+            .world()
+            .resource::<Events<DisconnectedFromHost>>();
+        disconnects.extend(events.get_cursor().read(events).cloned());
+    }
+    assert_eq!(
+        disconnects[0].kind,
+        DisconnectKind::Kicked {
+            reason: Some("too many connection attempts, try again later".into())
+        }
+    );
+    let mut clients = server_app
+        .world_mut()
+        .query_filtered::<Entity, With<ConnectedClient>>();
+    assert_eq!(
+        clients.iter(server_app.world()).len(),
+        1,
+        "no entity is spawned for the throttled attempt"
+    );
+}
+
+#[test]
+fn rejoin_grace_period_expires() {
+    let port = next_test_port();
+    let mut server_app = App::new();
+    let mut client_app = App::new();
+    for app in [&mut server_app, &mut client_app] {
+        app.add_plugins((
+            MinimalPlugins,
+            RepliconPlugins.set(ServerPlugin {
+                tick_policy: TickPolicy::EveryFrame,
+                ..Default::default()
+            }),
+            RepliconMatchboxPlugins,
+        ))
+        .finish();
+    }
+    // every host frame advances the clock by a second
+    server_app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs(1)));
+
+    start_signaling_server(&mut server_app, port);
+    let room_url = format!("ws://localhost:{port}/TestRoom");
+    let config = MatchboxConfig {
+        rejoin_grace_period: Duration::from_secs(10),
+        ..Default::default()
+    };
+    let channels = server_app.world().resource::<RepliconChannels>();
+    let server = MatchboxHost::with_config(&room_url, channels, config).unwrap();
+    server_app.insert_resource(server);
+    setup_client(&mut client_app, port);
+    wait_for_connection(&mut server_app, &mut client_app);
+
+    client_app.world_mut().remove_resource::<MatchboxClient>();
+    let mut dropped = server_app
+        .world_mut()
+        .query_filtered::<Entity, With<PeerDisconnected>>();
+    while dropped.iter(server_app.world()).len() == 0 {
+        client_app.update();
+        server_app.update();
+    }
+
+    for _ in 0..5 {
+        server_app.update();
+    }
+    assert_eq!(
+        dropped.iter(server_app.world()).len(),
+        1,
+        "grace period isn't over yet"
+    );
+
+    for _ in 0..6 {
+        server_app.update();
+    }
+    let mut clients = server_app.world_mut().query::<&ConnectedClient>();
+    assert_eq!(clients.iter(server_app.world()).len(), 0);
+}
+
+#[test]
+fn channel_open_timeout() {
+    let port = next_test_port();
+    let mut server_app = App::new();
+    let mut client_app = App::new();
+    for app in [&mut server_app, &mut client_app] {
+        app.add_plugins((MinimalPlugins, RepliconPlugins, RepliconMatchboxPlugins))
+            .finish();
+    }
+    // every client frame advances the clock by a second
+    client_app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs(1)));
+
+    // no host joins the room, so no data channel ever opens
+    start_signaling_server(&mut server_app, port);
+    let room_url = format!("ws://localhost:{port}/TestRoom");
+    let config = MatchboxConfig {
+        channel_open_timeout: Some(Duration::from_secs(5)),
+        ..Default::default()
+    };
+    let channels = client_app.world().resource::<RepliconChannels>();
+    let client = MatchboxClient::with_config(room_url, channels, config).unwrap();
+    client_app.insert_resource(client);
+
+    let mut disconnects = Vec::new();
+    while disconnects.is_empty() {
+        server_app.update();
+        client_app.update();
+        let events = client_app
+            .world()
+            .resource::<Events<DisconnectedFromHost>>();
+        disconnects.extend(events.get_cursor().read(events).cloned());
+    }
+    assert_eq!(disconnects[0].kind, DisconnectKind::ChannelOpenTimeout);
+    assert!(!client_app.world().contains_resource::<MatchboxClient>());
+}
+
+#[test]
+fn host_failover() {
+    let port = next_test_port();
+    let mut server_app = App::new();
+    let mut standby_app = App::new();
+    let mut client_app = App::new();
+    for app in [&mut server_app, &mut standby_app, &mut client_app] {
+        app.add_plugins((
+            MinimalPlugins,
+            RepliconPlugins.set(ServerPlugin {
+                tick_policy: TickPolicy::EveryFrame,
+                ..Default::default()
+            }),
+            RepliconMatchboxPlugins,
+        ))
+        .finish();
+    }
+
+    start_signaling_server(&mut server_app, port);
+    setup_server(&mut server_app, port);
+    let standby_url = format!("ws://localhost:{port}/StandbyRoom");
+    let channels = standby_app.world().resource::<RepliconChannels>();
+    let standby = MatchboxHost::new(&standby_url, channels).unwrap();
+    standby_app.insert_resource(standby);
+
+    let room_url = format!("ws://localhost:{port}/TestRoom");
+    let channels = client_app.world().resource::<RepliconChannels>();
+    let client = MatchboxClient::new(room_url, channels)
+        .unwrap()
+        .with_fallbacks([&standby_url])
+        .unwrap();
+    client_app.insert_resource(client);
+    wait_for_connection(&mut server_app, &mut client_app);
+
+    server_app.world_mut().remove_resource::<MatchboxHost>();
+    let mut failovers = Vec::new();
+    while failovers.is_empty() {
+        server_app.update();
+        standby_app.update();
+        client_app.update();
+        let events = client_app.world().resource::<Events<HostFailover>>();
+        failovers.extend(events.get_cursor().read(events).cloned());
+    }
+    assert_eq!(failovers[0].room_url, standby_url);
+    assert_eq!(failovers[0].remaining, 0);
+
+    wait_for_connection(&mut standby_app, &mut client_app);
+}
+
+#[test]
+fn waiting_room() {
+    let port = next_test_port();
+    let mut server_app = App::new();
+    let mut client_app = App::new();
+    let mut queued_app = App::new();
+    for app in [&mut server_app, &mut client_app, &mut queued_app] {
+        app.add_plugins((
+            MinimalPlugins,
+            RepliconPlugins.set(ServerPlugin {
+                tick_policy: TickPolicy::EveryFrame,
+                ..Default::default()
+            }),
+            RepliconMatchboxPlugins,
+        ))
+        .finish();
+    }
+
+    start_signaling_server(&mut server_app, port);
+    let room_url = format!("ws://localhost:{port}/TestRoom");
+    let config = MatchboxConfig {
+        max_clients: Some(1),
+        waiting_room: true,
+        ..Default::default()
+    };
+    let channels = server_app.world().resource::<RepliconChannels>();
+    let server = MatchboxHost::with_config(&room_url, channels, config).unwrap();
+    server_app.insert_resource(server);
+    setup_client(&mut client_app, port);
+    wait_for_connection(&mut server_app, &mut client_app);
+
+    setup_client(&mut queued_app, port);
+    let mut positions = Vec::new();
+    while positions.is_empty() {
+        server_app.update();
+        queued_app.update();
+        let events = queued_app.world().resource::<Events<ConnectionProgress>>();
+        positions.extend(
+            events
+                .get_cursor()
+                .read(events)
+                .filter(|progress| matches!(progress, ConnectionProgress::Queued { .. }))
+                .copied(),
+        );
+    }
+    assert_eq!(positions[0], ConnectionProgress::Queued { position: 1 });
+    let server = server_app.world().resource::<MatchboxHost>();
+    assert_eq!(server.connected_clients(), 1);
+    assert_eq!(server.waiting_peers().count(), 1);
+    assert!(
+        !queued_app
+            .world()
+            .resource::<MatchboxClient>()
+            .is_connected()
+    );
+
+    client_app.world_mut().remove_resource::<MatchboxClient>();
+    wait_for_connection(&mut server_app, &mut queued_app);
+    let server = server_app.world().resource::<MatchboxHost>();
+    assert_eq!(server.connected_clients(), 1);
+    assert_eq!(server.waiting_peers().count(), 0);
+}
+
+#[test]
+fn unresponsive_client() {
+    let port = next_test_port();
+    let mut server_app = App::new();
+    let mut client_app = App::new();
+    for app in [&mut server_app, &mut client_app] {
+        app.add_plugins((
+            MinimalPlugins,
+            RepliconPlugins.set(ServerPlugin {
+                tick_policy: TickPolicy::EveryFrame,
+                ..Default::default()
+            }),
+            RepliconMatchboxPlugins,
+        ))
+        .finish();
+    }
+
+    start_signaling_server(&mut server_app, port);
+    let room_url = format!("ws://localhost:{port}/TestRoom");
+    let config = MatchboxConfig {
+        unresponsive_timeout: Some(UnresponsiveTimeout {
+            after: Duration::from_secs(3),
+            grace_period: Duration::from_secs(3),
+        }),
+        ..Default::default()
+    };
+    let channels = server_app.world().resource::<RepliconChannels>();
+    let server = MatchboxHost::with_config(&room_url, channels, config).unwrap();
+    server_app.insert_resource(server);
+    setup_client(&mut client_app, port);
+    wait_for_connection(&mut server_app, &mut client_app);
+
+    // the client app stops updating, so its connection stays open without answering pings
+    server_app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs(1)));
+    let mut unresponsive = Vec::new();
+    while unresponsive.is_empty() {
+        server_app.update();
+        let events = server_app.world().resource::<Events<PeerUnresponsive>>();
+        unresponsive.extend(events.get_cursor().read(events).copied());
+    }
+    let client_entity = unresponsive[0].client_entity;
+    assert_eq!(
+        server_app
+            .world()
+            .resource::<MatchboxHost>()
+            .connected_clients(),
+        1,
+        "client is only disconnected after the grace period"
+    );
+
+    while server_app
+        .world()
+        .resource::<MatchboxHost>()
+        .connected_clients()
+        > 0
+    {
+        server_app.update();
+    }
+    let events = wait_for_disconnect_acks(&mut server_app);
+    assert!(
+        events
+            .iter()
+            .any(|event| event.client_entity == client_entity
+                && event.kind == DisconnectKind::Timeout)
+    );
+}
+
+#[test]
+fn full_mesh() {
+    let port = next_test_port();
+    let mut server_app = App::new();
+    let mut client_app = App::new();
+    let mut other_app = App::new();
+    for app in [&mut server_app, &mut client_app, &mut other_app] {
+        app.add_plugins((MinimalPlugins, RepliconPlugins, RepliconMatchboxPlugins))
+            .finish();
+    }
+
+    let addr = SocketAddrV4::new(Ipv4Addr::LOCALHOST, port);
+    let signaling_server =
+        bevy_matchbox::MatchboxServer::from(SignalingServer::full_mesh_builder(addr).build());
+    server_app.insert_resource(signaling_server);
+    setup_server(&mut server_app, port);
+    setup_client(&mut client_app, port);
+    setup_client(&mut other_app, port);
+    loop {
+        server_app.update();
+        client_app.update();
+        other_app.update();
+        let host = server_app.world().resource::<MatchboxHost>();
+        let client = client_app.world().resource::<MatchboxClient>();
+        let other = other_app.world().resource::<MatchboxClient>();
+        if host.connected_clients() == 2
+            && client.is_connected()
+            && other.is_connected()
+            && client.direct_peers().count() == 1
+        {
+            break;
+        }
+    }
+    let client = client_app.world().resource::<MatchboxClient>();
+    let host_peer_id = client.host_peer_id();
+    let client_peer_id = client.peer_id().unwrap();
+
+    // the other client impersonates the host
+    let mut other = other_app.world_mut().resource_mut::<MatchboxClient>();
+    // postcard encoding of `HostRequestsDisconnect { reason: None }`
+    let kick = [1, 0];
+    let malformed = [u8::MAX];
+    for packet in [&kick[..], &malformed] {
+        other
+            .socket
+            .channel_mut(0)
+            .send(packet.into(), client_peer_id);
+    }
+    other
+        .socket
+        .channel_mut(1)
+        .send([0; 8].into(), client_peer_id);
+    for _ in 0..10 {
+        server_app.update();
+        other_app.update();
+        client_app.update();
+    }
+
+    let client = client_app.world().resource::<MatchboxClient>();
+    assert_eq!(client.host_peer_id(), host_peer_id);
+    assert_eq!(
+        client.stats().protocol_violations,
+        0,
+        "messages of other clients aren't the host's violations"
+    );
+    let replicon_client = client_app.world().resource::<RepliconClient>();
+    assert!(replicon_client.is_connected());
+}
+
+fn setup(server_app: &mut App, client_app: &mut App, port: u16) {
+    start_signaling_server(server_app, port);
+    setup_server(server_app, port);
+    setup_client(client_app, port);
+    wait_for_connection(server_app, client_app);
+}
+
+use bevy_matchbox::matchbox_signaling::SignalingServer;
+
+fn start_signaling_server(server_app: &mut App, port: u16) {
+    info!("Starting signaling server");
+    let addr = SocketAddrV4::new(Ipv4Addr::LOCALHOST, port);
+    let signaling_server = bevy_matchbox::MatchboxServer::from(
+        SignalingServer::client_server_builder(addr)
+            .on_connection_request(|connection| {
+                info!("Connecting: {connection:?}");
+                Ok(true) // Allow all connections
+            })
+            .on_id_assignment(|(socket, id)| info!("{socket} received {id}"))
+            .on_host_connected(|id| info!("Host joined: {id}"))
+            .on_host_disconnected(|id| info!("Host left: {id}"))
+            .on_client_connected(|id| info!("Client joined: {id}"))
+            .on_client_disconnected(|id| info!("Client left: {id}"))
+            .cors()
+            // .trace()
+            .build(),
+    );
+    server_app.insert_resource(signaling_server);
+}
+
+fn setup_server(app: &mut App, port: u16) {
+    let room_url = format!("ws://localhost:{port}/TestRoom");
+    let channels = app.world().resource::<RepliconChannels>();
+
+    let server = MatchboxHost::new(room_url, channels).unwrap();
+
+    app.insert_resource(server);
+}
+
+fn setup_client(app: &mut App, port: u16) {
+    let room_url = format!("ws://localhost:{port}/TestRoom");
+    let channels = app.world().resource::<RepliconChannels>();
+    let client = MatchboxClient::new(room_url, channels).unwrap();
+    app.insert_resource(client);
+}
 
 fn wait_for_connection(server_app: &mut App, client_app: &mut App) {
     loop {
@@ -435,5 +1597,47 @@ fn wait_for_connection(server_app: &mut App, client_app: &mut App) {
     }
 }
 
+/// Updates the host until all disconnects are acknowledged, returns the recent disconnect events.
+fn wait_for_disconnect_acks(server_app: &mut App) -> Vec<ClientDisconnected> {
+    loop {
+        server_app.update();
+        let host = server_app.world().resource::<MatchboxHost>();
+        if host.pending_disconnects() == 0 {
+            let events = server_app.world().resource::<Events<ClientDisconnected>>();
+            return events.get_cursor().read(events).cloned().collect();
+        }
+    }
+}
+
+/// Updates both apps until `receiver` got a [`UserMessage`].
+fn wait_for_user_message(sender: &mut App, receiver: &mut App) -> UserMessage {
+    loop {
+        sender.update();
+        receiver.update();
+        let events = receiver.world().resource::<Events<UserMessage>>();
+        if let Some(message) = events.get_cursor().read(events).next() {
+            return message.clone();
+        }
+    }
+}
+
+#[derive(Resource)]
+struct Delivered(DeliveryId);
+
+#[derive(Resource)]
+struct AssignedHost(bevy_matchbox::prelude::PeerId);
+
+#[derive(Resource)]
+struct ConnectedHost(bevy_matchbox::prelude::PeerId);
+
 #[derive(Deserialize, Event, Serialize)]
 struct TestEvent;
+
+#[derive(Deserialize, Event, Serialize)]
+struct LateEvent;
+
+#[derive(Deserialize, Event, Serialize)]
+struct LateClientEvent;
+
+#[derive(Component, Deserialize, Serialize)]
+struct TestComponent;
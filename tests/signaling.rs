@@ -0,0 +1,50 @@
+use bevy::prelude::*;
+use bevy_replicon::prelude::*;
+use bevy_replicon_matchbox::*;
+use std::net::{Ipv4Addr, SocketAddr};
+use test_log::test;
+
+#[test]
+fn ephemeral_port() {
+    let mut server_app = App::new();
+    let mut client_app = App::new();
+    for app in [&mut server_app, &mut client_app] {
+        app.add_plugins((MinimalPlugins, RepliconPlugins, RepliconMatchboxPlugins))
+            .finish();
+    }
+
+    let channels = server_app.world().resource::<RepliconChannels>();
+    let addr = SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0));
+    let (host, signaling) =
+        MatchboxHost::with_embedded_signaling(addr, "TestRoom", channels).unwrap();
+    let local_addr = signaling.local_addr().unwrap();
+    assert_ne!(local_addr.port(), 0, "OS assigns a free port");
+    let room_url = signaling.room_url("TestRoom").unwrap();
+    assert_eq!(
+        room_url,
+        format!("ws://127.0.0.1:{}/TestRoom", local_addr.port())
+    );
+    server_app.insert_resource(host);
+    server_app.insert_resource(signaling);
+
+    let channels = client_app.world().resource::<RepliconChannels>();
+    let client = MatchboxClient::new(room_url, channels).unwrap();
+    client_app.insert_resource(client);
+
+    loop {
+        client_app.update();
+        server_app.update();
+        let host = server_app.world().resource::<MatchboxHost>();
+        let client = client_app.world().resource::<MatchboxClient>();
+        if host.connected_clients() > 0 && client.is_connected() {
+            break;
+        }
+    }
+
+    let metrics = server_app
+        .world()
+        .resource::<EmbeddedSignalingServer>()
+        .metrics();
+    assert_eq!(metrics.connected_peers, 2);
+    assert_eq!(metrics.active_rooms, 1);
+}
@@ -0,0 +1,229 @@
+//! Host with dozens of in-process clients, guarding the per-client loops against scalability
+//! regressions.
+//!
+//! A few clients connect over WebRTC through the embedded signaling server, so the socket
+//! path with its queues and buckets is part of the measurement. The rest connect
+//! through a [`LoopbackHost`] to reach a realistic client count without dozens of peer
+//! connections on a single machine.
+//!
+//! Frame times depend on the machine, so their bound is checked by an ignored test:
+//! `cargo test --release --features signaling --test stress -- --ignored`.
+
+use bevy::prelude::*;
+use bevy_replicon::prelude::*;
+use bevy_replicon_matchbox::*;
+use serde::{Deserialize, Serialize};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+const SOCKET_CLIENTS: usize = 4;
+const LOOPBACK_CLIENTS: usize = 28;
+const ENTITIES: usize = 100;
+const FRAMES: u32 = 300;
+/// Average time of a host frame, debug builds are an order of magnitude slower.
+const MAX_HOST_FRAME_TIME: Duration = if cfg!(debug_assertions) {
+    Duration::from_millis(100)
+} else {
+    Duration::from_millis(10)
+};
+/// Share of the mutations each client has to apply, the rest may still be in flight.
+const MIN_APPLIED_MUTATIONS: f64 = 0.95;
+/// Growth of the heap over all measured frames, more means buffers pile up.
+const MAX_MEMORY_GROWTH: usize = 16 * 1024 * 1024;
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Bytes currently allocated on the heap.
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[test]
+fn many_clients() {
+    let mut stress = Stress::new();
+    let measurement = stress.run();
+
+    let expected = (FRAMES as usize * ENTITIES) as f64;
+    let mut applied_total = 0;
+    for (index, app) in stress.client_apps.iter().enumerate() {
+        let applied = app.world().resource::<AppliedMutations>().0;
+        applied_total += applied;
+        let transport = if index < SOCKET_CLIENTS {
+            "socket"
+        } else {
+            "loopback"
+        };
+        assert!(
+            applied as f64 >= expected * MIN_APPLIED_MUTATIONS,
+            "a {transport} client applied {applied} of {expected} mutations"
+        );
+    }
+    info!(
+        "clients applied {:.0} mutations per second",
+        applied_total as f64 / measurement.elapsed.as_secs_f64()
+    );
+
+    let memory_growth = measurement.memory_growth;
+    info!("heap grew by {memory_growth} bytes");
+    assert!(
+        memory_growth < MAX_MEMORY_GROWTH,
+        "heap grew by {memory_growth} bytes"
+    );
+}
+
+#[test]
+#[ignore = "frame times depend on the machine"]
+fn host_frame_time() {
+    let measurement = Stress::new().run();
+
+    let average_frame_time = measurement.host_frame_time / FRAMES;
+    info!("average host frame time: {average_frame_time:?}");
+    assert!(
+        average_frame_time < MAX_HOST_FRAME_TIME,
+        "host frames took {average_frame_time:?} on average"
+    );
+}
+
+/// Host with all clients connected and all entities replicated.
+struct Stress {
+    host_app: App,
+    /// Socket clients first, followed by the loopback clients.
+    client_apps: Vec<App>,
+}
+
+impl Stress {
+    fn new() -> Self {
+        let mut host_app = test_app();
+        host_app.add_systems(Update, move_entities);
+        let channels = host_app.world().resource::<RepliconChannels>();
+        let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, 0));
+        let (host, signaling) =
+            MatchboxHost::with_embedded_signaling(addr, "StressRoom", channels).unwrap();
+        let room_url = signaling.room_url("StressRoom").unwrap();
+        host_app.insert_resource(host);
+        host_app.insert_resource(signaling);
+
+        let mut loopback = LoopbackHost::default();
+        let client_apps = (0..SOCKET_CLIENTS + LOOPBACK_CLIENTS)
+            .map(|index| {
+                let mut app = test_app();
+                app.init_resource::<AppliedMutations>()
+                    .add_systems(Update, count_mutations);
+                if index < SOCKET_CLIENTS {
+                    let channels = app.world().resource::<RepliconChannels>();
+                    let client = MatchboxClient::new(&room_url, channels).unwrap();
+                    app.insert_resource(client);
+                } else {
+                    app.insert_resource(loopback.connect());
+                }
+                app
+            })
+            .collect();
+        host_app.insert_resource(loopback);
+        for _ in 0..ENTITIES {
+            host_app.world_mut().spawn((Replicated, Position(0.0)));
+        }
+
+        let mut stress = Self {
+            host_app,
+            client_apps,
+        };
+        while !stress.replicated() {
+            stress.update();
+        }
+        for app in &mut stress.client_apps {
+            app.insert_resource(AppliedMutations::default());
+        }
+        stress
+    }
+
+    fn replicated(&mut self) -> bool {
+        self.client_apps.iter_mut().all(|app| {
+            let mut positions = app.world_mut().query::<&Position>();
+            positions.iter(app.world()).count() == ENTITIES
+        })
+    }
+
+    /// Runs the measured frames.
+    fn run(&mut self) -> Measurement {
+        let allocated = ALLOCATED.load(Ordering::Relaxed);
+        let mut host_frame_time = Duration::ZERO;
+        let start = Instant::now();
+        for _ in 0..FRAMES {
+            host_frame_time += self.update();
+        }
+        Measurement {
+            host_frame_time,
+            elapsed: start.elapsed(),
+            memory_growth: ALLOCATED.load(Ordering::Relaxed).saturating_sub(allocated),
+        }
+    }
+
+    /// Runs a frame of the host and then of every client, returns the time of the host frame.
+    fn update(&mut self) -> Duration {
+        let frame_start = Instant::now();
+        self.host_app.update();
+        let host_frame_time = frame_start.elapsed();
+        for app in &mut self.client_apps {
+            app.update();
+        }
+        host_frame_time
+    }
+}
+
+struct Measurement {
+    /// Sum of all host frames.
+    host_frame_time: Duration,
+    elapsed: Duration,
+    memory_growth: usize,
+}
+
+fn test_app() -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        RepliconPlugins.set(ServerPlugin {
+            tick_policy: TickPolicy::EveryFrame,
+            ..Default::default()
+        }),
+        RepliconMatchboxPlugins,
+    ))
+    .replicate::<Position>()
+    .finish();
+    app
+}
+
+fn move_entities(mut positions: Query<&mut Position>) {
+    for mut position in &mut positions {
+        position.0 += 1.0;
+    }
+}
+
+fn count_mutations(mut applied: ResMut<AppliedMutations>, positions: Query<(), Changed<Position>>) {
+    applied.0 += positions.iter().count();
+}
+
+/// Position changes replicated to a client.
+#[derive(Resource, Default)]
+struct AppliedMutations(usize);
+
+#[derive(Component, Deserialize, Serialize)]
+struct Position(f32);
@@ -0,0 +1,76 @@
+use bevy::prelude::*;
+use bevy_replicon::prelude::*;
+use bevy_replicon_matchbox::RepliconMatchboxPlugins;
+use bevy_replicon_matchbox::test_utils::{ServerTestAppExt, TestPair};
+use serde::{Deserialize, Serialize};
+use test_log::test;
+
+#[test]
+fn replicate_with_pair() {
+    let mut pair = TestPair::new(|app| {
+        app.replicate::<Health>();
+    });
+    pair.host.world_mut().spawn((Replicated, Health(100)));
+    pair.update_until_replicated::<Health>(1);
+    assert_eq!(pair.client_components::<Health>(), [Health(100)]);
+
+    let client_entity = pair.client_entity();
+    pair.host
+        .world_mut()
+        .send_event(DisconnectRequest { client_entity });
+    pair.update_until(|_, client| {
+        client
+            .world()
+            .resource::<RepliconClient>()
+            .is_disconnected()
+    });
+}
+
+#[test]
+fn replicon_test_app_pattern() {
+    let mut server_app = App::new();
+    let mut client_app = App::new();
+    for app in [&mut server_app, &mut client_app] {
+        app.add_plugins((
+            MinimalPlugins,
+            RepliconPlugins.set(ServerPlugin {
+                tick_policy: TickPolicy::EveryFrame,
+                ..Default::default()
+            }),
+            RepliconMatchboxPlugins,
+        ))
+        .replicate::<Health>()
+        .finish();
+    }
+
+    server_app.connect_client(&mut client_app);
+    assert!(
+        client_app
+            .world()
+            .resource::<RepliconClient>()
+            .is_connected()
+    );
+
+    server_app.world_mut().spawn((Replicated, Health(100)));
+    server_app.update();
+    server_app.exchange_with_client(&mut client_app);
+    client_app.update();
+
+    let mut healths = client_app.world_mut().query::<&Health>();
+    assert_eq!(healths.single(client_app.world()).unwrap(), &Health(100));
+
+    server_app.disconnect_client(&mut client_app);
+    assert!(
+        client_app
+            .world()
+            .resource::<RepliconClient>()
+            .is_disconnected()
+    );
+    let mut clients = server_app
+        .world_mut()
+        .query_filtered::<(), With<ConnectedClient>>();
+    assert_eq!(clients.iter(server_app.world()).len(), 0);
+}
+
+#[derive(Component, Clone, Debug, PartialEq, Deserialize, Serialize)]
+struct Health(u32);